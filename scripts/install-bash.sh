@@ -29,18 +29,20 @@ if [[ ":$PATH:" != *":$HOME/.local/bin:"* ]]; then
     echo "Added ~/.local/bin to PATH in .bashrc"
 fi
 
+# Replace the hooks older versions wrote, which record no exit codes or durations
+if grep -q "recall_log_last_command" "$HOME/.bashrc" 2>/dev/null; then
+    sed -i -e '/^# recall command logger integration$/d' \
+        -e '/^recall_log_last_command() {$/,/^}$/d' \
+        -e '/PROMPT_COMMAND.*recall_log_last_command/d' "$HOME/.bashrc"
+    echo "Removed the old bash integration from ~/.bashrc"
+fi
+
 # Add bash integration
 if ! grep -q "recall command logger integration" "$HOME/.bashrc" 2>/dev/null; then
     cat >> "$HOME/.bashrc" << 'EOF'
 
 # recall command logger integration
-recall_log_last_command() {
-    local last_cmd=$(history 1 | sed 's/^[ ]*[0-9]*[ ]*//')
-    if [[ "$last_cmd" != recall* ]] && [[ -n "$last_cmd" ]]; then
-        ~/.local/bin/recall log "$last_cmd" 2>/dev/null
-    fi
-}
-export PROMPT_COMMAND="${PROMPT_COMMAND:+$PROMPT_COMMAND$'\n'}history -a; recall_log_last_command"
+eval "$(recall init bash)"
 EOF
     echo "Added bash integration to ~/.bashrc"
 fi
@@ -29,15 +29,20 @@ if not contains "$HOME/.local/bin" $PATH
     echo "Added ~/.local/bin to PATH in fish config"
 end
 
+# Replace the hooks older versions wrote with the init script
+set CONFIG "$HOME/.config/fish/config.fish"
+if grep -q "recall command logger integration" "$CONFIG" 2>/dev/null; and not grep -q "recall init fish" "$CONFIG"
+    sed -i -e '/^# recall command logger integration$/d' \
+        -e '/^function recall_log_command/,/^end$/d' \
+        -e '/^function recall_finish_command/,/^end$/d' "$CONFIG"
+    echo "Removed the old fish integration from ~/.config/fish/config.fish"
+end
+
 # Add fish integration
 if not grep -q "recall command logger integration" "$HOME/.config/fish/config.fish" 2>/dev/null
     echo '
 # recall command logger integration
-function recall_log_command --on-event fish_preexec
-    if not string match -q "recall*" -- "$argv"
-        ~/.local/bin/recall log "$argv" 2>/dev/null &
-    end
-end' >> "$HOME/.config/fish/config.fish"
+recall init fish | source' >> "$HOME/.config/fish/config.fish"
     echo "Added fish integration to ~/.config/fish/config.fish"
 end
 
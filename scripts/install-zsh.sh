@@ -33,13 +33,18 @@ fi
 
 # Add zsh integration
 if [[ -f "$HOME/.zshrc" ]]; then
+    # Replace the hooks older versions wrote with the init script
+    if grep -q "recall command logger integration" "$HOME/.zshrc" && ! grep -q "recall init zsh" "$HOME/.zshrc"; then
+        sed -i -e '/^# recall command logger integration$/d' \
+            -e '/^preexec() {$/{N;N;/recall log/d}' \
+            -e '/^precmd() {$/{N;N;N;N;N;N;/recall log --finish/d}' "$HOME/.zshrc"
+        echo "Removed the old zsh integration from ~/.zshrc"
+    fi
     if ! grep -q "recall command logger integration" "$HOME/.zshrc" 2>/dev/null; then
         cat >> "$HOME/.zshrc" << 'EOF'
 
 # recall command logger integration
-preexec() {
-    [[ "$1" != recall* ]] && ~/.local/bin/recall log "$1" 2>/dev/null
-}
+eval "$(recall init zsh)"
 EOF
         echo "Added zsh integration to ~/.zshrc"
     fi
@@ -12,6 +12,7 @@ fi
 
 # Remove bash integration
 if [[ -f "$HOME/.bashrc" ]]; then
+    sed -i '/# recall command logger integration/{N;/recall init/d}' "$HOME/.bashrc"
     sed -i '/# recall command logger integration/,/^$/d' "$HOME/.bashrc"
     sed -i '/recall_log_last_command/d' "$HOME/.bashrc"
     sed -i '/PROMPT_COMMAND.*recall.*log/d' "$HOME/.bashrc"
@@ -11,8 +11,10 @@ end
 
 # Remove fish integration
 if test -f "$HOME/.config/fish/config.fish"
+    sed -i '/# recall command logger integration/{N;/recall init/d}' "$HOME/.config/fish/config.fish"
     sed -i '/# recall command logger integration/,/^end$/d' "$HOME/.config/fish/config.fish"
     sed -i '/recall_log_command.*fish_preexec/d' "$HOME/.config/fish/config.fish"
+    sed -i '/^function recall_finish_command/,/^end$/d' "$HOME/.config/fish/config.fish"
     echo "Removed fish integration from ~/.config/fish/config.fish"
 end
 
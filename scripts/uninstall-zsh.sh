@@ -12,8 +12,10 @@ fi
 
 # Remove zsh integration
 if [[ -f "$HOME/.zshrc" ]]; then
+    sed -i '/# recall command logger integration/{N;/recall init/d}' "$HOME/.zshrc"
     sed -i '/# recall command logger integration/,/^$/d' "$HOME/.zshrc"
     sed -i '/preexec.*recall.*log/d' "$HOME/.zshrc"
+    sed -i '/recall log --finish/d' "$HOME/.zshrc"
     echo "Removed zsh integration from ~/.zshrc"
 fi
 
@@ -0,0 +1,256 @@
+use crate::config::{self, get_config_file_path, parse_value, Config};
+use std::process::Command;
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, Item};
+
+pub fn print_config_path() {
+    println!("{}", get_config_file_path().display());
+}
+
+/// Settings that are secret, or lead to one, and are masked when the config
+/// is printed. `config get` still prints each of them when asked by name.
+const SECRET_KEYS: [&str; 2] = ["sync.auth_token", "database.key_file"];
+
+/// What a secret setting is shown as.
+const MASK: &str = "********";
+
+/// Prints the effective config, with defaults and environment overrides
+/// filled in and secrets masked.
+pub fn show_config() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut table = config::get()?.to_table();
+    mask_secrets(&mut table, None);
+    print!("{}", toml::to_string(&table)?);
+    Ok(())
+}
+
+/// Prints one setting, or a whole section with its secrets masked. Strings
+/// are printed bare so the output can be used in scripts.
+pub fn get_config_value(key: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match lookup(&config::get()?.to_table(), key)? {
+        Value::String(s) => println!("{}", s),
+        Value::Table(mut table) => {
+            mask_secrets(&mut table, Some(key));
+            print!("{}", toml::to_string(&table)?)
+        }
+        other => println!("{}", other),
+    }
+    Ok(())
+}
+
+/// Replaces every secret that is set in `table` with [`MASK`]. `table` is
+/// the whole config, or the named section of it.
+fn mask_secrets(table: &mut Table, section: Option<&str>) {
+    for key in SECRET_KEYS {
+        let (secret_section, name) = key.split_once('.').expect("secret keys are dotted");
+        let holder = match section {
+            Some(section) if section == secret_section => Some(&mut *table),
+            Some(_) => None,
+            None => match table.get_mut(secret_section) {
+                Some(Value::Table(holder)) => Some(holder),
+                _ => None,
+            },
+        };
+        if let Some(Value::String(value)) = holder.and_then(|holder| holder.get_mut(name)) {
+            if !value.is_empty() {
+                *value = MASK.to_string();
+            }
+        }
+    }
+}
+
+/// Writes one setting to the config file, keeping the rest of the file,
+/// comments included, as it was. The file is only written when the result
+/// is a valid config.
+pub fn set_config_value(key: &str, raw: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (section, name) = key
+        .split_once('.')
+        .ok_or_else(|| format!("'{}' is not a setting; use section.key, e.g. ui.history_limit", key))?;
+    let value = match lookup(&Config::default().to_table(), key)? {
+        Value::String(_) => Value::String(raw.to_string()),
+        Value::Table(_) => return Err(format!("'{}' is a section, not a setting", key).into()),
+        _ => parse_value(raw).unwrap_or_else(|| Value::String(raw.to_string())),
+    };
+
+    let path = get_config_file_path();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into()),
+    };
+    let updated = set_in_document(&text, section, name, &value).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Config::from_toml(&updated)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, updated)?;
+    println!("{} = {}", key, value);
+
+    Ok(())
+}
+
+/// Opens the config file in `$VISUAL` or `$EDITOR`, creating it with the
+/// defaults commented out if it does not exist, and checks it afterwards.
+pub fn edit_config() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = get_config_file_path();
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let defaults = toml::to_string(&Config::default().to_table())?;
+        let template: String = defaults
+            .lines()
+            .map(|line| match line {
+                "" => "\n".to_string(),
+                line if line.starts_with('[') => format!("{}\n", line),
+                line => format!("# {}\n", line),
+            })
+            .collect();
+        std::fs::write(&path, format!("# recall configuration; uncomment a setting to change it.\n\n{}", template))?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    // The editor may carry arguments, e.g. `code --wait`.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .map_err(|e| format!("Could not run {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", editor, status).into());
+    }
+
+    let text = std::fs::read_to_string(&path)?;
+    Config::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// The value at a dotted key such as `ui.history_limit`.
+fn lookup(table: &Table, key: &str) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let mut parts = key.split('.');
+    let first = parts.next().unwrap_or_default();
+    let mut value = table.get(first);
+    for part in parts {
+        value = match value {
+            Some(Value::Table(table)) => table.get(part),
+            _ => None,
+        };
+    }
+    value.cloned().ok_or_else(|| format!("unknown key '{}'", key).into())
+}
+
+/// Sets `key = value` in `[section]` of a TOML document, adding the section
+/// when needed. Everything else in the document, comments and layout
+/// included, is left as it was, as is a comment after the old value.
+fn set_in_document(
+    text: &str,
+    section: &str,
+    key: &str,
+    value: &Value,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut document: DocumentMut = text.parse()?;
+    let table = document
+        .entry(section)
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or_else(|| format!("'{}' is not a table", section))?;
+
+    let mut new_value: toml_edit::Value = value.to_string().parse()?;
+    match table.get_mut(key) {
+        // Replaced in place, which keeps the comments above the key
+        Some(item) => {
+            if let Some(old_value) = item.as_value() {
+                *new_value.decor_mut() = old_value.decor().clone();
+            }
+            *item = Item::Value(new_value);
+        }
+        None => {
+            table.insert(key, Item::Value(new_value));
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(text: &str, key: &str, value: Value) -> String {
+        let (section, name) = key.split_once('.').unwrap();
+        set_in_document(text, section, name, &value).unwrap()
+    }
+
+    #[test]
+    fn replaces_a_value_keeping_comments() {
+        let text = "# recall settings\n\n[ui]\n# more is slower\nhistory_limit = 100 # was 50\n\n[sync]\nevery = 5\n";
+        assert_eq!(
+            set(text, "ui.history_limit", Value::Integer(250)),
+            "# recall settings\n\n[ui]\n# more is slower\nhistory_limit = 250 # was 50\n\n[sync]\nevery = 5\n"
+        );
+    }
+
+    #[test]
+    fn replaces_a_multi_line_array() {
+        let text = "[ignore]\npatterns = [\n  \"ls\",\n  \"cd\", # common\n]\nbinaries = []\n";
+        let updated = set(text, "ignore.patterns", Value::Array(vec!["pwd".into()]));
+        assert_eq!(updated, "[ignore]\npatterns = [\"pwd\"]\nbinaries = []\n");
+        assert_eq!(Config::from_toml(&updated).unwrap().ignore.patterns, ["pwd"]);
+    }
+
+    #[test]
+    fn adds_keys_and_sections() {
+        let text = "[ui]\nhistory_limit = 100\n";
+        assert_eq!(
+            set(text, "ui.session_colors", Value::Array(vec!["red".into()])),
+            "[ui]\nhistory_limit = 100\nsession_colors = [\"red\"]\n"
+        );
+        assert_eq!(
+            set(text, "sync.url", Value::String("http://sync.lan".to_string())),
+            "[ui]\nhistory_limit = 100\n\n[sync]\nurl = \"http://sync.lan\"\n"
+        );
+        assert_eq!(set("", "sync.every", Value::Integer(0)), "[sync]\nevery = 0\n");
+    }
+
+    #[test]
+    fn quotes_strings_that_need_it() {
+        let updated = set("", "redact.placeholder", Value::String("say \"no\"\\".to_string()));
+        assert_eq!(Config::from_toml(&updated).unwrap().redact.placeholder, "say \"no\"\\");
+    }
+
+    #[test]
+    fn refuses_a_broken_document() {
+        assert!(set_in_document("[ui\n", "ui", "history_limit", &Value::Integer(1)).is_err());
+        assert!(set_in_document("ui = 5\n", "ui", "history_limit", &Value::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn masks_secrets_that_are_set() {
+        let mut config = Config::default();
+        config.sync.auth_token = Some("hunter2".to_string());
+        config.database.key_file = Some("~/.recall.key".to_string());
+
+        let mut table = config.to_table();
+        mask_secrets(&mut table, None);
+        let shown = toml::to_string(&table).unwrap();
+        assert!(!shown.contains("hunter2") && !shown.contains("recall.key"), "{}", shown);
+        assert_eq!(lookup(&table, "sync.auth_token").unwrap(), Value::String(MASK.to_string()));
+        assert_eq!(lookup(&table, "sync.url").unwrap(), Value::String(String::new()));
+
+        let Value::Table(mut sync) = lookup(&config.to_table(), "sync").unwrap() else { panic!() };
+        mask_secrets(&mut sync, Some("sync"));
+        assert_eq!(sync["auth_token"], Value::String(MASK.to_string()));
+
+        // Unset secrets stay empty, so it is clear they are unset
+        let mut table = Config::default().to_table();
+        mask_secrets(&mut table, None);
+        assert_eq!(lookup(&table, "database.key_file").unwrap(), Value::String(String::new()));
+    }
+}
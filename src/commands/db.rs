@@ -0,0 +1,116 @@
+use crate::db::{get_db_file_path, DatabaseManager};
+use crate::encryption;
+use crate::migrations::latest_version;
+
+pub async fn migrate_database() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::open_unmigrated(&get_db_file_path()?).await?;
+
+    let applied = db_manager.migrate().await?;
+    if applied.is_empty() {
+        println!("Database is up to date (schema version {}).", db_manager.schema_version().await?);
+    } else {
+        for migration in applied {
+            println!("Applied migration {}: {}", migration.version, migration.name);
+        }
+        println!("Database is now at schema version {}.", db_manager.schema_version().await?);
+    }
+
+    Ok(())
+}
+
+pub async fn database_status() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_path = get_db_file_path()?;
+    let db_manager = DatabaseManager::open_unmigrated(&db_path).await?;
+
+    println!("Database: {}", db_path.display());
+    println!("Encrypted: {}", if encryption::is_encrypted(&db_path)? { "yes" } else { "no" });
+    println!(
+        "Schema version: {} (latest {})",
+        db_manager.schema_version().await?,
+        latest_version()
+    );
+
+    let pending = db_manager.pending_migrations().await?;
+    if pending.is_empty() {
+        println!("No pending migrations.");
+    } else {
+        println!("Pending migrations:");
+        for migration in pending {
+            println!("  {:>3}  {}", migration.version, migration.name);
+        }
+        println!("Run `recall db migrate` to apply them.");
+    }
+
+    Ok(())
+}
+
+/// Encrypts the database in place with the configured key, or a new
+/// passphrase typed twice.
+pub async fn encrypt_database() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_path = encryption_target()?;
+    if encryption::is_encrypted(&db_path)? {
+        return Err(format!("{} is already encrypted", db_path.display()).into());
+    }
+
+    let (key, prompted) = match encryption::configured_key()? {
+        Some(key) => (key, false),
+        None if encryption::can_prompt() => {
+            let key = encryption::prompt_passphrase("New passphrase: ")?;
+            if encryption::prompt_passphrase("Repeat the passphrase: ")? != key {
+                return Err("The passphrases do not match".into());
+            }
+            (key, true)
+        }
+        None => {
+            return Err(format!(
+                "No key to encrypt with: set {} or database.key_file, or run this in a terminal to type a passphrase",
+                encryption::KEY_VARIABLE
+            )
+            .into())
+        }
+    };
+
+    let db_manager = DatabaseManager::open_unmigrated(&db_path).await?;
+    db_manager.rekey(Some(&key)).await?;
+    if !encryption::is_encrypted(&db_path)? {
+        return Err(format!("{} could not be encrypted", db_path.display()).into());
+    }
+
+    println!("Encrypted {}.", db_path.display());
+    if prompted {
+        println!(
+            "The shell integration cannot ask for the passphrase: set {} or database.key_file so that it can keep recording commands.",
+            encryption::KEY_VARIABLE
+        );
+    }
+    Ok(())
+}
+
+/// Turns an encrypted database back into a plain one.
+pub async fn decrypt_database() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_path = encryption_target()?;
+    if !encryption::is_encrypted(&db_path)? {
+        return Err(format!("{} is not encrypted", db_path.display()).into());
+    }
+
+    let db_manager = DatabaseManager::open_unmigrated(&db_path).await?;
+    db_manager.rekey(None).await?;
+    if encryption::is_encrypted(&db_path)? {
+        return Err(format!("{} could not be decrypted", db_path.display()).into());
+    }
+
+    println!("Decrypted {}.", db_path.display());
+    Ok(())
+}
+
+/// The database to encrypt or decrypt, when this recall can do either.
+fn encryption_target() -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    if !encryption::is_supported() {
+        return Err("This recall was built without encryption support; rebuild it with `cargo install --features encryption`".into());
+    }
+    let db_path = get_db_file_path()?;
+    if !db_path.is_file() {
+        return Err(format!("No database at {}", db_path.display()).into());
+    }
+    Ok(db_path)
+}
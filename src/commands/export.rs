@@ -0,0 +1,178 @@
+use crate::commands::search::FilterArgs;
+use crate::db::{CommandHistoryEntry, DatabaseManager, HistoryFilter};
+use crate::output::{EntryWriter, OutputFormat};
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    Csv,
+    /// A bash history file with `#<epoch>` timestamps
+    Bash,
+    /// A zsh history file in the EXTENDED_HISTORY format
+    Zsh,
+    /// A fish history file
+    Fish,
+}
+
+pub async fn export_commands(
+    filter_args: &FilterArgs,
+    format: ExportFormat,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let filter = filter_args.to_filter(&db_manager, None).await?;
+    let oldest_first = !filter_args.reverse;
+
+    let write_entry: fn(&mut dyn Write, &CommandHistoryEntry) -> io::Result<()> = match format {
+        ExportFormat::Json => return export_table(&db_manager, &filter, oldest_first, OutputFormat::Json).await,
+        ExportFormat::Ndjson => return export_table(&db_manager, &filter, oldest_first, OutputFormat::Ndjson).await,
+        ExportFormat::Csv => return export_table(&db_manager, &filter, oldest_first, OutputFormat::Csv).await,
+        ExportFormat::Bash => write_bash_entry,
+        ExportFormat::Zsh => write_zsh_entry,
+        ExportFormat::Fish => write_fish_entry,
+    };
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    db_manager
+        .stream_commands(&filter, oldest_first, |entry| Ok(write_entry(&mut out, &entry)?))
+        .await?;
+    out.flush()?;
+
+    Ok(())
+}
+
+async fn export_table(
+    db_manager: &DatabaseManager,
+    filter: &HistoryFilter,
+    oldest_first: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut writer = EntryWriter::new(io::BufWriter::new(io::stdout().lock()), format);
+    writer.begin()?;
+    db_manager
+        .stream_commands(filter, oldest_first, |entry| Ok(writer.write(&entry)?))
+        .await?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+// The history file formats below are written the way the shells write them,
+// so the output can be read back by `recall import` or by the shell itself.
+
+fn write_bash_entry(out: &mut dyn Write, entry: &CommandHistoryEntry) -> io::Result<()> {
+    writeln!(out, "#{}\n{}", entry.timestamp.timestamp(), entry.full_command())
+}
+
+fn write_zsh_entry(out: &mut dyn Write, entry: &CommandHistoryEntry) -> io::Result<()> {
+    let elapsed = entry.duration_ms.unwrap_or(0) / 1000;
+    write!(out, ": {}:{};", entry.timestamp.timestamp(), elapsed)?;
+    out.write_all(&metafy(entry.full_command().replace('\n', "\\\n").as_bytes()))?;
+    writeln!(out)
+}
+
+fn write_fish_entry(out: &mut dyn Write, entry: &CommandHistoryEntry) -> io::Result<()> {
+    let escaped = entry
+        .full_command()
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n");
+    writeln!(out, "- cmd: {}\n  when: {}", escaped, entry.timestamp.timestamp())
+}
+
+/// The inverse of zsh's unmetafy: NUL, the Meta byte 0x83 and zsh's internal
+/// token bytes are written as 0x83 followed by the byte XOR 32.
+fn metafy(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        if byte == 0 || (0x83..=0xa2).contains(&byte) {
+            result.push(0x83);
+            result.push(byte ^ 32);
+        } else {
+            result.push(byte);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::import::{parse_fish_history, parse_zsh_history, unmetafy, HistoryLine};
+    use chrono::{TimeZone, Utc};
+
+    fn entry(line: &str, timestamp: i64, duration_ms: Option<i64>) -> CommandHistoryEntry {
+        CommandHistoryEntry {
+            id: None,
+            timestamp: Utc.timestamp_opt(timestamp, 0).unwrap(),
+            command: String::new(),
+            binary: String::new(),
+            env: None,
+            wrappers: None,
+            user: "alice".to_string(),
+            pwd: "/home/alice".to_string(),
+            session_id: 1,
+            exit_code: None,
+            duration_ms,
+            finished_at: None,
+            invocation_id: None,
+            position: 0,
+            line: Some(line.to_string()),
+            hostname: None,
+        }
+    }
+
+    fn entries() -> Vec<CommandHistoryEntry> {
+        vec![
+            entry("git status", 1_700_000_000, Some(1200)),
+            entry("for f in *.rs\ndo\n  wc -l \"$f\"\ndone", 1_700_000_060, None),
+            entry(r"printf 'a\nb\\c' | grep -E '\d+'", 1_700_000_120, Some(5000)),
+            entry("echo 'héllo wörld ✓ 日本'", 1_700_000_180, None),
+        ]
+    }
+
+    fn export(write_entry: fn(&mut dyn Write, &CommandHistoryEntry) -> io::Result<()>) -> Vec<u8> {
+        let mut out = Vec::new();
+        for entry in entries() {
+            write_entry(&mut out, &entry).unwrap();
+        }
+        out
+    }
+
+    fn assert_round_trip(lines: &[HistoryLine], skipped: usize) {
+        assert_eq!(skipped, 0);
+        let expected = entries();
+        assert_eq!(lines.len(), expected.len());
+        for (line, entry) in lines.iter().zip(&expected) {
+            assert_eq!(line.command, entry.full_command());
+            assert_eq!(line.timestamp, Some(entry.timestamp));
+        }
+    }
+
+    #[test]
+    fn zsh_export_reads_back() {
+        let bytes = export(write_zsh_entry);
+        let (lines, skipped) = parse_zsh_history(&String::from_utf8_lossy(&unmetafy(&bytes)));
+        assert_round_trip(&lines, skipped);
+        assert_eq!(lines[0].duration_ms, Some(1000));
+        assert_eq!(lines[2].duration_ms, Some(5000));
+    }
+
+    #[test]
+    fn fish_export_reads_back() {
+        let bytes = export(write_fish_entry);
+        let (lines, skipped) = parse_fish_history(&String::from_utf8(bytes).unwrap());
+        assert_round_trip(&lines, skipped);
+    }
+
+    #[test]
+    fn metafy_escapes_zsh_tokens() {
+        let text = "echo '日本'".as_bytes();
+        assert_ne!(metafy(text), text);
+        assert_eq!(unmetafy(&metafy(text)), text);
+        assert_eq!(unmetafy(&metafy(&[0, 0x83, 0xa2, 0xa3])), [0, 0x83, 0xa2, 0xa3]);
+    }
+}
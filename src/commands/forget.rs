@@ -0,0 +1,136 @@
+use crate::commands::log::get_session_id;
+use crate::db::{CommandHistoryEntry, DatabaseManager, HistoryFilter};
+use clap::Args;
+use regex::Regex;
+
+/// Which commands `recall forget` deletes; exactly one must be given.
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = false)]
+pub struct ForgetTarget {
+    /// The most recent command run in this terminal; an error when this
+    /// terminal has not recorded any
+    #[arg(long)]
+    pub last: bool,
+    /// The command with this id, as shown by `recall search --format json`
+    #[arg(long, value_name = "ID")]
+    pub id: Option<i64>,
+    /// Every command line matching this regex
+    #[arg(long = "match", value_name = "REGEX")]
+    pub pattern: Option<String>,
+}
+
+/// Deletes commands from the history for good, including from the search
+/// index, and vacuums the database so their text does not linger on disk.
+pub async fn forget_commands(target: &ForgetTarget, dry_run: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let entries = find_targets(&db_manager, target).await?;
+
+    for entry in &entries {
+        println!("{}\t{}", entry.id.unwrap_or_default(), entry.full_command());
+    }
+
+    match (entries.len(), dry_run) {
+        (0, _) => println!("No matching commands."),
+        (n, true) => println!("Would forget {} commands. Run without --dry-run to delete them.", n),
+        (_, false) => {
+            let ids: Vec<i64> = entries.iter().filter_map(|entry| entry.id).collect();
+            let stats = db_manager.delete_entries(&ids).await?;
+            db_manager.vacuum(false).await?;
+            println!("Forgot {} commands.", stats.commands);
+        }
+    }
+
+    Ok(())
+}
+
+async fn find_targets(
+    db_manager: &DatabaseManager,
+    target: &ForgetTarget,
+) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    if target.last {
+        // Never another terminal's command: it would be deleted unseen
+        let session_id = db_manager.find_session(&get_session_id()).await?.ok_or(
+            "This terminal has no recorded session, so it has no last command; use --id or --match instead",
+        )?;
+        let filter = HistoryFilter {
+            session_id: Some(session_id),
+            limit: Some(1),
+            ..HistoryFilter::default()
+        };
+        return db_manager.query_commands(&filter).await;
+    }
+
+    if let Some(id) = target.id {
+        return match db_manager.get_entry(id).await? {
+            Some(entry) => Ok(vec![entry]),
+            None => Err(format!("No command with id {}", id).into()),
+        };
+    }
+
+    let pattern = target.pattern.as_deref().unwrap_or_default();
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid regex '{}': {}", pattern, e))?;
+    let mut matches = Vec::new();
+    db_manager
+        .stream_commands(&HistoryFilter::default(), true, |entry| {
+            if regex.is_match(entry.full_command()) {
+                matches.push(entry);
+            }
+            Ok(())
+        })
+        .await?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::log::command_entries;
+    use crate::db::SessionDetails;
+    use chrono::Utc;
+
+    async fn log(db: &DatabaseManager, key: &str, line: &str) {
+        let session_id = db.get_or_create_session(key, &SessionDetails::default()).await.unwrap();
+        let template = CommandHistoryEntry {
+            id: None,
+            timestamp: Utc::now(),
+            command: String::new(),
+            binary: String::new(),
+            env: None,
+            wrappers: None,
+            user: "alice".to_string(),
+            pwd: "/home/alice".to_string(),
+            session_id,
+            exit_code: None,
+            duration_ms: None,
+            finished_at: None,
+            invocation_id: None,
+            position: 0,
+            line: None,
+            hostname: None,
+        };
+        db.log_command(line, &command_entries(line, &template)).await.unwrap();
+    }
+
+    fn last() -> ForgetTarget {
+        ForgetTarget {
+            last: true,
+            id: None,
+            pattern: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn last_only_targets_this_terminal() {
+        let path = std::env::temp_dir().join(format!("recall-test-{}.db", uuid::Uuid::new_v4()));
+        let db = DatabaseManager::open(&path).await.unwrap();
+        log(&db, "term_pts_9_77", "rm -rf build").await;
+
+        let error = find_targets(&db, &last()).await.unwrap_err();
+        assert!(error.to_string().starts_with("This terminal has no recorded session"), "{}", error);
+
+        log(&db, &get_session_id(), "echo secret").await;
+        log(&db, "term_pts_9_77", "make").await;
+        let targets = find_targets(&db, &last()).await.unwrap();
+        assert_eq!(targets.iter().map(|entry| entry.full_command()).collect::<Vec<_>>(), ["echo secret"]);
+    }
+}
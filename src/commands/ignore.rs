@@ -0,0 +1,29 @@
+use crate::commands::log::{get_session_id, ignore_reason};
+use crate::config;
+use crate::db::DatabaseManager;
+use crate::ignore::IgnoreRules;
+use crate::redact::Redactor;
+use std::env;
+use std::path::Path;
+
+/// Reports whether `command`, run here in this shell, would be recorded.
+/// Unlike logging, a broken config is an error rather than falling back to
+/// the defaults, since checking the config is the point.
+pub async fn test_ignore(command: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = config::get()?;
+    let rules = IgnoreRules::from_config(&config.ignore)?;
+    let redactor = Redactor::from_config(&config.redact)?;
+    let redacted = redactor.redact(command);
+
+    let db_manager = DatabaseManager::new().await?;
+    let session_id = db_manager.find_session(&get_session_id()).await?;
+    let cwd = env::current_dir().ok();
+    let cwd_path = cwd.as_deref().unwrap_or(Path::new(""));
+
+    match ignore_reason(&rules, command, &redacted.text, cwd_path, &db_manager, session_id).await? {
+        Some(reason) => println!("Ignored: {}", reason),
+        None => println!("Recorded: no ignore rule matches"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,722 @@
+use crate::commands::log::{command_entries, hostname, machine_id};
+use crate::config;
+use crate::db::{CommandHistoryEntry, DatabaseManager, ImportStats, ImportedInvocation, SessionDetails};
+use crate::redact::Redactor;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use clap::ValueEnum;
+use dirs::home_dir;
+use libsql::{Builder, Connection, OpenFlags};
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ImportSource {
+    /// ~/.bash_history, with `#<epoch>` timestamps when HISTTIMEFORMAT was set
+    Bash,
+    /// ~/.zsh_history, plain or in the EXTENDED_HISTORY format
+    Zsh,
+    /// ~/.local/share/fish/fish_history
+    Fish,
+    /// atuin's history.db
+    Atuin,
+    /// McFly's history.db
+    Mcfly,
+}
+
+impl ImportSource {
+    fn name(self) -> &'static str {
+        match self {
+            ImportSource::Bash => "bash",
+            ImportSource::Zsh => "zsh",
+            ImportSource::Fish => "fish",
+            ImportSource::Atuin => "atuin",
+            ImportSource::Mcfly => "mcfly",
+        }
+    }
+
+    fn default_path(self) -> Option<PathBuf> {
+        let home = home_dir()?;
+        match self {
+            ImportSource::Bash => Some(home.join(".bash_history")),
+            ImportSource::Zsh => [".zsh_history", ".histfile"]
+                .iter()
+                .map(|name| home.join(name))
+                .find(|path| path.exists())
+                .or_else(|| Some(home.join(".zsh_history"))),
+            ImportSource::Fish => Some(data_dir(&home).join("fish").join("fish_history")),
+            ImportSource::Atuin => Some(data_dir(&home).join("atuin").join("history.db")),
+            ImportSource::Mcfly => [
+                data_dir(&home).join("mcfly").join("history.db"),
+                home.join(".mcfly").join("history.db"),
+                home.join("Library/Application Support/McFly/history.db"),
+            ]
+            .into_iter()
+            .find(|path| path.exists())
+            .or_else(|| Some(data_dir(&home).join("mcfly").join("history.db"))),
+        }
+    }
+}
+
+fn data_dir(home: &Path) -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local/share"))
+}
+
+/// One command read from a shell history file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryLine {
+    pub command: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub duration_ms: Option<i64>,
+}
+
+pub async fn import_history(
+    source: ImportSource,
+    file: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = match file {
+        Some(path) => path.to_path_buf(),
+        None => source
+            .default_path()
+            .ok_or("Could not determine the home directory; pass --file")?,
+    };
+    if !path.exists() {
+        return Err(format!("{} does not exist; pass --file", path.display()).into());
+    }
+
+    match source {
+        ImportSource::Bash => {
+            import_history_file(source, &path, |bytes| parse_bash_history(&String::from_utf8_lossy(bytes))).await
+        }
+        ImportSource::Zsh => {
+            import_history_file(source, &path, |bytes| {
+                parse_zsh_history(&String::from_utf8_lossy(&unmetafy(bytes)))
+            })
+            .await
+        }
+        ImportSource::Fish => {
+            import_history_file(source, &path, |bytes| parse_fish_history(&String::from_utf8_lossy(bytes))).await
+        }
+        ImportSource::Atuin => import_atuin(&path).await,
+        ImportSource::Mcfly => import_mcfly(&path).await,
+    }
+}
+
+/// Imports a plain-text shell history file into a session of its own.
+async fn import_history_file(
+    source: ImportSource,
+    path: &Path,
+    parse: impl Fn(&[u8]) -> (Vec<HistoryLine>, usize),
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let (lines, mut skipped) = parse(&bytes);
+    let redactor = Redactor::from_config(&config::get()?.redact)?;
+
+    let db_manager = DatabaseManager::new().await?;
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let session_key = format!("import:{}:{}", source.name(), canonical.display());
+    let session_id = db_manager.get_or_create_session(&session_key, &imported_session(hostname())).await?;
+
+    let template = CommandHistoryEntry {
+        id: None,
+        timestamp: Utc::now(),
+        command: String::new(),
+        binary: String::new(),
+        env: None,
+        wrappers: None,
+        user: env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
+        pwd: "unknown".to_string(),
+        session_id,
+        exit_code: None,
+        duration_ms: None,
+        finished_at: None,
+        invocation_id: None,
+        position: 0,
+        line: None,
+        hostname: None,
+    };
+
+    // Lines without a timestamp are placed a second apart after the last
+    // known one, or counted back from the file's modification time when there
+    // is none.
+    let modified: DateTime<Utc> = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Utc::now());
+    let count = lines.len() as i64;
+    let mut last_known = None;
+
+    let mut invocations = Vec::with_capacity(lines.len());
+    for (index, line) in lines.into_iter().enumerate() {
+        if line.command.trim().is_empty() {
+            skipped += 1;
+            continue;
+        }
+
+        let timestamp = match (line.timestamp, last_known) {
+            (Some(timestamp), _) => {
+                last_known = Some((timestamp, index));
+                timestamp
+            }
+            (None, Some((previous, previous_index))) => previous + Duration::seconds((index - previous_index) as i64),
+            (None, None) => modified - Duration::seconds(count - index as i64),
+        };
+        let finished_at = match line.duration_ms.map(|ms| finish_time(timestamp, ms)) {
+            Some(None) => {
+                skipped += 1;
+                continue;
+            }
+            finished_at => finished_at.flatten(),
+        };
+
+        let entry = CommandHistoryEntry {
+            timestamp,
+            duration_ms: line.duration_ms,
+            finished_at,
+            ..template.clone()
+        };
+        let command = redactor.redact(&line.command).text.into_owned();
+        invocations.push(ImportedInvocation {
+            entries: command_entries(&command, &entry),
+            line: command,
+            source_position: line.timestamp.is_none().then_some(index as i64),
+        });
+    }
+
+    let stats = db_manager.import_invocations(&invocations).await?;
+    print_import_stats(&stats, skipped, &path.display().to_string());
+
+    Ok(())
+}
+
+/// A command read from another tool's history database.
+struct ForeignCommand {
+    session: String,
+    command: String,
+    timestamp: DateTime<Utc>,
+    duration_ms: Option<i64>,
+    exit_code: Option<i32>,
+    cwd: Option<String>,
+    user: Option<String>,
+    /// The machine the command ran on, when the tool records it
+    host: Option<String>,
+}
+
+/// Imports atuin's `history` table. atuin records times in nanoseconds,
+/// marks commands that never finished with a negative duration and keeps
+/// deleted entries around with `deleted_at` set.
+async fn import_atuin(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = open_foreign_database(path).await?;
+    let deleted_at = if has_column(&conn, "history", "deleted_at").await? {
+        "deleted_at"
+    } else {
+        "NULL"
+    };
+
+    let mut commands = Vec::new();
+    let mut skipped = 0;
+    let mut rows = conn
+        .query(
+            &format!(
+                "SELECT session, command, timestamp, duration, exit, cwd, hostname, {} FROM history ORDER BY timestamp",
+                deleted_at
+            ),
+            (),
+        )
+        .await?;
+    while let Some(row) = rows.next().await? {
+        if row.get::<Option<i64>>(7)?.is_some() {
+            skipped += 1;
+            continue;
+        }
+
+        let duration = row.get::<Option<i64>>(3)?.filter(|d| *d >= 0);
+        // atuin stores `hostname:username`
+        let (host, user) = match row.get::<Option<String>>(6)? {
+            Some(value) => match value.rsplit_once(':') {
+                Some((host, user)) => (Some(host.to_string()), Some(user.to_string())),
+                None => (Some(value), None),
+            },
+            None => (None, None),
+        };
+        commands.push(ForeignCommand {
+            session: row.get::<String>(0)?,
+            command: row.get::<String>(1)?,
+            timestamp: Utc.timestamp_nanos(row.get::<i64>(2)?),
+            duration_ms: duration.map(|d| d / 1_000_000),
+            exit_code: duration.and(row.get::<Option<i32>>(4)?),
+            cwd: row.get::<Option<String>>(5)?,
+            user,
+            host,
+        });
+    }
+
+    import_foreign_commands(ImportSource::Atuin, path, commands, skipped).await
+}
+
+/// Imports McFly's `commands` table, whose times are in seconds.
+async fn import_mcfly(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = open_foreign_database(path).await?;
+
+    let mut commands = Vec::new();
+    let mut skipped = 0;
+    let mut rows = conn
+        .query("SELECT session_id, cmd, when_run, exit_code, dir FROM commands ORDER BY id", ())
+        .await?;
+    while let Some(row) = rows.next().await? {
+        let Some(timestamp) = row
+            .get::<Option<i64>>(2)?
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        else {
+            skipped += 1;
+            continue;
+        };
+
+        commands.push(ForeignCommand {
+            session: row.get::<Option<String>>(0)?.unwrap_or_default(),
+            command: row.get::<String>(1)?,
+            timestamp,
+            duration_ms: None,
+            exit_code: row.get::<Option<i32>>(3)?,
+            cwd: row.get::<Option<String>>(4)?,
+            user: None,
+            host: None,
+        });
+    }
+
+    import_foreign_commands(ImportSource::Mcfly, path, commands, skipped).await
+}
+
+/// Stores commands read from another tool, giving each of its sessions a
+/// recall session of its own.
+async fn import_foreign_commands(
+    source: ImportSource,
+    path: &Path,
+    commands: Vec<ForeignCommand>,
+    mut skipped: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let redactor = Redactor::from_config(&config::get()?.redact)?;
+    let db_manager = DatabaseManager::new().await?;
+    let default_user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let mut sessions: HashMap<String, i64> = HashMap::new();
+    let local_host = hostname();
+
+    let mut invocations = Vec::with_capacity(commands.len());
+    for command in commands {
+        if command.command.trim().is_empty() {
+            skipped += 1;
+            continue;
+        }
+
+        let finished_at = match command.duration_ms.map(|ms| finish_time(command.timestamp, ms)) {
+            Some(None) => {
+                skipped += 1;
+                continue;
+            }
+            finished_at => finished_at.flatten(),
+        };
+
+        let session_id = match sessions.get(&command.session) {
+            Some(id) => *id,
+            None => {
+                let key = format!("import:{}:{}", source.name(), command.session);
+                let host = command.host.clone().or_else(|| local_host.clone());
+                let id = db_manager.get_or_create_session(&key, &imported_session(host)).await?;
+                sessions.insert(command.session.clone(), id);
+                id
+            }
+        };
+
+        let template = CommandHistoryEntry {
+            id: None,
+            timestamp: command.timestamp,
+            command: String::new(),
+            binary: String::new(),
+            env: None,
+            wrappers: None,
+            user: command.user.unwrap_or_else(|| default_user.clone()),
+            pwd: command.cwd.unwrap_or_else(|| "unknown".to_string()),
+            session_id,
+            exit_code: command.exit_code,
+            duration_ms: command.duration_ms,
+            finished_at,
+            invocation_id: None,
+            position: 0,
+            line: None,
+            hostname: None,
+        };
+        let line = redactor.redact(&command.command).text.into_owned();
+        invocations.push(ImportedInvocation {
+            entries: command_entries(&line, &template),
+            line,
+            source_position: None,
+        });
+    }
+
+    let stats = db_manager.import_invocations(&invocations).await?;
+    print_import_stats(&stats, skipped, &path.display().to_string());
+
+    Ok(())
+}
+
+/// When a command that started at `timestamp` and ran for `duration_ms`
+/// finished, or `None` if a corrupt duration puts that out of range.
+fn finish_time(timestamp: DateTime<Utc>, duration_ms: i64) -> Option<DateTime<Utc>> {
+    timestamp.checked_add_signed(Duration::try_milliseconds(duration_ms)?)
+}
+
+/// Sessions created by an import only know the host they came from, and
+/// its machine id when that is this machine.
+fn imported_session(hostname: Option<String>) -> SessionDetails {
+    let machine_id = match &hostname {
+        Some(host) if Some(host) == self::hostname().as_ref() => machine_id(),
+        _ => None,
+    };
+    SessionDetails {
+        hostname,
+        machine_id,
+        strategy: Some("import".to_string()),
+        ..Default::default()
+    }
+}
+
+async fn open_foreign_database(path: &Path) -> Result<Connection, Box<dyn std::error::Error + Send + Sync>> {
+    let db = Builder::new_local(path)
+        .flags(OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .build()
+        .await
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    Ok(db.connect()?)
+}
+
+async fn has_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let mut rows = conn.query(&format!("PRAGMA table_info({})", table), ()).await?;
+    while let Some(row) = rows.next().await? {
+        if row.get::<String>(1)? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+pub fn print_import_stats(stats: &ImportStats, skipped: usize, from: &str) {
+    println!(
+        "Imported {} commands from {} ({} duplicates, {} skipped)",
+        stats.imported,
+        from,
+        stats.duplicates,
+        stats.skipped + skipped
+    );
+}
+
+/// Reads a bash history file. With HISTTIMEFORMAT set, bash writes a
+/// `#<epoch>` line before each entry and everything up to the next marker
+/// belongs to that entry; otherwise every line is its own command.
+pub fn parse_bash_history(content: &str) -> (Vec<HistoryLine>, usize) {
+    let mut lines: Vec<HistoryLine> = Vec::new();
+    let mut current: Option<HistoryLine> = None;
+
+    for line in content.lines() {
+        if let Some(timestamp) = line.strip_prefix('#').and_then(parse_epoch) {
+            lines.extend(current.take());
+            current = Some(HistoryLine {
+                command: String::new(),
+                timestamp: Some(timestamp),
+                duration_ms: None,
+            });
+            continue;
+        }
+
+        match &mut current {
+            Some(entry) if entry.command.is_empty() => entry.command.push_str(line),
+            Some(entry) => {
+                entry.command.push('\n');
+                entry.command.push_str(line);
+            }
+            None => lines.push(HistoryLine {
+                command: line.to_string(),
+                timestamp: None,
+                duration_ms: None,
+            }),
+        }
+    }
+    lines.extend(current);
+
+    (lines, 0)
+}
+
+/// Reads a zsh history file, either plain or written with EXTENDED_HISTORY
+/// (`: <start>:<elapsed>;<command>`). Multi-line commands are stored with a
+/// backslash before each embedded newline.
+pub fn parse_zsh_history(content: &str) -> (Vec<HistoryLine>, usize) {
+    let mut lines = Vec::new();
+    let mut skipped = 0;
+    let mut raw_lines = content.lines();
+
+    while let Some(first) = raw_lines.next() {
+        let mut record = first.to_string();
+        while record.ends_with('\\') {
+            record.pop();
+            record.push('\n');
+            match raw_lines.next() {
+                Some(next) => record.push_str(next),
+                None => break,
+            }
+        }
+
+        if let Some(rest) = record.strip_prefix(": ") {
+            let Some((meta, command)) = rest.split_once(';') else {
+                skipped += 1;
+                continue;
+            };
+            let (start, elapsed) = meta.split_once(':').unwrap_or((meta, ""));
+            let Some(timestamp) = parse_epoch(start) else {
+                skipped += 1;
+                continue;
+            };
+            let duration_ms = match elapsed.trim().parse::<i64>() {
+                Ok(secs) => match secs.checked_mul(1000) {
+                    Some(ms) => Some(ms),
+                    None => {
+                        skipped += 1;
+                        continue;
+                    }
+                },
+                Err(_) => None,
+            };
+            lines.push(HistoryLine {
+                command: command.to_string(),
+                timestamp: Some(timestamp),
+                duration_ms,
+            });
+        } else {
+            lines.push(HistoryLine {
+                command: record,
+                timestamp: None,
+                duration_ms: None,
+            });
+        }
+    }
+
+    (lines, skipped)
+}
+
+/// Reads fish's YAML-like history file:
+///
+/// ```text
+/// - cmd: git status
+///   when: 1700000000
+///   paths:
+///     - src
+/// ```
+pub fn parse_fish_history(content: &str) -> (Vec<HistoryLine>, usize) {
+    let mut lines: Vec<HistoryLine> = Vec::new();
+
+    for line in content.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            lines.push(HistoryLine {
+                command: unescape_fish(command),
+                timestamp: None,
+                duration_ms: None,
+            });
+        } else if let Some(when) = line.strip_prefix("  when: ") {
+            if let Some(entry) = lines.last_mut() {
+                entry.timestamp = parse_epoch(when);
+            }
+        }
+    }
+
+    (lines, 0)
+}
+
+/// fish escapes backslashes and newlines in the `cmd` field.
+fn unescape_fish(command: &str) -> String {
+    let mut result = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// zsh writes bytes that clash with its internal tokens as 0x83 followed by
+/// the byte XOR 32.
+pub fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&byte) = iter.next() {
+        if byte == 0x83 {
+            if let Some(&next) = iter.next() {
+                result.push(next ^ 32);
+            }
+        } else {
+            result.push(byte);
+        }
+    }
+    result
+}
+
+fn parse_epoch(value: &str) -> Option<DateTime<Utc>> {
+    let seconds = value.trim().parse::<i64>().ok()?;
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(lines: &[HistoryLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.command.as_str()).collect()
+    }
+
+    fn epoch(seconds: i64) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(seconds, 0).single()
+    }
+
+    #[test]
+    fn parses_plain_bash_history() {
+        let (lines, skipped) = parse_bash_history("ls\ncd /tmp\nls\n");
+        assert_eq!(commands(&lines), ["ls", "cd /tmp", "ls"]);
+        assert!(lines.iter().all(|line| line.timestamp.is_none()));
+        assert_eq!(skipped, 0);
+    }
+
+    #[test]
+    fn parses_timestamped_bash_history() {
+        let (lines, _) = parse_bash_history("#1700000000\nls\n#1700000060\nfor x in a b\ndo echo $x\ndone\n#comment\n");
+        assert_eq!(commands(&lines), ["ls", "for x in a b\ndo echo $x\ndone\n#comment"]);
+        assert_eq!(lines[0].timestamp, epoch(1_700_000_000));
+        assert_eq!(lines[1].timestamp, epoch(1_700_000_060));
+    }
+
+    #[test]
+    fn parses_extended_zsh_history() {
+        let content = ": 1700000000:5;make test\n: 1700000010:0;echo a\\\nb\nplain command\n";
+        let (lines, skipped) = parse_zsh_history(content);
+        assert_eq!(commands(&lines), ["make test", "echo a\nb", "plain command"]);
+        assert_eq!(lines[0].timestamp, epoch(1_700_000_000));
+        assert_eq!(lines[0].duration_ms, Some(5000));
+        assert_eq!(lines[1].duration_ms, Some(0));
+        assert_eq!(lines[2].timestamp, None);
+        assert_eq!(skipped, 0);
+    }
+
+    #[test]
+    fn skips_corrupt_zsh_lines() {
+        let content = ": 1700000000:99999999999999999;ls\n: notatime:0;ls\n: 1700000000:0\n: 1700000000:0;pwd\n";
+        let (lines, skipped) = parse_zsh_history(content);
+        assert_eq!(commands(&lines), ["pwd"]);
+        assert_eq!(skipped, 3);
+    }
+
+    #[test]
+    fn parses_fish_history() {
+        let content = "- cmd: git status\n  when: 1700000000\n  paths:\n    - src\n- cmd: echo a\\nb \\\\n\n- cmd: ls\n  when: 1700000100\n";
+        let (lines, _) = parse_fish_history(content);
+        assert_eq!(commands(&lines), ["git status", "echo a\nb \\n", "ls"]);
+        assert_eq!(lines[0].timestamp, epoch(1_700_000_000));
+        assert_eq!(lines[1].timestamp, None);
+        assert_eq!(lines[2].timestamp, epoch(1_700_000_100));
+    }
+
+    #[test]
+    fn unescapes_fish_commands() {
+        assert_eq!(unescape_fish(r"a\nb"), "a\nb");
+        assert_eq!(unescape_fish(r"a\\nb"), r"a\nb");
+        assert_eq!(unescape_fish(r"\t\"), r"\t\");
+        assert_eq!(unescape_fish("plain"), "plain");
+    }
+
+    #[test]
+    fn unmetafies_zsh_bytes() {
+        assert_eq!(unmetafy(b"ls"), b"ls");
+        assert_eq!(unmetafy(&[b'a', 0x83, 0xa3 ^ 32, b'b']), [b'a', 0xa3, b'b']);
+        assert_eq!(unmetafy(&[b'a', 0x83]), b"a");
+
+        let metafied: Vec<u8> = "é".bytes().flat_map(|b| [0x83, b ^ 32]).collect();
+        assert_eq!(String::from_utf8(unmetafy(&metafied)).unwrap(), "é");
+    }
+
+    #[test]
+    fn finish_time_rejects_overflow() {
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        assert_eq!(finish_time(start, 1500), Some(start + Duration::milliseconds(1500)));
+        assert_eq!(finish_time(start, i64::MAX), None);
+        assert_eq!(finish_time(start, i64::MIN), None);
+    }
+
+    /// Imports untimed `lines` the way `import_history_file` does.
+    async fn import_untimed(db: &DatabaseManager, session_id: i64, lines: &[&str]) -> ImportStats {
+        let template = CommandHistoryEntry {
+            id: None,
+            timestamp: Utc::now(),
+            command: String::new(),
+            binary: String::new(),
+            env: None,
+            wrappers: None,
+            user: "alice".to_string(),
+            pwd: "unknown".to_string(),
+            session_id,
+            exit_code: None,
+            duration_ms: None,
+            finished_at: None,
+            invocation_id: None,
+            position: 0,
+            line: None,
+            hostname: None,
+        };
+        let invocations: Vec<ImportedInvocation> = lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| ImportedInvocation {
+                entries: command_entries(line, &template),
+                line: line.to_string(),
+                source_position: Some(index as i64),
+            })
+            .collect();
+        db.import_invocations(&invocations).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn untimed_repeats_are_kept_and_reimports_add_nothing() {
+        let path = std::env::temp_dir().join(format!("recall-test-{}.db", uuid::Uuid::new_v4()));
+        let db = DatabaseManager::open(&path).await.unwrap();
+        let session_id = db
+            .get_or_create_session("import:bash:test", &SessionDetails::default())
+            .await
+            .unwrap();
+
+        let history = ["ls", "cd /tmp", "ls", "ls", "git status"];
+        let stats = import_untimed(&db, session_id, &history).await;
+        assert_eq!((stats.imported, stats.duplicates), (5, 0));
+
+        let stats = import_untimed(&db, session_id, &history).await;
+        assert_eq!((stats.imported, stats.duplicates), (0, 5));
+
+        let appended = ["ls", "cd /tmp", "ls", "ls", "git status", "ls"];
+        let stats = import_untimed(&db, session_id, &appended).await;
+        assert_eq!((stats.imported, stats.duplicates), (1, 5));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,101 @@
+use clap::{Args, ValueEnum};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Which keys the integration binds to `recall pick`.
+#[derive(Args, Debug, Clone, Copy, Default)]
+pub struct InitArgs {
+    /// Leave Ctrl-R to the shell's own history search
+    #[arg(long)]
+    pub disable_ctrl_r: bool,
+    /// Leave the up arrow to the shell's own history
+    #[arg(long)]
+    pub disable_up_arrow: bool,
+}
+
+/// Prints the code that hooks recall into `shell`, meant to be evaluated from
+/// the shell's startup file.
+pub fn print_init(shell: Shell, args: &InitArgs) {
+    let script = match shell {
+        Shell::Bash => include_str!("../shell/init.bash"),
+        Shell::Zsh => include_str!("../shell/init.zsh"),
+        Shell::Fish => include_str!("../shell/init.fish"),
+    };
+    print!("{}", script);
+
+    // A fresh token each time this is evaluated gives every shell its own
+    // session, even when several share a tty or a desktop login
+    let token = uuid::Uuid::new_v4();
+    println!();
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            println!("export RECALL_SESSION={}", token);
+            println!("export RECALL_SHELL_PID=$$");
+        }
+        Shell::Fish => {
+            println!("set -gx RECALL_SESSION {}", token);
+            println!("set -gx RECALL_SHELL_PID $fish_pid");
+        }
+    }
+    println!("command recall session start >/dev/null 2>&1");
+
+    let mut bindings = Vec::new();
+    if !args.disable_ctrl_r {
+        bindings.extend(key_bindings(shell, Key::CtrlR));
+    }
+    if !args.disable_up_arrow {
+        bindings.extend(key_bindings(shell, Key::Up));
+    }
+    if !bindings.is_empty() {
+        println!();
+        for binding in bindings {
+            println!("{}", binding);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Key {
+    CtrlR,
+    Up,
+}
+
+fn key_bindings(shell: Shell, key: Key) -> Vec<&'static str> {
+    match (shell, key) {
+        (Shell::Bash, Key::CtrlR) => vec![
+            r#"bind -m emacs '"\C-r": "\C-x\C-r\C-p\C-x\C-r\C-a"'"#,
+            r#"bind -m vi-insert '"\C-r": "\C-x\C-r\C-p\C-x\C-r\C-a"'"#,
+        ],
+        (Shell::Bash, Key::Up) => vec![
+            r#"bind -m emacs '"\e[A": "\C-x\C-r\C-p\C-x\C-r\C-a"'"#,
+            r#"bind -m emacs '"\eOA": "\C-x\C-r\C-p\C-x\C-r\C-a"'"#,
+            r#"bind -m vi-insert '"\e[A": "\C-x\C-r\C-p\C-x\C-r\C-a"'"#,
+            r#"bind -m vi-insert '"\eOA": "\C-x\C-r\C-p\C-x\C-r\C-a"'"#,
+        ],
+        (Shell::Zsh, Key::CtrlR) => vec![
+            "bindkey -M emacs '^R' _recall_pick_widget",
+            "bindkey -M viins '^R' _recall_pick_widget",
+        ],
+        (Shell::Zsh, Key::Up) => vec![
+            "bindkey -M emacs '^[[A' _recall_up_widget",
+            "bindkey -M emacs '^[OA' _recall_up_widget",
+            "bindkey -M viins '^[[A' _recall_up_widget",
+            "bindkey -M viins '^[OA' _recall_up_widget",
+        ],
+        (Shell::Fish, Key::CtrlR) => vec![
+            r"bind \cr _recall_pick",
+            r"bind -M insert \cr _recall_pick",
+        ],
+        (Shell::Fish, Key::Up) => vec![
+            r"bind \e\[A _recall_pick",
+            r"bind \eOA _recall_pick",
+            r"bind -M insert \e\[A _recall_pick",
+            r"bind -M insert \eOA _recall_pick",
+        ],
+    }
+}
@@ -1,38 +1,60 @@
-use crate::db::{CommandHistoryEntry, DatabaseManager};
+use crate::commands::prune::{apply_retention, RETENTION_INTERVAL};
+use crate::commands::sync::spawn_background_sync;
+use crate::config::{self, Config};
+use crate::db::{CommandHistoryEntry, DatabaseManager, SessionDetails};
+use crate::ignore::{IgnoreReason, IgnoreRules};
+use crate::parser::{self, SimpleCommand};
+use crate::redact::Redactor;
 use chrono::Utc;
 use std::env;
+use std::path::Path;
 use std::os::unix::fs::MetadataExt;
 
-fn get_session_id() -> String {
-    if let Ok(session_id) = env::var("XDG_SESSION_ID") {
-        return format!("xdg:{}", session_id);
-    }
-
-    if let Ok(terminal) = std::fs::read_link("/proc/self/fd/0") {
-        if let Some(term_name) = terminal.to_str() {
-            if term_name.starts_with("/dev/") {
-                let clean_name = term_name.replace("/dev/", "");
-                if let Ok(metadata) = std::fs::metadata(term_name) {
-                    return format!("term_{}_{}", clean_name, metadata.ino());
-                }
-                return format!("term_{}", clean_name);
-            }
+/// The key a terminal's commands are recorded under, and where it came from.
+pub struct SessionIdentity {
+    pub key: String,
+    /// Which source the key was taken from, e.g. `token` for `$RECALL_SESSION`
+    pub strategy: &'static str,
+}
+
+/// Works out which session this terminal belongs to. The token that
+/// `recall init` exports is unique to each shell; the other sources are
+/// guesses for shells without it.
+pub fn session_identity() -> SessionIdentity {
+    let identity = |key: String, strategy| SessionIdentity { key, strategy };
+
+    if let Ok(token) = env::var("RECALL_SESSION") {
+        if !token.is_empty() {
+            return identity(format!("recall:{}", token), "token");
         }
     }
 
+    if let Some(tty) = tty() {
+        let clean_name = tty.replace("/dev/", "");
+        if let Ok(metadata) = std::fs::metadata(&tty) {
+            return identity(format!("term_{}_{}", clean_name, metadata.ino()), "tty");
+        }
+        return identity(format!("term_{}", clean_name), "tty");
+    }
+
+    // Shared by every terminal of a desktop login, so only used without a tty
+    if let Ok(session_id) = env::var("XDG_SESSION_ID") {
+        return identity(format!("xdg:{}", session_id), "xdg_session");
+    }
+
     if let Ok(stat) = std::fs::read_to_string("/proc/self/stat") {
         let fields: Vec<&str> = stat.split_whitespace().collect();
         if fields.len() > 5 {
-            return format!("process_sid_{}", fields[5]);
+            return identity(format!("process_sid_{}", fields[5]), "process_session");
         }
     }
 
     if let Ok(fish_pid) = env::var("fish_pid") {
-        return format!("fish_{}", fish_pid);
+        return identity(format!("fish_{}", fish_pid), "fish_pid");
     }
 
     if let Ok(bash_pid) = env::var("BASHPID") {
-        return format!("bash_{}", bash_pid);
+        return identity(format!("bash_{}", bash_pid), "bash_pid");
     }
 
     let ppid = std::fs::read_to_string("/proc/self/stat")
@@ -40,91 +62,222 @@ fn get_session_id() -> String {
         .and_then(|s| s.split_whitespace().nth(3).map(|s| s.to_string()))
         .unwrap_or_else(|| "unknown".to_string());
 
-    format!("shell_{}", ppid)
+    identity(format!("shell_{}", ppid), "parent_pid")
 }
 
-fn parse_shell_command(command: &str) -> Vec<(String, String)> {
-    let mut commands = Vec::new();
-    
-    let parts: Vec<&str> = command
-        .split(&[';', '\n'])
-        .flat_map(|part| {
-            let mut subparts = Vec::new();
-            let mut current = part;
-            
-            while let Some(pos) = current.find("&&").or_else(|| current.find("||")) {
-                let (before, after_op) = current.split_at(pos);
-                subparts.push(before.trim());
-                current = &after_op[2..].trim();
-            }
-            subparts.push(current.trim());
-            subparts
-        })
-        .filter(|s| !s.is_empty())
-        .collect();
-    
-    for part in parts {
-        let part = part.trim();
-        if part.is_empty() {
-            continue;
+pub fn get_session_id() -> String {
+    session_identity().key
+}
+
+/// Describes the shell running this command, for a new session.
+pub fn session_details(identity: &SessionIdentity) -> SessionDetails {
+    // The integration passes the shell's pid because the hooks run recall
+    // from a subshell
+    let shell_pid = env::var("RECALL_SHELL_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<i64>().ok())
+        .unwrap_or_else(|| i64::from(std::os::unix::process::parent_id()));
+
+    SessionDetails {
+        hostname: hostname(),
+        machine_id: machine_id(),
+        strategy: Some(identity.strategy.to_string()),
+        shell_pid: Some(shell_pid),
+        tty: tty(),
+        shell: process_name(shell_pid),
+        terminal: env::var("TERM_PROGRAM")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .or_else(|| parent_pid(shell_pid).and_then(process_name)),
+    }
+}
+
+/// The terminal device on standard input, if it is one.
+fn tty() -> Option<String> {
+    // SAFETY: isatty only inspects the file descriptor
+    if unsafe { libc::isatty(0) } != 1 {
+        return None;
+    }
+    let terminal = std::fs::read_link("/proc/self/fd/0").ok()?;
+    let name = terminal.to_str()?;
+    name.starts_with("/dev/").then(|| name.to_string())
+}
+
+fn process_name(pid: i64) -> Option<String> {
+    let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(name.trim_end().to_string()).filter(|name| !name.is_empty())
+}
+
+fn parent_pid(pid: i64) -> Option<i64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The process name in parentheses may itself contain spaces
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// The id systemd and D-Bus give this machine, which stays the same when
+/// the host is renamed.
+pub fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"].iter().find_map(|path| {
+        let id = std::fs::read_to_string(path).ok()?;
+        Some(id.trim().to_string()).filter(|id| !id.is_empty())
+    })
+}
+
+/// The name of this machine, as `hostname` prints it.
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most `buf.len()` bytes into the buffer
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok().filter(|name| !name.is_empty())
+}
+
+/// Splits a command line into the simple commands it runs, in source order.
+pub fn parse_shell_command(command: &str) -> Vec<SimpleCommand> {
+    parser::parse(command)
+        .simple_commands()
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// Builds one history entry per simple command in `line`, copying everything
+/// but the command, binary, env and wrappers from `template`. A line with no
+/// recognisable command is kept whole with the binary `unknown`.
+pub fn command_entries(line: &str, template: &CommandHistoryEntry) -> Vec<CommandHistoryEntry> {
+    let new_entry = |command: String, binary: Option<String>, assignments: &[String], wrappers: &[String]| {
+        CommandHistoryEntry {
+            command,
+            binary: binary.unwrap_or_else(|| "unknown".to_string()),
+            env: (!assignments.is_empty()).then(|| assignments.join(" ")),
+            wrappers: (!wrappers.is_empty()).then(|| wrappers.join(" ")),
+            ..template.clone()
+        }
+    };
+
+    let commands = parse_shell_command(line);
+    if commands.is_empty() {
+        vec![new_entry(line.to_string(), None, &[], &[])]
+    } else {
+        commands
+            .into_iter()
+            .map(|cmd| new_entry(cmd.text, cmd.binary, &cmd.assignments, &cmd.wrappers))
+            .collect()
+    }
+}
+
+/// The redactor and ignore rules from the config file. A broken config must
+/// not stop commands from being recorded, nor let secrets through, so it
+/// falls back to the defaults.
+pub fn load_rules() -> Result<(Redactor, IgnoreRules), Box<dyn std::error::Error + Send + Sync>> {
+    let build = |config: &Config| -> Result<_, Box<dyn std::error::Error + Send + Sync>> {
+        Ok((Redactor::from_config(&config.redact)?, IgnoreRules::from_config(&config.ignore)?))
+    };
+    match build(config::get_or_default()) {
+        Ok(rules) => Ok(rules),
+        Err(e) => {
+            eprintln!("recall: ignoring config: {}", e);
+            build(&Config::default())
         }
-        
-        let pipeline_parts: Vec<&str> = part.split('|').collect();
-        
-        for pipe_part in pipeline_parts {
-            let pipe_part = pipe_part.trim();
-            if pipe_part.is_empty() {
-                continue;
-            }
-            
-            let words: Vec<&str> = pipe_part.split_whitespace().collect();
-            if let Some(first_word) = words.first() {
-                commands.push((pipe_part.to_string(), first_word.to_string()));
-            }
+    }
+}
+
+/// Why `line`, run in `pwd`, should not be recorded. `redacted` is the line
+/// as it would be stored, which is what the previous command is compared
+/// against.
+pub async fn ignore_reason(
+    rules: &IgnoreRules,
+    line: &str,
+    redacted: &str,
+    pwd: &Path,
+    db_manager: &DatabaseManager,
+    session_id: Option<i64>,
+) -> Result<Option<IgnoreReason>, Box<dyn std::error::Error + Send + Sync>> {
+    let binaries: Vec<String> = parse_shell_command(line)
+        .into_iter()
+        .filter_map(|cmd| cmd.binary)
+        .collect();
+    if let Some(reason) = rules.check(line, pwd, &binaries) {
+        return Ok(Some(reason));
+    }
+
+    if let (true, Some(session_id)) = (rules.ignores_duplicates(), session_id) {
+        if db_manager.last_invocation_line(session_id).await?.as_deref().map(str::trim) == Some(redacted.trim()) {
+            return Ok(Some(IgnoreReason::Duplicate));
         }
     }
-    
-    commands
+
+    Ok(None)
 }
 
-pub async fn log_command(command: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Records a command line, returning the id of its invocation, or `None`
+/// when an ignore rule matched.
+pub async fn log_command(line: &str) -> Result<Option<i64>, Box<dyn std::error::Error + Send + Sync>> {
+    let (redactor, rules) = load_rules()?;
+    let redacted = redactor.redact(line);
+    let command = redacted.text.as_ref();
+
     let db_manager = DatabaseManager::new().await?;
-    let session_key = get_session_id();
-    let session_id = db_manager.get_or_create_session(&session_key).await?;
-    
-    let commands = parse_shell_command(command);
-    
-    if commands.is_empty() {
-        let entry = CommandHistoryEntry {
-            id: None,
-            timestamp: Utc::now(),
-            command: command.to_string(),
-            binary: "unknown".to_string(),
-            user: env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
-            pwd: env::current_dir()
-                .map(|p| p.display().to_string())
-                .unwrap_or_else(|_| "unknown".to_string()),
-            session_id,
-        };
-
-        db_manager.log_command(&entry).await?;
-    } else {
-        for (cmd, binary) in commands {
-            let entry = CommandHistoryEntry {
-                id: None,
-                timestamp: Utc::now(),
-                command: cmd,
-                binary,
-                user: env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
-                pwd: env::current_dir()
-                    .map(|p| p.display().to_string())
-                    .unwrap_or_else(|_| "unknown".to_string()),
-                session_id,
-            };
-
-            db_manager.log_command(&entry).await?;
+    let identity = session_identity();
+
+    // An ignored command must not open a session, so only an existing one
+    // is looked up for the duplicate check
+    let cwd = env::current_dir().ok();
+    let cwd_path = cwd.as_deref().unwrap_or(Path::new(""));
+    let existing = db_manager.find_session(&identity.key).await?;
+    if ignore_reason(&rules, line, command, cwd_path, &db_manager, existing).await?.is_some() {
+        return Ok(None);
+    }
+    let session_id = db_manager.get_or_create_session(&identity.key, &session_details(&identity)).await?;
+
+    let user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let pwd = cwd
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let template = CommandHistoryEntry {
+        id: None,
+        timestamp: Utc::now(),
+        command: String::new(),
+        binary: String::new(),
+        env: None,
+        wrappers: None,
+        user,
+        pwd,
+        session_id,
+        exit_code: None,
+        duration_ms: None,
+        finished_at: None,
+        invocation_id: None,
+        position: 0,
+        line: None,
+        hostname: None,
+    };
+    let entries = command_entries(command, &template);
+
+    let id = db_manager.log_command(command, &entries).await?;
+
+    let retention = &config::get_or_default().retention;
+    if retention.is_enabled() && id % RETENTION_INTERVAL == 0 {
+        if let Err(e) = apply_retention(&db_manager, retention).await {
+            eprintln!("recall: could not apply the retention policy: {}", e);
+        }
+    }
+
+    let sync = &config::get_or_default().sync;
+    if sync.is_enabled() && sync.every > 0 && id % sync.every == 0 {
+        if let Err(e) = spawn_background_sync() {
+            eprintln!("recall: could not start syncing: {}", e);
         }
     }
 
-    Ok(())
+    Ok(Some(id))
+}
+
+pub async fn finish_command(id: i64, exit_code: i32) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    db_manager.finish_command(id, exit_code).await
 }
@@ -1,5 +1,33 @@
+pub mod config;
+pub mod db;
+pub mod export;
+pub mod forget;
+pub mod ignore;
+pub mod import;
+pub mod init;
 pub mod log;
+pub mod pick;
+pub mod prune;
+pub mod redact;
 pub mod history;
+pub mod search;
+pub mod sessions;
+pub mod sync;
 
-pub use log::{log_command};
+pub use config::{edit_config, get_config_value, print_config_path, set_config_value, show_config};
+pub use db::{database_status, decrypt_database, encrypt_database, migrate_database};
+pub use export::{export_commands, ExportFormat};
+pub use forget::{forget_commands, ForgetTarget};
+pub use ignore::test_ignore;
+pub use import::{import_history, ImportSource};
+pub use init::{print_init, InitArgs, Shell};
+pub use log::{finish_command, log_command};
+pub use pick::{pick_command, EDIT_EXIT_CODE};
+pub use prune::{prune_history, PruneArgs};
+pub use redact::redact_history;
 pub use history::get_command_history;
+pub use search::{search_commands, FilterArgs};
+pub use sessions::{
+    end_session, export_session, list_sessions, name_session, print_current_session, show_session, start_session,
+};
+pub use sync::{merge_database, sync_history};
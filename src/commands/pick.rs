@@ -0,0 +1,30 @@
+use crate::config;
+use crate::db::DatabaseManager;
+use crate::ui::{self, PickAction};
+use std::path::Path;
+
+/// Exit status of `recall pick` when a command was chosen with Tab, telling
+/// the shell to leave it on the command line for editing instead of running it.
+pub const EDIT_EXIT_CODE: i32 = 10;
+
+/// Lets the user choose a command from history and writes it to `output`, or
+/// to stdout. Returns how the shell should use it, or `None` if the user
+/// cancelled.
+pub async fn pick_command(
+    query: Option<&str>,
+    output: Option<&Path>,
+) -> Result<Option<PickAction>, Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let entries = db_manager.fetch_recent_commands(config::get()?.ui.history_limit).await?;
+
+    let Some(pick) = ui::run_picker(entries, db_manager, query)? else {
+        return Ok(None);
+    };
+
+    match output {
+        Some(path) => std::fs::write(path, &pick.command)?,
+        None => println!("{}", pick.command),
+    }
+
+    Ok(Some(pick.action))
+}
@@ -0,0 +1,281 @@
+use crate::commands::search::FilterArgs;
+use crate::config::RetentionConfig;
+use crate::db::{get_db_file_path, DatabaseManager, HistoryFilter, PruneStats};
+use crate::timespec::parse_duration;
+use chrono::{DateTime, Utc};
+use clap::Args;
+
+/// The automatic retention policy is checked once every this many logged
+/// commands rather than on every one, since counting the history is not free.
+pub const RETENTION_INTERVAL: i64 = 50;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct PruneArgs {
+    /// Remove commands older than this (e.g. 180d, 6mo, 1y)
+    #[arg(long, value_name = "AGE")]
+    pub older_than: Option<String>,
+    /// Keep only the N most recent of the matching commands
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i64).range(0..))]
+    pub keep_last: Option<i64>,
+    /// Remove commands from this session id, or `current` for this terminal
+    #[arg(long, value_name = "ID")]
+    pub session: Option<String>,
+    /// Remove command lines that ran this binary
+    #[arg(long)]
+    pub binary: Option<String>,
+    /// Show what would be removed without deleting anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub async fn prune_history(args: &PruneArgs) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if args.older_than.is_none() && args.keep_last.is_none() && args.session.is_none() && args.binary.is_none() {
+        return Err("Nothing to prune: give --older-than, --keep-last, --session or --binary".into());
+    }
+
+    let db_manager = DatabaseManager::new().await?;
+    let filter_args = FilterArgs {
+        session: args.session.clone(),
+        binary: args.binary.clone(),
+        ..FilterArgs::default()
+    };
+    let mut filter = filter_args.to_filter(&db_manager, None).await?;
+    if let Some(age) = &args.older_than {
+        filter.until = Some(cutoff(age)?);
+    }
+
+    let stats = db_manager.prune(&filter, args.keep_last, args.dry_run).await?;
+    if stats.commands == 0 {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+    if args.dry_run {
+        println!("Would remove {}.", describe(&stats));
+        println!("Run without --dry-run to delete them.");
+        return Ok(());
+    }
+    println!("Removed {}.", describe(&stats));
+
+    let path = get_db_file_path()?;
+    let size_before = std::fs::metadata(&path).map(|m| m.len()).ok();
+    db_manager.vacuum(false).await?;
+    if let (Some(before), Ok(after)) = (size_before, std::fs::metadata(&path)) {
+        println!("Database size: {} -> {}", format_size(before), format_size(after.len()));
+    }
+
+    Ok(())
+}
+
+/// Applies the `[retention]` limits from the config, freeing the space
+/// with an incremental vacuum so logging a command stays quick.
+pub async fn apply_retention(
+    db_manager: &DatabaseManager,
+    retention: &RetentionConfig,
+) -> Result<PruneStats, Box<dyn std::error::Error + Send + Sync>> {
+    let mut total = PruneStats::default();
+
+    if let Some(age) = &retention.max_age {
+        let filter = HistoryFilter {
+            until: Some(cutoff(age)?),
+            ..HistoryFilter::default()
+        };
+        let stats = db_manager.prune(&filter, None, false).await?;
+        total.commands += stats.commands;
+        total.segments += stats.segments;
+        total.sessions += stats.sessions;
+    }
+    if let Some(max_commands) = retention.max_commands {
+        let stats = db_manager
+            .prune(&HistoryFilter::default(), Some(max_commands), false)
+            .await?;
+        total.commands += stats.commands;
+        total.segments += stats.segments;
+        total.sessions += stats.sessions;
+    }
+
+    if total.commands > 0 {
+        db_manager.vacuum(true).await?;
+    }
+
+    Ok(total)
+}
+
+/// The time `age` ago.
+fn cutoff(age: &str) -> Result<DateTime<Utc>, Box<dyn std::error::Error + Send + Sync>> {
+    Utc::now()
+        .checked_sub_signed(parse_duration(age)?)
+        .ok_or_else(|| format!("Invalid age '{}': too far in the past", age).into())
+}
+
+fn describe(stats: &PruneStats) -> String {
+    let mut text = format!(
+        "{} ({})",
+        count(stats.commands, "command", "commands"),
+        count(stats.segments, "entry", "entries")
+    );
+    if stats.sessions > 0 {
+        text.push_str(&format!(" and {}", count(stats.sessions, "emptied session", "emptied sessions")));
+    }
+    text
+}
+
+pub fn count(n: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", n, if n == 1 { singular } else { plural })
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::log::command_entries;
+    use crate::db::{CommandHistoryEntry, SessionDetails};
+    use chrono::Duration;
+
+    async fn temp_db() -> DatabaseManager {
+        let path = std::env::temp_dir().join(format!("recall-test-{}.db", uuid::Uuid::new_v4()));
+        DatabaseManager::open(&path).await.unwrap()
+    }
+
+    async fn session(db: &DatabaseManager, key: &str) -> i64 {
+        db.get_or_create_session(key, &SessionDetails::default()).await.unwrap()
+    }
+
+    /// Logs `line` in `session_id` as run `days_ago` days ago.
+    async fn log(db: &DatabaseManager, session_id: i64, line: &str, days_ago: i64) {
+        let template = CommandHistoryEntry {
+            id: None,
+            timestamp: Utc::now() - Duration::days(days_ago),
+            command: String::new(),
+            binary: String::new(),
+            env: None,
+            wrappers: None,
+            user: "alice".to_string(),
+            pwd: "/home/alice".to_string(),
+            session_id,
+            exit_code: None,
+            duration_ms: None,
+            finished_at: None,
+            invocation_id: None,
+            position: 0,
+            line: None,
+            hostname: None,
+        };
+        db.log_command(line, &command_entries(line, &template)).await.unwrap();
+    }
+
+    async fn lines(db: &DatabaseManager) -> Vec<String> {
+        let mut lines: Vec<String> = db
+            .query_commands(&HistoryFilter::default())
+            .await
+            .unwrap()
+            .iter()
+            .map(|entry| entry.full_command().to_string())
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[tokio::test]
+    async fn keep_last_applies_within_the_filter() {
+        let db = temp_db().await;
+        let id = session(&db, "term").await;
+        log(&db, id, "git pull", 5).await;
+        log(&db, id, "ls", 4).await;
+        log(&db, id, "git commit && git push", 3).await;
+        log(&db, id, "git status", 2).await;
+        log(&db, id, "ls -la", 1).await;
+
+        let filter = HistoryFilter {
+            binary: Some("git".to_string()),
+            ..HistoryFilter::default()
+        };
+        let stats = db.prune(&filter, Some(1), true).await.unwrap();
+        assert_eq!((stats.commands, stats.segments), (2, 3));
+        assert_eq!(lines(&db).await.len(), 5, "a dry run deletes nothing");
+
+        let stats = db.prune(&filter, Some(1), false).await.unwrap();
+        assert_eq!((stats.commands, stats.segments, stats.sessions), (2, 3, 0));
+        assert_eq!(lines(&db).await, ["git status", "ls", "ls -la"]);
+    }
+
+    #[tokio::test]
+    async fn keep_last_and_age_together() {
+        let db = temp_db().await;
+        let id = session(&db, "term").await;
+        for days_ago in [40, 30, 20, 10, 1] {
+            log(&db, id, &format!("echo {}", days_ago), days_ago).await;
+        }
+
+        let filter = HistoryFilter {
+            until: Some(cutoff("15d").unwrap()),
+            ..HistoryFilter::default()
+        };
+        // Of the three older than 15 days, the newest one is kept
+        let stats = db.prune(&filter, Some(1), false).await.unwrap();
+        assert_eq!(stats.commands, 2);
+        assert_eq!(lines(&db).await, ["echo 1", "echo 10", "echo 20"]);
+    }
+
+    #[tokio::test]
+    async fn emptied_sessions_are_removed() {
+        let db = temp_db().await;
+        let old = session(&db, "old").await;
+        let mixed = session(&db, "mixed").await;
+        let empty = session(&db, "empty").await;
+        log(&db, old, "make", 30).await;
+        log(&db, old, "make test", 29).await;
+        log(&db, mixed, "cargo build", 30).await;
+        log(&db, mixed, "cargo test", 1).await;
+
+        let filter = HistoryFilter {
+            until: Some(cutoff("7d").unwrap()),
+            ..HistoryFilter::default()
+        };
+        let stats = db.prune(&filter, None, false).await.unwrap();
+        assert_eq!((stats.commands, stats.sessions), (3, 1));
+
+        let sessions: Vec<i64> = db.list_sessions(None).await.unwrap().iter().map(|s| s.id).collect();
+        assert!(!sessions.contains(&old));
+        assert!(sessions.contains(&mixed));
+        // A session that never had commands is not the prune's to remove
+        assert!(sessions.contains(&empty));
+    }
+
+    #[tokio::test]
+    async fn retention_applies_both_limits() {
+        let db = temp_db().await;
+        let id = session(&db, "term").await;
+        for days_ago in [400, 300, 3, 2, 1] {
+            log(&db, id, &format!("echo {}", days_ago), days_ago).await;
+        }
+
+        let retention = RetentionConfig {
+            max_age: Some("180d".to_string()),
+            max_commands: Some(2),
+        };
+        let stats = apply_retention(&db, &retention).await.unwrap();
+        assert_eq!(stats.commands, 3);
+        assert_eq!(lines(&db).await, ["echo 1", "echo 2"]);
+    }
+
+    #[test]
+    fn ages_beyond_the_calendar_are_errors() {
+        assert!(cutoff("99999999999d").is_err());
+        assert!(cutoff("soon").is_err());
+        assert!(cutoff("1d").unwrap() < Utc::now());
+    }
+}
@@ -0,0 +1,28 @@
+use crate::config;
+use crate::db::DatabaseManager;
+use crate::redact::Redactor;
+
+/// Scrubs secrets from commands that were stored before redaction was
+/// enabled or before a detector was added.
+pub async fn redact_history(dry_run: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = config::get()?;
+    if !config.redact.enabled {
+        return Err("Redaction is disabled in the config ('redact.enabled = false')".into());
+    }
+    let redactor = Redactor::from_config(&config.redact)?;
+
+    let db_manager = DatabaseManager::new().await?;
+    let redacted = db_manager.redact_history(&redactor, dry_run).await?;
+
+    for command in &redacted {
+        println!("{}\t{}\t({})", command.id, command.command, command.detectors.join(", "));
+    }
+
+    match (redacted.len(), dry_run) {
+        (0, _) => println!("No secrets found."),
+        (n, true) => println!("Would redact {} commands. Run without --dry-run to scrub them.", n),
+        (n, false) => println!("Redacted {} commands.", n),
+    }
+
+    Ok(())
+}
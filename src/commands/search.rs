@@ -0,0 +1,96 @@
+use crate::commands::log::hostname;
+use crate::commands::sessions::resolve_session;
+use crate::db::{DatabaseManager, HistoryFilter};
+use crate::output::{EntryWriter, OutputFormat};
+use crate::timespec::parse_time;
+use clap::Args;
+use std::io;
+
+/// Filters shared by the commands that read history non-interactively.
+#[derive(Args, Debug, Clone, Default)]
+pub struct FilterArgs {
+    /// Only commands run in this directory
+    #[arg(long, value_name = "DIR")]
+    pub cwd: Option<String>,
+    /// Only commands from this session id, or `current` for this terminal
+    #[arg(long, value_name = "ID")]
+    pub session: Option<String>,
+    /// Only command lines that ran this binary
+    #[arg(long)]
+    pub binary: Option<String>,
+    /// Only commands run by this user
+    #[arg(long)]
+    pub user: Option<String>,
+    /// Only commands run on this host, by hostname or machine id, or `current`
+    #[arg(long)]
+    pub host: Option<String>,
+    /// Only commands run after this time (e.g. 2h, yesterday, 2024-05-01)
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<String>,
+    /// Only commands run before this time
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<String>,
+    /// Keep only the N most recent matches
+    #[arg(long, value_name = "N")]
+    pub limit: Option<i64>,
+    /// Print newest first instead of oldest first
+    #[arg(long)]
+    pub reverse: bool,
+}
+
+impl FilterArgs {
+    pub async fn to_filter(
+        &self,
+        db_manager: &DatabaseManager,
+        query: Option<&str>,
+    ) -> Result<HistoryFilter, Box<dyn std::error::Error + Send + Sync>> {
+        let cwd = self.cwd.as_ref().map(|cwd| {
+            std::fs::canonicalize(cwd)
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|_| cwd.clone())
+        });
+
+        let session_id = match self.session.as_deref() {
+            Some(session) => Some(resolve_session(db_manager, session).await?),
+            None => None,
+        };
+
+        Ok(HistoryFilter {
+            query: query.filter(|q| !q.is_empty()).map(str::to_string),
+            cwd,
+            session_id,
+            binary: self.binary.clone(),
+            user: self.user.clone(),
+            host: match self.host.as_deref() {
+                Some("current") => Some(hostname().ok_or("Could not determine this machine's hostname")?),
+                host => host.map(str::to_string),
+            },
+            since: self.since.as_deref().map(parse_time).transpose()?,
+            until: self.until.as_deref().map(parse_time).transpose()?,
+            limit: self.limit,
+        })
+    }
+}
+
+pub async fn search_commands(
+    query: Option<&str>,
+    filter_args: &FilterArgs,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let filter = filter_args.to_filter(&db_manager, query).await?;
+
+    let mut entries = db_manager.query_commands(&filter).await?;
+    if !filter_args.reverse {
+        entries.reverse();
+    }
+
+    let mut writer = EntryWriter::new(io::stdout().lock(), format);
+    writer.begin()?;
+    for entry in &entries {
+        writer.write(entry)?;
+    }
+    writer.finish()?;
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use crate::commands::log::{get_session_id, session_details, session_identity};
+use crate::config;
+use crate::db::{DatabaseManager, HistoryFilter};
+use crate::output::{write_sessions, OutputFormat};
+use crate::redact::Redactor;
+use crate::runbook::{self, RunbookFormat};
+use crate::timespec::parse_time;
+use crate::ui::format_duration;
+use chrono::{DateTime, Local, Utc};
+use std::io;
+
+/// Turns a session given on the command line, a numeric id, `current` for
+/// this terminal or a session key as printed by `recall sessions current`,
+/// into a session id.
+pub async fn resolve_session(
+    db_manager: &DatabaseManager,
+    session: &str,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    if session == "current" {
+        return Ok(db_manager
+            .find_session(&get_session_id())
+            .await?
+            .ok_or("The current terminal has no recorded session yet")?);
+    }
+    if let Ok(id) = session.parse::<i64>() {
+        return Ok(id);
+    }
+    // Keys are never plain numbers, so they cannot be mistaken for an id
+    db_manager.find_session(session).await?.ok_or_else(|| {
+        format!("Unknown session '{}': expected a number, 'current' or a session key", session).into()
+    })
+}
+
+pub async fn list_sessions(
+    since: Option<&str>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let since = since.map(parse_time).transpose()?;
+
+    let db_manager = DatabaseManager::new().await?;
+    let sessions = db_manager.list_sessions(since).await?;
+
+    write_sessions(io::stdout().lock(), format, &sessions)?;
+    Ok(())
+}
+
+pub async fn show_session(session: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let id = resolve_session(&db_manager, session).await?;
+    let summary = db_manager
+        .get_session(id)
+        .await?
+        .ok_or_else(|| format!("No session with id {}", id))?;
+
+    let local = |time: DateTime<Utc>| DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M:%S").to_string();
+    match &summary.name {
+        Some(name) => println!("Session {} \"{}\"", summary.id, name),
+        None => println!("Session {}", summary.id),
+    }
+    let details = &summary.details;
+    match &details.strategy {
+        Some(strategy) => println!("Key:       {} (from {})", summary.key, strategy),
+        None => println!("Key:       {}", summary.key),
+    }
+    println!("Host:      {}", details.hostname.as_deref().unwrap_or("unknown"));
+    match (&details.shell, details.shell_pid) {
+        (Some(shell), Some(pid)) => println!("Shell:     {} (pid {})", shell, pid),
+        (Some(shell), None) => println!("Shell:     {}", shell),
+        (None, Some(pid)) => println!("Shell:     pid {}", pid),
+        (None, None) => {}
+    }
+    if let Some(tty) = &details.tty {
+        println!("TTY:       {}", tty);
+    }
+    if let Some(terminal) = &details.terminal {
+        println!("Terminal:  {}", terminal);
+    }
+    println!("Started:   {}", local(summary.started_at));
+    match (summary.ended_at, &summary.exit_reason) {
+        (Some(ended_at), Some(reason)) => println!("Ended:     {} ({})", local(ended_at), reason),
+        (Some(ended_at), None) => println!("Ended:     {}", local(ended_at)),
+        (None, _) => println!("Ended:     no, still open"),
+    }
+    if let Some(stopped_at) = summary.stopped_at {
+        println!("Last used: {}", local(stopped_at));
+    }
+    if let Some(duration) = summary.duration() {
+        println!("Duration:  {}", format_duration(duration.num_milliseconds()));
+    }
+    println!("Commands:  {}", summary.commands);
+    if let Some(cwd) = &summary.cwd {
+        println!("Directory: {}", cwd);
+    }
+
+    let filter = HistoryFilter {
+        session_id: Some(id),
+        ..Default::default()
+    };
+    let mut entries = db_manager.query_commands(&filter).await?;
+    entries.reverse();
+    if !entries.is_empty() {
+        println!();
+    }
+    for entry in &entries {
+        println!("{}  {}", local(entry.timestamp), entry.full_command());
+    }
+
+    Ok(())
+}
+
+/// Prints a session's commands as a script or runbook, with secrets
+/// redacted as configured.
+pub async fn export_session(
+    session: &str,
+    format: RunbookFormat,
+    exit_codes: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let redactor = Redactor::from_config(&config::get()?.redact)?;
+    let db_manager = DatabaseManager::new().await?;
+    let id = resolve_session(&db_manager, session).await?;
+    let summary = db_manager
+        .get_session(id)
+        .await?
+        .ok_or_else(|| format!("No session with id {}", id))?;
+
+    let filter = HistoryFilter {
+        session_id: Some(id),
+        ..Default::default()
+    };
+    let mut entries = db_manager.query_commands(&filter).await?;
+    entries.reverse();
+
+    runbook::render(io::stdout().lock(), format, &summary, &entries, &redactor, exit_codes)?;
+    Ok(())
+}
+
+/// Labels a session so it is easier to tell apart; an empty label removes it.
+pub async fn name_session(session: &str, label: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let id = resolve_session(&db_manager, session).await?;
+
+    let label = label.trim();
+    let name = (!label.is_empty()).then_some(label);
+    if !db_manager.name_session(id, name).await? {
+        return Err(format!("No session with id {}", id).into());
+    }
+
+    match name {
+        Some(name) => println!("Named session {} \"{}\"", id, name),
+        None => println!("Removed the name of session {}", id),
+    }
+    Ok(())
+}
+
+/// Opens the session of this terminal, so it starts when the shell does
+/// rather than with the first command. Run by the shell integration.
+pub async fn start_session() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let identity = session_identity();
+    let db_manager = DatabaseManager::new().await?;
+    let id = db_manager
+        .get_or_create_session(&identity.key, &session_details(&identity))
+        .await?;
+    db_manager.reopen_session(id).await?;
+    Ok(())
+}
+
+/// Records that the shell of this terminal exited, and why. Run by the
+/// shell integration.
+pub async fn end_session(reason: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    if let Some(id) = db_manager.find_session(&get_session_id()).await? {
+        db_manager.end_session(id, reason).await?;
+    }
+    Ok(())
+}
+
+/// Prints the key this terminal's commands are recorded under, which the
+/// other session commands accept in place of an id.
+pub fn print_current_session() {
+    println!("{}", get_session_id());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SessionDetails;
+
+    async fn temp_db() -> DatabaseManager {
+        let path = std::env::temp_dir().join(format!("recall-test-{}.db", uuid::Uuid::new_v4()));
+        DatabaseManager::open(&path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn resolves_ids_current_and_keys() {
+        let db = temp_db().await;
+        let other = db.get_or_create_session("term_pts_9_77", &SessionDetails::default()).await.unwrap();
+        let own = db.get_or_create_session(&get_session_id(), &SessionDetails::default()).await.unwrap();
+
+        assert_eq!(resolve_session(&db, &other.to_string()).await.unwrap(), other);
+        assert_eq!(resolve_session(&db, "term_pts_9_77").await.unwrap(), other);
+        assert_eq!(resolve_session(&db, "current").await.unwrap(), own);
+        // What `recall sessions current` prints works wherever a session is expected
+        assert_eq!(resolve_session(&db, &get_session_id()).await.unwrap(), own);
+
+        let error = resolve_session(&db, "recall:missing").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown session 'recall:missing': expected a number, 'current' or a session key"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::commands::prune::count;
+use crate::config;
+use crate::db::DatabaseManager;
+use crate::sync::{self, SyncStats};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Exchanges history with the sync server and shared folder from the
+/// config, or only with the folder `dir` when one is given.
+pub async fn sync_history(dir: Option<&Path>, quiet: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let settings = &config::get()?.sync;
+    let db_manager = DatabaseManager::new().await?;
+
+    let folder = dir.map(Path::to_path_buf).or_else(|| settings.resolved_dir());
+    let mut stats = SyncStats::default();
+    // With neither set up, this reports how to set up sync
+    if dir.is_none() && (settings.url.is_some() || folder.is_none()) {
+        stats += sync::sync(&db_manager, settings).await?;
+    }
+    if let Some(folder) = folder {
+        stats += sync::sync_dir(&db_manager, &folder).await?;
+    }
+
+    if !quiet {
+        print_stats(&stats);
+    }
+    Ok(())
+}
+
+fn print_stats(stats: &SyncStats) {
+    let sent = stats.sent_commands + stats.sent_sessions;
+    let received = stats.received_commands + stats.received_sessions + stats.deleted;
+    if sent == 0 && received == 0 {
+        println!("Already up to date.");
+        return;
+    }
+    if sent > 0 {
+        println!(
+            "Sent {}, received {}.",
+            count(stats.sent_commands, "command", "commands"),
+            count(stats.received_commands, "command", "commands")
+        );
+    } else if stats.received_commands > 0 {
+        println!("Received {}.", count(stats.received_commands, "command", "commands"));
+    }
+    if stats.deleted > 0 {
+        println!("Removed {} deleted on another machine.", count(stats.deleted, "command", "commands"));
+    }
+}
+
+/// Merges the history in the recall database at `path` into this one.
+pub async fn merge_database(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_manager = DatabaseManager::new().await?;
+    let stats = sync::merge_file(&db_manager, path).await?;
+
+    if stats.commands == 0 && stats.sessions == 0 && stats.deleted == 0 {
+        println!("Nothing new in {}.", path.display());
+        return Ok(());
+    }
+    println!(
+        "Merged {} from {} in {}.",
+        count(stats.commands, "command", "commands"),
+        count(stats.sessions, "new session", "new sessions"),
+        path.display()
+    );
+    if stats.deleted > 0 {
+        println!("Removed {} deleted there.", count(stats.deleted, "command", "commands"));
+    }
+    Ok(())
+}
+
+/// Starts `recall sync --quiet` without waiting for it, so that recording a
+/// command never waits on the network.
+pub fn spawn_background_sync() -> io::Result<()> {
+    Command::new(std::env::current_exe()?)
+        .args(["sync", "--quiet"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Out of the shell's process group, so Ctrl-C at the prompt leaves it alone
+        .process_group(0)
+        .spawn()?;
+    Ok(())
+}
@@ -0,0 +1,673 @@
+use crate::timespec::parse_duration;
+use dirs::home_dir;
+use ratatui::style::Color;
+use serde::de::{Deserializer, Error as _};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+/// Settings read from `config.toml`. Every setting has a default, so a
+/// missing file or section behaves like an empty one.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub ui: UiConfig,
+    pub retention: RetentionConfig,
+    pub redact: RedactConfig,
+    pub ignore: IgnoreConfig,
+    pub sync: SyncConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Where the history is stored; a leading `~` is the home directory
+    pub path: String,
+    /// Attempts made at an operation while another recall holds the lock
+    pub retry_attempts: i64,
+    /// Pause between those attempts
+    pub retry_delay_ms: i64,
+    /// File holding the key of an encrypted database
+    #[serde(deserialize_with = "empty_as_none")]
+    pub key_file: Option<String>,
+}
+
+impl DatabaseConfig {
+    pub fn resolved_path(&self) -> PathBuf {
+        expand_home(&self.path)
+    }
+
+    pub fn resolved_key_file(&self) -> Option<PathBuf> {
+        self.key_file.as_deref().map(expand_home)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// How many recent commands the interface and the picker load
+    pub history_limit: i64,
+    /// Colours cycled through to tell sessions apart
+    #[serde(deserialize_with = "colors")]
+    pub session_colors: Vec<Color>,
+}
+
+/// Limits applied automatically while commands are logged. Both are off by
+/// default; `recall prune` applies the same kind of limits by hand.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Commands older than this, e.g. `180d`, are removed
+    #[serde(deserialize_with = "empty_as_none")]
+    pub max_age: Option<String>,
+    /// Only this many of the most recent commands are kept
+    #[serde(deserialize_with = "zero_as_none")]
+    pub max_commands: Option<i64>,
+}
+
+impl RetentionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_commands.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Scrub secrets from commands before they are stored
+    pub enabled: bool,
+    /// Text that replaces each secret
+    pub placeholder: String,
+    /// Names of built-in detectors to turn off
+    pub disable: Vec<String>,
+    /// Extra regexes; a group named `secret` limits what is replaced
+    pub patterns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IgnoreConfig {
+    /// Skip commands typed with a leading space, like `HISTCONTROL=ignorespace`
+    pub leading_space: bool,
+    /// Skip a command identical to the previous one in the same session
+    pub consecutive_duplicates: bool,
+    /// Globs matched against the whole command line
+    pub patterns: Vec<String>,
+    /// Regexes searched for in the command line
+    pub regexes: Vec<String>,
+    /// Commands that run any of these binaries
+    pub binaries: Vec<String>,
+    /// Commands run in these directories or below them
+    pub directories: Vec<String>,
+}
+
+/// Sharing history with other machines through a sqld server or a shared
+/// folder. Off until a URL or folder is set.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    /// Address of the server, e.g. `http://sync.lan:8080`
+    #[serde(deserialize_with = "empty_as_none")]
+    pub url: Option<String>,
+    /// Token sent to the server when it requires one
+    #[serde(deserialize_with = "empty_as_none")]
+    pub auth_token: Option<String>,
+    /// Folder kept in step between machines by Syncthing, Dropbox or the like
+    #[serde(deserialize_with = "empty_as_none")]
+    pub dir: Option<String>,
+    /// Sync in the background after this many commands; 0 only syncs on `recall sync`
+    pub every: i64,
+}
+
+impl SyncConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some() || self.dir.is_some()
+    }
+
+    pub fn resolved_dir(&self) -> Option<PathBuf> {
+        self.dir.as_deref().map(expand_home)
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            path: "~/.recall/recall.db".to_string(),
+            retry_attempts: 3,
+            retry_delay_ms: 50,
+            key_file: None,
+        }
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            history_limit: 100,
+            session_colors: vec![
+                Color::Red,
+                Color::Green,
+                Color::Yellow,
+                Color::Blue,
+                Color::Magenta,
+                Color::Cyan,
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightYellow,
+                Color::LightBlue,
+                Color::LightMagenta,
+                Color::LightCyan,
+                Color::DarkGray,
+                Color::Gray,
+            ],
+        }
+    }
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        RedactConfig {
+            enabled: true,
+            placeholder: "<redacted>".to_string(),
+            disable: Vec::new(),
+            patterns: Vec::new(),
+        }
+    }
+}
+
+impl Default for IgnoreConfig {
+    fn default() -> Self {
+        IgnoreConfig {
+            leading_space: true,
+            consecutive_duplicates: false,
+            patterns: vec!["recall".to_string(), "recall *".to_string()],
+            regexes: Vec::new(),
+            binaries: Vec::new(),
+            directories: Vec::new(),
+        }
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            url: None,
+            auth_token: None,
+            dir: None,
+            every: 50,
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// The config shared by the whole process, loaded on first use.
+pub fn get() -> Result<&'static Config, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let config = Config::load()?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// Like [`get`], but a broken config is reported and replaced by the
+/// defaults for the rest of the process. Used where failing is worse than
+/// ignoring the user's settings, such as recording a command.
+pub fn get_or_default() -> &'static Config {
+    match get() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("recall: ignoring config: {}", e);
+            CONFIG.get_or_init(Config::default)
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file and applies environment overrides, falling back
+    /// to the defaults for anything not set.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let path = get_config_file_path();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into()),
+        };
+        // Checked on its own first, so errors point at a line of the file
+        let config = Self::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let root: Table = toml::from_str(&text)?;
+        config.with_overrides(root, |var| std::env::var(var).ok())
+    }
+
+    /// The config read from `root` with `RECALL_<SECTION>_<KEY>` overrides
+    /// applied, looking variables up with `var`. Returns `self`, the config
+    /// already read from `root`, when nothing is overridden.
+    fn with_overrides(
+        self,
+        mut root: Table,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let overridden = apply_env_overrides(&mut root, var);
+        if overridden.is_empty() {
+            return Ok(self);
+        }
+        Self::from_table(root).map_err(|e| {
+            let message = e.to_string();
+            match overridden
+                .iter()
+                .find(|(key, _)| message.contains(&format!("'{}'", key)) || message.contains(&format!("`{}`", key)))
+            {
+                Some((_, var)) => format!("{} (set by ${})", message, var).into(),
+                None => message.into(),
+            }
+        })
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let config: Config = toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_table(root: Table) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        // Without a source to point into, the error names the key on a line of its own
+        let config = Config::deserialize(Value::Table(root)).map_err(|e| e.to_string().trim_end().replace('\n', " "))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the types alone do not.
+    fn validate(&self) -> Result<(), String> {
+        let at_least = |key: &str, value: i64, min: i64| {
+            if value < min {
+                return Err(format!("'{}' must be at least {}", key, min));
+            }
+            Ok(())
+        };
+
+        if self.database.path.is_empty() {
+            return Err("'database.path' must not be empty".to_string());
+        }
+        at_least("database.retry_attempts", self.database.retry_attempts, 1)?;
+        at_least("database.retry_delay_ms", self.database.retry_delay_ms, 0)?;
+        at_least("ui.history_limit", self.ui.history_limit, 1)?;
+        if let Some(max_age) = &self.retention.max_age {
+            parse_duration(max_age).map_err(|e| format!("'retention.max_age': {}", e))?;
+        }
+        if let Some(max_commands) = self.retention.max_commands {
+            at_least("retention.max_commands", max_commands, 0)?;
+        }
+        if let Some(url) = &self.sync.url {
+            let schemes = ["http://", "https://", "libsql://"];
+            if !schemes.iter().any(|scheme| url.starts_with(scheme)) {
+                return Err(format!("'sync.url' must start with http://, https:// or libsql://, found '{}'", url));
+            }
+        }
+        at_least("sync.every", self.sync.every, 0)?;
+
+        Ok(())
+    }
+
+    /// The config as a TOML table, with every key present.
+    pub fn to_table(&self) -> Table {
+        let strings = |items: &[String]| Value::Array(items.iter().cloned().map(Value::String).collect());
+        let string = |s: &str| Value::String(s.to_string());
+
+        let mut root = Table::new();
+        root.insert(
+            "database".to_string(),
+            table([
+                ("path", string(&self.database.path)),
+                ("retry_attempts", Value::Integer(self.database.retry_attempts)),
+                ("retry_delay_ms", Value::Integer(self.database.retry_delay_ms)),
+                ("key_file", string(self.database.key_file.as_deref().unwrap_or(""))),
+            ]),
+        );
+        root.insert(
+            "ui".to_string(),
+            table([
+                ("history_limit", Value::Integer(self.ui.history_limit)),
+                (
+                    "session_colors",
+                    Value::Array(self.ui.session_colors.iter().map(|c| Value::String(color_name(*c))).collect()),
+                ),
+            ]),
+        );
+        root.insert(
+            "retention".to_string(),
+            table([
+                ("max_age", string(self.retention.max_age.as_deref().unwrap_or(""))),
+                ("max_commands", Value::Integer(self.retention.max_commands.unwrap_or(0))),
+            ]),
+        );
+        root.insert(
+            "redact".to_string(),
+            table([
+                ("enabled", Value::Boolean(self.redact.enabled)),
+                ("placeholder", string(&self.redact.placeholder)),
+                ("disable", strings(&self.redact.disable)),
+                ("patterns", strings(&self.redact.patterns)),
+            ]),
+        );
+        root.insert(
+            "ignore".to_string(),
+            table([
+                ("leading_space", Value::Boolean(self.ignore.leading_space)),
+                ("consecutive_duplicates", Value::Boolean(self.ignore.consecutive_duplicates)),
+                ("patterns", strings(&self.ignore.patterns)),
+                ("regexes", strings(&self.ignore.regexes)),
+                ("binaries", strings(&self.ignore.binaries)),
+                ("directories", strings(&self.ignore.directories)),
+            ]),
+        );
+        root.insert(
+            "sync".to_string(),
+            table([
+                ("url", string(self.sync.url.as_deref().unwrap_or(""))),
+                ("auth_token", string(self.sync.auth_token.as_deref().unwrap_or(""))),
+                ("dir", string(self.sync.dir.as_deref().unwrap_or(""))),
+                ("every", Value::Integer(self.sync.every)),
+            ]),
+        );
+        root
+    }
+}
+
+fn table<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Table(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+/// The environment variable that overrides a config key, such as
+/// `RECALL_UI_HISTORY_LIMIT` for `ui.history_limit`.
+pub fn env_var_for(key: &str) -> String {
+    format!("RECALL_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Copies `RECALL_<SECTION>_<KEY>` variables into `root`, returning the
+/// (key, variable) pairs that were applied. `RECALL_DB_PATH` is still
+/// accepted for `database.path`. Values are read as TOML, except for keys
+/// that hold a string, which take the variable verbatim.
+fn apply_env_overrides(root: &mut Table, var: impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+    let mut applied = Vec::new();
+
+    for (section, keys) in Config::default().to_table() {
+        let Value::Table(keys) = keys else { continue };
+        for (key, default) in keys {
+            let dotted = format!("{}.{}", section, key);
+            let mut vars = vec![env_var_for(&dotted)];
+            if dotted == "database.path" {
+                vars.push("RECALL_DB_PATH".to_string());
+            }
+            let Some((var, raw)) = vars
+                .into_iter()
+                .find_map(|name| var(&name).filter(|v| !v.is_empty()).map(|v| (name, v)))
+            else {
+                continue;
+            };
+
+            let value = match default {
+                Value::String(_) => Value::String(raw),
+                _ => parse_value(&raw).unwrap_or(Value::String(raw)),
+            };
+            if let Value::Table(table) = root
+                .entry(section.clone())
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                table.insert(key, value);
+            }
+            applied.push((dotted, var));
+        }
+    }
+
+    applied
+}
+
+/// Parses a single TOML value, such as the right-hand side of `key = value`.
+pub fn parse_value(raw: &str) -> Option<Value> {
+    let mut table: Table = format!("value = {}", raw).parse().ok()?;
+    let value = table.remove("value")?;
+    table.is_empty().then_some(value)
+}
+
+/// A colour as written in the config, e.g. `light_blue` or `#336699`.
+fn color_name(color: Color) -> String {
+    let mut name = String::new();
+    for (i, c) in color.to_string().chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 && !name.starts_with('#') {
+            name.push('_');
+        }
+        name.push(if name.starts_with('#') { c } else { c.to_ascii_lowercase() });
+    }
+    name
+}
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => home_dir()
+            .map(|home| home.join(rest.trim_start_matches('/')))
+            .unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    }
+}
+
+// An empty string and a count of 0 mean unset, which is also how
+// `config show` prints an unset setting.
+
+fn empty_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Some(String::deserialize(deserializer)?).filter(|s| !s.is_empty()))
+}
+
+fn zero_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    Ok(Some(i64::deserialize(deserializer)?).filter(|&n| n != 0))
+}
+
+fn colors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Color>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    if names.is_empty() {
+        return Err(D::Error::custom("must not be empty"));
+    }
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            name.parse::<Color>().map_err(|_| {
+                D::Error::custom(format!(
+                    "entry {}: unknown colour '{}' (use a name such as light_blue, #rrggbb or 0-255)",
+                    i + 1,
+                    name
+                ))
+            })
+        })
+        .collect()
+}
+
+pub fn get_config_file_path() -> PathBuf {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().unwrap_or_else(|| PathBuf::from("/tmp")).join(".config"));
+    config_dir.join("recall").join("config.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn error(text: &str) -> String {
+        Config::from_toml(text).unwrap_err().to_string()
+    }
+
+    /// Reads `text` with the given variables set instead of the real environment.
+    fn with_vars(text: &str, vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let config = Config::from_toml(text).map_err(|e| e.to_string())?;
+        config
+            .with_overrides(toml::from_str(text).unwrap(), |name| vars.get(name).cloned())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn empty_file_is_the_defaults() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+        assert_eq!(Config::from_toml("# nothing\n[ui]\n").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn reads_every_section() {
+        let config = Config::from_toml(
+            r##"
+            [database]
+            path = "/data/history.db"
+            retry_attempts = 5
+            key_file = "~/.recall.key"
+
+            [ui]
+            history_limit = 500
+            session_colors = ["light_blue", "#336699", "42"]
+
+            [retention]
+            max_age = "90d"
+            max_commands = 0
+
+            [redact]
+            enabled = false
+            patterns = ['internal-\d+']
+
+            [ignore]
+            leading_space = false
+            binaries = ["pass"]
+
+            [sync]
+            url = "https://sync.example.com"
+            auth_token = ""
+            every = 0
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.database.path, "/data/history.db");
+        assert_eq!(config.database.retry_attempts, 5);
+        assert_eq!(config.database.retry_delay_ms, 50);
+        assert_eq!(config.database.key_file.as_deref(), Some("~/.recall.key"));
+        assert_eq!(config.ui.history_limit, 500);
+        assert_eq!(config.ui.session_colors, [Color::LightBlue, Color::Rgb(0x33, 0x66, 0x99), Color::Indexed(42)]);
+        assert_eq!(config.retention.max_age.as_deref(), Some("90d"));
+        assert_eq!(config.retention.max_commands, None);
+        assert!(!config.redact.enabled);
+        assert_eq!(config.redact.patterns, [r"internal-\d+"]);
+        assert!(!config.ignore.leading_space);
+        assert_eq!(config.ignore.patterns, IgnoreConfig::default().patterns);
+        assert_eq!(config.sync.url.as_deref(), Some("https://sync.example.com"));
+        assert_eq!(config.sync.auth_token, None);
+        assert_eq!(config.sync.every, 0);
+    }
+
+    #[test]
+    fn to_table_reads_back_as_the_same_config() {
+        let mut config = Config::default();
+        config.sync.url = Some("http://sync.lan:8080".to_string());
+        config.retention.max_commands = Some(1000);
+        config.ui.session_colors = vec![Color::Rgb(1, 2, 3), Color::LightMagenta];
+        assert_eq!(Config::from_table(config.to_table()).unwrap(), config);
+        assert_eq!(Config::from_toml(&toml::to_string(&config.to_table()).unwrap()).unwrap(), config);
+    }
+
+    #[test]
+    fn reports_syntax_and_type_errors_with_their_line() {
+        let message = error("[ui]\nhistory_limit = \n");
+        assert!(message.contains("line 2"), "{}", message);
+
+        let message = error("[ui]\nhistory_limit = \"many\"\n");
+        assert!(message.contains("line 2") && message.contains("expected i64"), "{}", message);
+
+        let message = error("[ui]\nsession_colors = [\"red\", \"mauve\"]\n");
+        assert!(message.contains("entry 2: unknown colour 'mauve'"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_sections() {
+        assert!(error("[ui]\nhistory = 5\n").contains("unknown field `history`"));
+        assert!(error("[colours]\n").contains("unknown field `colours`"));
+        assert!(error("verbose = true\n").contains("unknown field `verbose`"));
+        assert!(error("ui = 5\n").contains("invalid type"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let cases = [
+            ("[database]\npath = \"\"", "'database.path' must not be empty"),
+            ("[database]\nretry_attempts = 0", "'database.retry_attempts' must be at least 1"),
+            ("[database]\nretry_delay_ms = -1", "'database.retry_delay_ms' must be at least 0"),
+            ("[ui]\nhistory_limit = 0", "'ui.history_limit' must be at least 1"),
+            ("[ui]\nsession_colors = []", "must not be empty"),
+            ("[retention]\nmax_age = \"soon\"", "'retention.max_age'"),
+            ("[retention]\nmax_commands = -5", "'retention.max_commands' must be at least 0"),
+            ("[sync]\nurl = \"ftp://example.com\"", "'sync.url' must start with"),
+            ("[sync]\nevery = -1", "'sync.every' must be at least 0"),
+        ];
+        for (text, expected) in cases {
+            let message = error(text);
+            assert!(message.contains(expected), "{}: {}", text, message);
+        }
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let file = "[ui]\nhistory_limit = 50\n[sync]\nevery = 10\n";
+        let config = with_vars(
+            file,
+            &[
+                ("RECALL_UI_HISTORY_LIMIT", "7"),
+                ("RECALL_SYNC_URL", "http://sync.lan"),
+                ("RECALL_REDACT_PLACEHOLDER", "[hidden]"),
+                ("RECALL_IGNORE_PATTERNS", r#"["ls", "cd *"]"#),
+                ("RECALL_DB_PATH", "/tmp/other.db"),
+                ("RECALL_RETENTION_MAX_AGE", ""),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.ui.history_limit, 7);
+        assert_eq!(config.sync.every, 10);
+        assert_eq!(config.sync.url.as_deref(), Some("http://sync.lan"));
+        // Strings are taken verbatim even when they would parse as TOML
+        assert_eq!(config.redact.placeholder, "[hidden]");
+        assert_eq!(config.ignore.patterns, ["ls", "cd *"]);
+        assert_eq!(config.database.path, "/tmp/other.db");
+        assert_eq!(config.retention.max_age, None);
+
+        let config = with_vars("", &[("RECALL_DATABASE_PATH", "/a.db"), ("RECALL_DB_PATH", "/b.db")]).unwrap();
+        assert_eq!(config.database.path, "/a.db");
+        assert_eq!(with_vars(file, &[]).unwrap(), Config::from_toml(file).unwrap());
+    }
+
+    #[test]
+    fn invalid_overrides_name_their_variable() {
+        let message = with_vars("", &[("RECALL_UI_HISTORY_LIMIT", "lots")]).unwrap_err();
+        assert!(message.contains("`ui.history_limit`"), "{}", message);
+        assert!(message.ends_with("(set by $RECALL_UI_HISTORY_LIMIT)"), "{}", message);
+
+        let message = with_vars("", &[("RECALL_SYNC_EVERY", "-3")]).unwrap_err();
+        assert!(message.ends_with("(set by $RECALL_SYNC_EVERY)"), "{}", message);
+    }
+
+    #[test]
+    fn env_var_names() {
+        assert_eq!(env_var_for("ui.history_limit"), "RECALL_UI_HISTORY_LIMIT");
+        assert_eq!(env_var_for("sync.auth_token"), "RECALL_SYNC_AUTH_TOKEN");
+    }
+
+    #[test]
+    fn parses_single_values() {
+        assert_eq!(parse_value("42"), Some(Value::Integer(42)));
+        assert_eq!(parse_value("true"), Some(Value::Boolean(true)));
+        assert_eq!(parse_value(r#"["a", 'b']"#), Some(Value::Array(vec!["a".into(), "b".into()])));
+        assert_eq!(parse_value("bare words"), None);
+        assert_eq!(parse_value("1\nother = 2"), None);
+    }
+}
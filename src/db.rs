@@ -1,9 +1,13 @@
 use chrono::{DateTime, Utc};
-use dirs::home_dir;
+use crate::config;
+use crate::encryption;
 use libsql::{Builder, Database};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use crate::migrations::{self, Migration};
+use crate::redact::Redactor;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CommandHistoryEntry {
@@ -11,33 +15,228 @@ pub struct CommandHistoryEntry {
     pub timestamp: DateTime<Utc>,
     pub command: String,
     pub binary: String,
+    /// `NAME=value` assignments prefixed to the command, space separated
+    pub env: Option<String>,
+    /// Wrappers such as `sudo -E` that ran the binary, space separated
+    pub wrappers: Option<String>,
     pub user: String,
     pub pwd: String,
     pub session_id: i64,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<i64>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The invocation (full command line) this segment belongs to
+    pub invocation_id: Option<i64>,
+    /// Index of this segment within its invocation
+    pub position: i64,
+    /// The full command line as typed, when the entry is part of an invocation
+    pub line: Option<String>,
+    /// The machine the command ran on, taken from its session
+    #[serde(default)]
+    pub hostname: Option<String>,
 }
 
+impl CommandHistoryEntry {
+    /// The command line to show and re-run: the full invocation when known,
+    /// otherwise just this segment.
+    pub fn full_command(&self) -> &str {
+        self.line.as_deref().unwrap_or(&self.command)
+    }
+}
+
+/// Criteria for [`DatabaseManager::query_commands`]. Every field is optional
+/// and the ones that are set must all match.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Substring of the full command line
+    pub query: Option<String>,
+    pub cwd: Option<String>,
+    pub session_id: Option<i64>,
+    /// Matches invocations where any segment ran this binary
+    pub binary: Option<String>,
+    pub user: Option<String>,
+    /// Hostname or machine id of the machine the command ran on
+    pub host: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Keep only the most recent entries
+    pub limit: Option<i64>,
+}
+
+/// A command line read from another history source, ready to be stored.
+#[derive(Debug, Clone)]
+pub struct ImportedInvocation {
+    pub line: String,
+    /// Parsed segments; the first one supplies the session and timestamp
+    pub entries: Vec<CommandHistoryEntry>,
+    /// Index of the entry in its history file when the file gave no
+    /// timestamp and one was made up, in which case duplicates are recognised
+    /// by session and position instead of by time
+    pub source_position: Option<i64>,
+}
+
+/// Outcome of [`DatabaseManager::import_invocations`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportStats {
+    pub imported: usize,
+    pub duplicates: usize,
+    pub skipped: usize,
+}
+
+/// A stored command that contained secrets, as it reads after redaction.
+#[derive(Debug, Clone)]
+pub struct RedactedCommand {
+    pub id: i64,
+    pub command: String,
+    pub detectors: Vec<String>,
+}
+
+/// What [`DatabaseManager::prune`] or [`DatabaseManager::delete_entries`]
+/// removed, or would remove.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PruneStats {
+    /// Command lines, counting a pipeline or `&&` chain once
+    pub commands: usize,
+    /// Rows in `command_history`, one per simple command
+    pub segments: usize,
+    /// Sessions left without any commands
+    pub sessions: usize,
+}
+
+/// What is known about the shell behind a session, stored when the session
+/// is created.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SessionDetails {
+    pub hostname: Option<String>,
+    /// Contents of `/etc/machine-id`, which stays the same when the host is renamed
+    pub machine_id: Option<String>,
+    /// How the session key was worked out, e.g. `token` for `$RECALL_SESSION`
+    pub strategy: Option<String>,
+    pub shell_pid: Option<i64>,
+    pub tty: Option<String>,
+    /// Name of the shell program, e.g. `bash`
+    pub shell: Option<String>,
+    /// The terminal emulator, multiplexer or other program the shell runs in
+    pub terminal: Option<String>,
+}
+
+/// A row of the `sessions` table with figures about its commands.
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionSummary {
+    pub id: i64,
+    /// Label given with `recall sessions name`
+    pub name: Option<String>,
+    pub key: String,
+    pub started_at: DateTime<Utc>,
+    /// When the last command was logged or finished
+    pub stopped_at: Option<DateTime<Utc>>,
+    /// When the shell exited, or `None` while it is still open
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Why the shell exited, e.g. `exit` or `hangup`
+    pub exit_reason: Option<String>,
+    #[serde(flatten)]
+    pub details: SessionDetails,
+    /// Command lines run in the session
+    pub commands: i64,
+    /// The directory most commands ran in
+    pub cwd: Option<String>,
+}
+
+impl SessionSummary {
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.ended_at.or(self.stopped_at).map(|stopped| stopped - self.started_at)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.ended_at.is_none()
+    }
+}
+
+/// Columns of a session as exchanged with a sync server.
+pub const SYNC_SESSION_COLUMNS: &[&str] = &[
+    "uuid", "key", "started_at", "stopped_at", "ended_at", "exit_reason", "name", "hostname", "machine_id",
+    "strategy", "shell_pid", "tty", "shell", "terminal",
+];
+
+/// Columns of an invocation as exchanged with a sync server.
+pub const SYNC_INVOCATION_COLUMNS: &[&str] = &["uuid", "session_uuid", "timestamp", "line"];
+
+/// Columns of a command segment as exchanged with a sync server.
+pub const SYNC_COMMAND_COLUMNS: &[&str] = &[
+    "uuid", "session_uuid", "invocation_uuid", "position", "timestamp", "command", "binary", "env", "wrappers",
+    "user", "pwd", "exit_code", "duration_ms", "finished_at",
+];
+
+/// Rows exchanged with a sync server. Each row holds the values of the
+/// matching `SYNC_*_COLUMNS`, and rows refer to each other by uuid, which
+/// unlike the id is the same in every database.
+#[derive(Debug, Clone, Default)]
+pub struct SyncChanges {
+    pub sessions: Vec<Vec<libsql::Value>>,
+    pub invocations: Vec<Vec<libsql::Value>>,
+    pub commands: Vec<Vec<libsql::Value>>,
+    /// Uuids of deleted sessions, invocations and commands
+    pub deleted: Vec<String>,
+}
+
+impl SyncChanges {
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty() && self.invocations.is_empty() && self.commands.is_empty() && self.deleted.is_empty()
+    }
+
+    /// Command lines among the rows, counting a pipeline or `&&` chain once.
+    pub fn command_lines(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|row| matches!(row[3], libsql::Value::Integer(0)))
+            .count()
+    }
+}
+
+/// Where a database stands with its sync server.
+#[derive(Debug, Clone)]
+pub struct SyncState {
+    /// Identifies this database to the server, which leaves out its own rows when pulling
+    pub replica: String,
+    /// Server version up to which changes have been pulled
+    pub pulled: i64,
+}
+
+/// What storing synced or merged rows added and removed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeStats {
+    /// Command lines that were not here before
+    pub commands: usize,
+    pub sessions: usize,
+    /// Command lines removed because they were deleted elsewhere
+    pub deleted: usize,
+}
+
+#[derive(Clone)]
 pub struct DatabaseManager {
     db: Arc<Database>,
+    /// Attempts made at an operation while the database is locked
+    retry_attempts: u32,
+    retry_delay: Duration,
+    /// Key of an encrypted database
+    key: Option<Vec<u8>>,
 }
 
 impl DatabaseManager {
     pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let db_path = get_db_file_path();
-
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        let db = Builder::new_local(db_path).build().await?;
+        Self::open(&get_db_file_path()?).await
+    }
 
-        let manager = DatabaseManager { db: Arc::new(db) };
+    /// Opens the database at `path`, bringing its schema up to date.
+    pub async fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let manager = Self::open_unmigrated(path).await?;
         
         // Retry initialization in case of lock
-        for attempt in 0..3 {
-            match manager.init_schema().await {
-                Ok(()) => break,
-                Err(e) if attempt < 2 && e.to_string().contains("database is locked") => {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        for attempt in 1..=manager.retry_attempts {
+            match manager.migrate().await {
+                Ok(_) => break,
+                Err(e) if attempt < manager.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(manager.retry_delay * 2).await;
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -47,51 +246,129 @@ impl DatabaseManager {
         Ok(manager)
     }
 
-    async fn init_schema(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let conn = self.db.connect()?;
+    /// Opens the database at `path` without touching its schema.
+    pub async fn open_unmigrated(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                key TEXT NOT NULL UNIQUE,
-                started_at TEXT NOT NULL,
-                stopped_at TEXT
-            )",
-            (),
-        )
-        .await?;
+        Self::open_with_flags(path, libsql::OpenFlags::default()).await
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS command_history (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                timestamp TEXT NOT NULL,
-                command TEXT NOT NULL,
-                binary TEXT NOT NULL,
-                user TEXT NOT NULL,
-                pwd TEXT NOT NULL,
-                session_id INTEGER NOT NULL,
-                FOREIGN KEY (session_id) REFERENCES sessions(id)
-            )",
-            (),
-        )
-        .await?;
+    /// Opens the database at `path` for reading only, leaving both the file
+    /// and its schema untouched, such as another machine's copy in a shared
+    /// folder.
+    pub async fn open_read_only(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if !path.is_file() {
+            return Err(format!("No database at {}", path.display()).into());
+        }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_timestamp ON command_history(timestamp DESC)",
-            (),
-        )
-        .await?;
+        Self::open_with_flags(path, libsql::OpenFlags::SQLITE_OPEN_READ_ONLY).await
+    }
 
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_session_key ON sessions(key)",
-            (),
-        )
-        .await?;
+    /// Opens the database at `path` with the key it needs when it is
+    /// encrypted. A new database is encrypted when a key is set up.
+    async fn open_with_flags(
+        path: &Path,
+        flags: libsql::OpenFlags,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let encrypted = encryption::is_encrypted(path)?;
+        let configured = if encrypted || !path.exists() { encryption::configured_key()? } else { None };
+        let key = match configured {
+            Some(key) => Some(key),
+            None if encrypted && encryption::is_supported() && encryption::can_prompt() => Some(
+                encryption::prompt_passphrase(&format!("Passphrase for {}: ", path.display()))?,
+            ),
+            None if encrypted => return Err(encryption::missing_key_error(path)),
+            None => None,
+        };
+        if key.is_some() && !encryption::is_supported() {
+            return Err(if encrypted {
+                encryption::missing_key_error(path)
+            } else {
+                format!(
+                    "A database key is set up, but this recall was built without encryption support; \
+                     rebuild it with `cargo install --features encryption` or unset {} and database.key_file",
+                    encryption::KEY_VARIABLE
+                )
+                .into()
+            });
+        }
+
+        Self::open_with_key(path, flags, key).await
+    }
+
+    /// Opens the database at `path` with `key`, which must be the one it is
+    /// encrypted with, if any.
+    pub(crate) async fn open_with_key(
+        path: &Path,
+        flags: libsql::OpenFlags,
+        key: Option<Vec<u8>>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut builder = Builder::new_local(path).flags(flags);
+        if let Some(key) = &key {
+            builder = builder.encryption_config(encryption::encryption_config(key.clone()));
+        }
+        let db = builder.build().await?;
+        if encryption::is_encrypted(path)? {
+            // A wrong key only shows once the file is read
+            let conn = db.connect()?;
+            if let Err(e) = conn.query("SELECT COUNT(*) FROM sqlite_master", ()).await {
+                if e.to_string().contains("not a database") {
+                    return Err(format!("Wrong key for the encrypted database at {}", path.display()).into());
+                }
+                return Err(e.into());
+            }
+        }
+        let settings = &config::get()?.database;
+
+        Ok(DatabaseManager {
+            db: Arc::new(db),
+            retry_attempts: settings.retry_attempts as u32,
+            retry_delay: Duration::from_millis(settings.retry_delay_ms as u64),
+            key,
+        })
+    }
 
+    /// Encrypts the database with `key`, or decrypts it when `key` is
+    /// `None`, rewriting every page in place in one transaction.
+    pub async fn rekey(&self, key: Option<&[u8]>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let pragmas = match key {
+            Some(key) => vec![
+                "PRAGMA cipher = 'aes256cbc'".to_string(),
+                format!("PRAGMA hexrekey = '{}'", encryption::hex_key(key)),
+            ],
+            None => vec!["PRAGMA rekey = ''".to_string()],
+        };
+        for pragma in pragmas {
+            let mut rows = conn.query(&pragma, ()).await?;
+            while rows.next().await?.is_some() {}
+        }
         Ok(())
     }
 
-    pub async fn get_or_create_session(&self, session_key: &str) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    /// Applies pending schema migrations, returning the ones that ran.
+    pub async fn migrate(&self) -> Result<Vec<&'static Migration>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        migrations::run(&conn).await
+    }
+
+    pub async fn pending_migrations(&self) -> Result<Vec<&'static Migration>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        migrations::pending(&conn).await
+    }
+
+    pub async fn schema_version(&self) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        migrations::current_version(&conn).await
+    }
+
+    pub async fn get_or_create_session(
+        &self,
+        session_key: &str,
+        details: &SessionDetails,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.db.connect()?;
 
         let mut rows = conn.query("SELECT id FROM sessions WHERE key = ?", &[session_key]).await?;
@@ -100,9 +377,26 @@ impl DatabaseManager {
             return Ok(row.get::<i64>(0)?);
         }
 
+        drop(rows);
+        let host_id = match &details.hostname {
+            Some(hostname) => Some(host_id(&conn, hostname, details.machine_id.as_deref()).await?),
+            None => None,
+        };
+
         conn.execute(
-            "INSERT INTO sessions (key, started_at) VALUES (?, ?)",
-            (session_key, Utc::now().to_rfc3339().as_str()),
+            "INSERT INTO sessions (key, started_at, hostname, host_id, strategy, shell_pid, tty, shell, terminal)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            libsql::params![
+                session_key,
+                Utc::now().to_rfc3339(),
+                details.hostname.clone(),
+                host_id,
+                details.strategy.clone(),
+                details.shell_pid,
+                details.tty.clone(),
+                details.shell.clone(),
+                details.terminal.clone(),
+            ],
         ).await?;
 
         let mut rows = conn.query("SELECT id FROM sessions WHERE key = ?", &[session_key]).await?;
@@ -114,6 +408,170 @@ impl DatabaseManager {
         }
     }
 
+    pub async fn find_session(&self, session_key: &str) -> Result<Option<i64>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let mut rows = conn.query("SELECT id FROM sessions WHERE key = ?", &[session_key]).await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get::<i64>(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The line of the most recent invocation in a session.
+    pub async fn last_invocation_line(&self, session_id: i64) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let mut rows = conn
+            .query(
+                "SELECT line FROM invocations WHERE session_id = ? ORDER BY timestamp DESC, id DESC LIMIT 1",
+                [session_id],
+            )
+            .await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get::<String>(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The sessions that started at or after `since`, or every session
+    /// without it, newest first, with their command count and the directory
+    /// they mostly ran in.
+    pub async fn list_sessions(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SessionSummary>, Box<dyn std::error::Error + Send + Sync>> {
+        match since {
+            Some(since) => self.session_summaries("s.started_at >= ?", vec![since.to_rfc3339().into()]).await,
+            None => self.session_summaries("1", Vec::new()).await,
+        }
+    }
+
+    pub async fn get_session(&self, id: i64) -> Result<Option<SessionSummary>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.session_summaries("s.id = ?", vec![id.into()]).await?.pop())
+    }
+
+    /// Summaries of the sessions `s` matching `condition`.
+    async fn session_summaries(
+        &self,
+        condition: &str,
+        params: Vec<libsql::Value>,
+    ) -> Result<Vec<SessionSummary>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        // session id -> (commands, most used directory and its count)
+        let mut stats: std::collections::HashMap<i64, (i64, Option<(String, i64)>)> = std::collections::HashMap::new();
+        {
+            let mut rows = conn
+                .query(
+                    &format!(
+                        "SELECT c.session_id, c.pwd, COUNT(*) FROM command_history c JOIN sessions s ON s.id = c.session_id
+                         WHERE c.position = 0 AND {} GROUP BY c.session_id, c.pwd",
+                        condition
+                    ),
+                    params.clone(),
+                )
+                .await?;
+            while let Some(row) = rows.next().await? {
+                let count = row.get::<i64>(2)?;
+                let (total, dominant) = stats.entry(row.get::<i64>(0)?).or_default();
+                *total += count;
+                if dominant.as_ref().is_none_or(|(_, most)| count > *most) {
+                    *dominant = Some((row.get::<String>(1)?, count));
+                }
+            }
+        }
+
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT s.id, s.key, s.started_at, s.stopped_at, s.hostname, s.name,
+                            s.strategy, s.shell_pid, s.tty, s.shell, s.terminal, s.ended_at, s.exit_reason,
+                            hosts.machine_id
+                     FROM sessions s LEFT JOIN hosts ON hosts.id = s.host_id
+                     WHERE {} ORDER BY s.started_at DESC, s.id DESC",
+                    condition
+                ),
+                params,
+            )
+            .await?;
+        let mut sessions = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let id = row.get::<i64>(0)?;
+            let (commands, cwd) = stats.remove(&id).unwrap_or_default();
+            sessions.push(SessionSummary {
+                id,
+                name: row.get::<Option<String>>(5)?,
+                key: row.get::<String>(1)?,
+                started_at: DateTime::parse_from_rfc3339(&row.get::<String>(2)?)?.with_timezone(&Utc),
+                stopped_at: row
+                    .get::<Option<String>>(3)?
+                    .map(|t| DateTime::parse_from_rfc3339(&t).map(|t| t.with_timezone(&Utc)))
+                    .transpose()?,
+                ended_at: row
+                    .get::<Option<String>>(11)?
+                    .map(|t| DateTime::parse_from_rfc3339(&t).map(|t| t.with_timezone(&Utc)))
+                    .transpose()?,
+                exit_reason: row.get::<Option<String>>(12)?,
+                details: SessionDetails {
+                    hostname: row.get::<Option<String>>(4)?,
+                    machine_id: row.get::<Option<String>>(13)?,
+                    strategy: row.get::<Option<String>>(6)?,
+                    shell_pid: row.get::<Option<i64>>(7)?,
+                    tty: row.get::<Option<String>>(8)?,
+                    shell: row.get::<Option<String>>(9)?,
+                    terminal: row.get::<Option<String>>(10)?,
+                },
+                commands,
+                cwd: cwd.map(|(cwd, _)| cwd),
+            });
+        }
+
+        Ok(sessions)
+    }
+
+    /// Labels a session, or clears its label when `name` is `None`.
+    /// Returns false if there is no such session.
+    pub async fn name_session(&self, id: i64, name: Option<&str>) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let updated = conn.execute("UPDATE sessions SET name = ? WHERE id = ?", (name, id)).await?;
+
+        Ok(updated > 0)
+    }
+
+    /// Marks a session as open again, e.g. when a shell with a reused key
+    /// starts. Returns false if it was not closed.
+    pub async fn reopen_session(&self, session_id: i64) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let updated = conn
+            .execute(
+                "UPDATE sessions SET ended_at = NULL, exit_reason = NULL WHERE id = ? AND ended_at IS NOT NULL",
+                [session_id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    /// Records that the shell of a session exited. Only the first end is
+    /// kept, so a hangup followed by the exit hook reports the hangup.
+    pub async fn end_session(&self, session_id: i64, reason: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let updated = conn
+            .execute(
+                "UPDATE sessions SET ended_at = ?, exit_reason = ? WHERE id = ? AND ended_at IS NULL",
+                (Utc::now().to_rfc3339().as_str(), reason, session_id),
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
     pub async fn update_session_stopped_at(&self, session_id: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.db.connect()?;
         
@@ -125,108 +583,1437 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Updates `stopped_at` for a newly logged command. A new command means
+    /// the shell is running, so a session that was closed, e.g. because its
+    /// tty was reused, is opened again.
+    pub async fn mark_session_active(&self, session_id: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        conn.execute(
+            "UPDATE sessions SET stopped_at = ?, ended_at = NULL, exit_reason = NULL WHERE id = ?",
+            (Utc::now().to_rfc3339().as_str(), session_id),
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Stores a command line as an invocation together with its parsed
+    /// segments, returning the invocation id.
     pub async fn log_command(
         &self,
-        entry: &CommandHistoryEntry,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        line: &str,
+        entries: &[CommandHistoryEntry],
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
         // Retry on database lock
-        for attempt in 0..3 {
-            match self.try_log_command(entry).await {
-                Ok(()) => return Ok(()),
-                Err(e) if attempt < 2 && e.to_string().contains("database is locked") => {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        for attempt in 1..=self.retry_attempts {
+            match self.try_log_command(line, entries).await {
+                Ok(id) => return Ok(id),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
                     continue;
                 }
                 Err(e) => return Err(e),
             }
         }
-        Ok(())
+        Err("Failed to log command".into())
     }
 
     async fn try_log_command(
         &self,
-        entry: &CommandHistoryEntry,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        line: &str,
+        entries: &[CommandHistoryEntry],
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(first) = entries.first() else {
+            return Err("No command segments to log".into());
+        };
+
         let conn = self.db.connect()?;
+        let tx = conn.transaction().await?;
 
-        conn.execute(
-            "INSERT INTO command_history (timestamp, command, binary, user, pwd, session_id) 
-             VALUES (?, ?, ?, ?, ?, ?)",
-            (
-                entry.timestamp.to_rfc3339().as_str(),
-                entry.command.as_str(),
-                entry.binary.as_str(),
-                entry.user.as_str(),
-                entry.pwd.as_str(),
-                entry.session_id,
-            ),
-        )
-        .await?;
+        let invocation_id = insert_invocation(&tx, line, entries).await?;
+
+        tx.commit().await?;
         
-        self.update_session_stopped_at(entry.session_id).await?;
+        self.mark_session_active(first.session_id).await?;
 
-        Ok(())
+        Ok(invocation_id)
     }
 
-    pub async fn fetch_recent_commands(
+    /// Stores command lines read from another history source. Lines already
+    /// present in their session are counted as duplicates and left alone, so
+    /// importing the same history twice is harmless.
+    pub async fn import_invocations(
         &self,
-        limit: i64,
-    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        invocations: &[ImportedInvocation],
+    ) -> Result<ImportStats, Box<dyn std::error::Error + Send + Sync>> {
         // Retry on database lock
-        for attempt in 0..3 {
-            match self.try_fetch_recent_commands(limit).await {
-                Ok(commands) => return Ok(commands),
-                Err(e) if attempt < 2 && e.to_string().contains("database is locked") => {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        for attempt in 1..=self.retry_attempts {
+            match self.try_import_invocations(invocations).await {
+                Ok(stats) => return Ok(stats),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay * 2).await;
                     continue;
                 }
                 Err(e) => return Err(e),
             }
         }
-        Ok(Vec::new())
+        Err("Failed to import commands".into())
     }
 
-    async fn try_fetch_recent_commands(
+    async fn try_import_invocations(
         &self,
-        limit: i64,
-    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        invocations: &[ImportedInvocation],
+    ) -> Result<ImportStats, Box<dyn std::error::Error + Send + Sync>> {
         let conn = self.db.connect()?;
+        let tx = conn.transaction().await?;
+        let mut stats = ImportStats::default();
+        let mut sessions = std::collections::BTreeSet::new();
 
-        let mut rows = conn
-            .query(
-                "SELECT id, timestamp, command, binary, user, pwd, session_id 
-                 FROM command_history 
-                 ORDER BY timestamp DESC 
-                 LIMIT ?",
-                &[limit],
+        for invocation in invocations {
+            let Some(first) = invocation.entries.first() else {
+                stats.skipped += 1;
+                continue;
+            };
+
+            let exists = {
+                let mut rows = match invocation.source_position {
+                    // Lines imported before positions were recorded only match by text
+                    Some(position) => {
+                        tx.query(
+                            "SELECT 1 FROM invocations WHERE session_id = ?
+                                AND (source_position = ? OR (source_position IS NULL AND line = ?))",
+                            (first.session_id, position, invocation.line.as_str()),
+                        )
+                        .await?
+                    }
+                    None => {
+                        tx.query(
+                            "SELECT 1 FROM invocations WHERE session_id = ? AND timestamp = ? AND line = ?",
+                            (first.session_id, first.timestamp.to_rfc3339().as_str(), invocation.line.as_str()),
+                        )
+                        .await?
+                    }
+                };
+                rows.next().await?.is_some()
+            };
+            if exists {
+                stats.duplicates += 1;
+                continue;
+            }
+
+            let invocation_id = insert_invocation(&tx, &invocation.line, &invocation.entries).await?;
+            if let Some(position) = invocation.source_position {
+                tx.execute(
+                    "UPDATE invocations SET source_position = ? WHERE id = ?",
+                    (position, invocation_id),
+                )
+                .await?;
+            }
+            sessions.insert(first.session_id);
+            stats.imported += 1;
+        }
+
+        // Imported sessions span the commands they contain rather than the
+        // time of the import.
+        for session_id in sessions {
+            tx.execute(
+                "UPDATE sessions SET
+                    started_at = (SELECT MIN(timestamp) FROM invocations WHERE session_id = ?1),
+                    stopped_at = (SELECT MAX(timestamp) FROM invocations WHERE session_id = ?1),
+                    ended_at = (SELECT MAX(timestamp) FROM invocations WHERE session_id = ?1),
+                    exit_reason = 'import'
+                 WHERE id = ?1",
+                [session_id],
             )
             .await?;
+        }
 
-        let mut commands = Vec::new();
-        while let Some(row) = rows.next().await? {
-            let entry = CommandHistoryEntry {
-                id: Some(row.get::<i64>(0)?),
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<String>(1)?)?
-                    .with_timezone(&Utc),
-                command: row.get::<String>(2)?,
-                binary: row.get::<String>(3)?,
-                user: row.get::<String>(4)?,
-                pwd: row.get::<String>(5)?,
-                session_id: row.get::<i64>(6)?,
-            };
-            commands.push(entry);
+        tx.commit().await?;
+
+        Ok(stats)
+    }
+
+    /// Runs `redactor` over every stored command line, segment and env
+    /// assignment. Unless `dry_run` is set, changed rows are rewritten and the
+    /// database is vacuumed so the original text does not linger in free pages.
+    pub async fn redact_history(
+        &self,
+        redactor: &Redactor,
+        dry_run: bool,
+    ) -> Result<Vec<RedactedCommand>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let mut lines = Vec::new();
+        let mut invocation_updates = Vec::new();
+        {
+            let mut rows = conn.query("SELECT id, line FROM invocations ORDER BY id", ()).await?;
+            while let Some(row) = rows.next().await? {
+                let id = row.get::<i64>(0)?;
+                let line = row.get::<String>(1)?;
+                let redaction = redactor.redact(&line);
+                if redaction.changed() {
+                    lines.push(RedactedCommand {
+                        id,
+                        command: redaction.text.to_string(),
+                        detectors: redaction.detectors,
+                    });
+                    invocation_updates.push((id, redaction.text.into_owned()));
+                }
+            }
         }
 
-        Ok(commands)
+        let mut segment_updates = Vec::new();
+        {
+            let mut rows = conn
+                .query("SELECT id, command, env, invocation_id FROM command_history ORDER BY id", ())
+                .await?;
+            while let Some(row) = rows.next().await? {
+                let id = row.get::<i64>(0)?;
+                let command = row.get::<String>(1)?;
+                let env = row.get::<Option<String>>(2)?;
+                let command_redaction = redactor.redact(&command);
+                let env_redaction = env.as_deref().map(|env| redactor.redact(env));
+                let env_changed = env_redaction.as_ref().is_some_and(|r| r.changed());
+                if !command_redaction.changed() && !env_changed {
+                    continue;
+                }
+
+                // Rows logged before invocations existed are reported on their own
+                if row.get::<Option<i64>>(3)?.is_none() {
+                    let mut detectors = command_redaction.detectors.clone();
+                    if let Some(env_redaction) = &env_redaction {
+                        detectors.extend(env_redaction.detectors.iter().cloned());
+                    }
+                    lines.push(RedactedCommand {
+                        id,
+                        command: command_redaction.text.to_string(),
+                        detectors,
+                    });
+                }
+                segment_updates.push((
+                    id,
+                    command_redaction.text.into_owned(),
+                    env_redaction.map(|r| r.text.into_owned()),
+                ));
+            }
+        }
+
+        if dry_run || (invocation_updates.is_empty() && segment_updates.is_empty()) {
+            return Ok(lines);
+        }
+
+        let tx = conn.transaction().await?;
+        for (id, line) in &invocation_updates {
+            tx.execute("UPDATE invocations SET line = ? WHERE id = ?", (line.as_str(), *id))
+                .await?;
+        }
+        for (id, command, env) in &segment_updates {
+            tx.execute(
+                "UPDATE command_history SET command = ?, env = ? WHERE id = ?",
+                (command.as_str(), env.as_deref(), *id),
+            )
+            .await?;
+        }
+        // Merge the index so removed terms are dropped rather than just marked deleted
+        tx.execute("INSERT INTO command_history_fts (command_history_fts) VALUES ('optimize')", ())
+            .await?;
+        tx.commit().await?;
+
+        conn.execute("VACUUM", ()).await?;
+
+        Ok(lines)
     }
-}
 
-pub fn get_db_file_path() -> PathBuf {
-    if let Ok(test_path) = std::env::var("RECALL_DB_PATH") {
-        return PathBuf::from(test_path);
+    /// Deletes the command lines matching `filter`, except the `keep_last`
+    /// most recent of them, along with their segments and any session left
+    /// empty. Only the session, binary and time criteria of the filter apply.
+    /// With `dry_run` the deletion is rolled back and only counted.
+    pub async fn prune(
+        &self,
+        filter: &HistoryFilter,
+        keep_last: Option<i64>,
+        dry_run: bool,
+    ) -> Result<PruneStats, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_prune(filter, keep_last, dry_run).await {
+                Ok(stats) => return Ok(stats),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err("Failed to prune history".into())
+    }
+
+    async fn try_prune(
+        &self,
+        filter: &HistoryFilter,
+        keep_last: Option<i64>,
+        dry_run: bool,
+    ) -> Result<PruneStats, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let (conditions, mut params) = filter_conditions(&HistoryFilter {
+            query: None,
+            cwd: None,
+            user: None,
+            limit: None,
+            ..filter.clone()
+        });
+        params.push(keep_last.unwrap_or(0).into());
+
+        let tx = conn.transaction().await?;
+        tx.execute("DROP TABLE IF EXISTS temp.prune_targets", ()).await?;
+        tx.execute(
+            &format!(
+                "CREATE TEMP TABLE prune_targets AS
+                 SELECT h.id AS entry_id, h.invocation_id, h.session_id FROM {} WHERE {}
+                 ORDER BY h.timestamp DESC, h.id DESC LIMIT -1 OFFSET ?",
+                ENTRY_SOURCE, conditions
+            ),
+            params,
+        )
+        .await?;
+
+        let stats = delete_prune_targets(&tx).await?;
+
+        if dry_run || stats.commands == 0 {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Deletes the command lines that the entries with these ids belong to,
+    /// with all their segments, and any session left empty.
+    pub async fn delete_entries(&self, ids: &[i64]) -> Result<PruneStats, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_delete_entries(ids).await {
+                Ok(stats) => return Ok(stats),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err("Failed to delete commands".into())
     }
-    let mut path = home_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
-    path.push(".recall");
-    path.push("recall.db");
-    path
+
+    async fn try_delete_entries(&self, ids: &[i64]) -> Result<PruneStats, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let tx = conn.transaction().await?;
+
+        tx.execute("DROP TABLE IF EXISTS temp.forget_ids", ()).await?;
+        tx.execute("CREATE TEMP TABLE forget_ids (id INTEGER PRIMARY KEY)", ()).await?;
+        for id in ids {
+            tx.execute("INSERT OR IGNORE INTO forget_ids (id) VALUES (?)", [*id]).await?;
+        }
+        tx.execute("DROP TABLE IF EXISTS temp.prune_targets", ()).await?;
+        tx.execute(
+            "CREATE TEMP TABLE prune_targets AS
+             SELECT h.id AS entry_id, h.invocation_id, h.session_id FROM command_history h
+             WHERE h.id IN (SELECT id FROM forget_ids)",
+            (),
+        )
+        .await?;
+        tx.execute("DROP TABLE temp.forget_ids", ()).await?;
+        let stats = delete_prune_targets(&tx).await?;
+        tx.commit().await?;
+
+        Ok(stats)
+    }
+
+    /// Returns the space freed by deleting rows to the filesystem. A full
+    /// VACUUM also switches the database to incremental auto-vacuum, so that
+    /// later `incremental` runs, which are cheap, have pages to release.
+    pub async fn vacuum(&self, incremental: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        if incremental {
+            conn.execute("PRAGMA incremental_vacuum", ()).await?;
+        } else {
+            conn.execute("INSERT INTO command_history_fts (command_history_fts) VALUES ('optimize')", ())
+                .await?;
+            conn.execute("PRAGMA auto_vacuum = INCREMENTAL", ()).await?;
+            conn.execute("VACUUM", ()).await?;
+        }
+        Ok(())
+    }
+
+    /// Prepares syncing with the server at `remote`. The first sync with a
+    /// server, including after switching to another one, sends every row.
+    pub async fn sync_state(&self, remote: &str) -> Result<SyncState, Box<dyn std::error::Error + Send + Sync>> {
+        let replica = self.replica_id().await?;
+        let conn = self.db.connect()?;
+        let mut state = std::collections::HashMap::new();
+        {
+            let mut rows = conn.query("SELECT key, value FROM sync_state", ()).await?;
+            while let Some(row) = rows.next().await? {
+                state.insert(row.get::<String>(0)?, row.get::<String>(1)?);
+            }
+        }
+
+        let pulled = if state.get("remote").map(String::as_str) == Some(remote) {
+            state.get("pulled").and_then(|pulled| pulled.parse().ok()).unwrap_or(0)
+        } else {
+            let tx = conn.transaction().await?;
+            tx.execute("INSERT OR REPLACE INTO sync_state (key, value) VALUES ('remote', ?)", [remote])
+                .await?;
+            tx.execute("DELETE FROM sync_state WHERE key = 'pulled'", ()).await?;
+            for table in ["sessions", "invocations", "command_history"] {
+                tx.execute(&format!("UPDATE {} SET synced = 0 WHERE synced <> 0", table), ())
+                    .await?;
+            }
+            tx.commit().await?;
+            0
+        };
+
+        Ok(SyncState { replica, pulled })
+    }
+
+    /// The uuid that tells this database apart from the ones it syncs with,
+    /// created the first time it is asked for.
+    pub async fn replica_id(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO sync_state (key, value) VALUES ('replica', ?)",
+            [uuid::Uuid::new_v4().to_string()],
+        )
+        .await?;
+        self.sync_value("replica")
+            .await?
+            .ok_or_else(|| "The database has no replica id".into())
+    }
+
+    /// A value recorded by earlier syncs, such as when a shared file was last merged.
+    pub async fn sync_value(&self, key: &str) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let mut rows = conn.query("SELECT value FROM sync_state WHERE key = ?", [key]).await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get::<String>(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_sync_value(&self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        conn.execute("INSERT OR REPLACE INTO sync_state (key, value) VALUES (?, ?)", (key, value))
+            .await?;
+        Ok(())
+    }
+
+    /// Writes a consistent copy of the whole database to `path`, which must
+    /// not exist yet. The copy of an encrypted database is encrypted with
+    /// the same key.
+    pub async fn export_snapshot(&self, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let target = match &self.key {
+            Some(key) => {
+                let path = path.to_string_lossy().replace('%', "%25").replace('?', "%3f").replace('#', "%23");
+                format!("file:{}?cipher=aes256cbc&hexkey={}", path, encryption::hex_key(key))
+            }
+            None => path.to_string_lossy().into_owned(),
+        };
+        conn.execute("VACUUM INTO ?", [target]).await?;
+
+        // Never leave history readable where the database itself is not
+        if self.key.is_some() && !encryption::is_encrypted(path)? {
+            std::fs::remove_file(path)?;
+            return Err("Could not write an encrypted copy of the database".into());
+        }
+        Ok(())
+    }
+
+    /// Returns every row changed or deleted since the last sync, marking the
+    /// rows as being sent.
+    pub async fn claim_sync_changes(&self) -> Result<SyncChanges, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_claim_sync_changes().await {
+                Ok(changes) => return Ok(changes),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err("Failed to read changes to sync".into())
+    }
+
+    async fn try_claim_sync_changes(&self) -> Result<SyncChanges, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let tx = conn.transaction().await?;
+
+        // Rows left at 2 by a sync that failed are sent again
+        for table in ["sessions", "invocations", "command_history"] {
+            tx.execute(&format!("UPDATE {} SET synced = 2 WHERE synced = 0", table), ())
+                .await?;
+        }
+        let changes = sync_rows(&tx, true).await?;
+        tx.commit().await?;
+
+        Ok(changes)
+    }
+
+    /// Returns every row and every deletion this database knows of, for
+    /// merging into another database.
+    pub async fn all_sync_rows(&self) -> Result<SyncChanges, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        sync_rows(&conn, false).await
+    }
+
+    /// Records that the server received the rows returned by
+    /// [`claim_sync_changes`](Self::claim_sync_changes). Rows changed again
+    /// in the meantime are still sent next time.
+    pub async fn finish_sync_push(&self, changes: &SyncChanges) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_finish_sync_push(changes).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err("Failed to record the sync".into())
+    }
+
+    async fn try_finish_sync_push(&self, changes: &SyncChanges) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+        let tx = conn.transaction().await?;
+        for table in ["sessions", "invocations", "command_history"] {
+            tx.execute(&format!("UPDATE {} SET synced = 1 WHERE synced = 2", table), ())
+                .await?;
+        }
+        tx.execute(
+            "DELETE FROM sync_tombstones WHERE uuid IN (SELECT value FROM json_each(?))
+                AND NOT EXISTS (SELECT 1 FROM sync_state WHERE key = 'dir')",
+            [serde_json::to_string(&changes.deleted)?],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Stores rows pulled from the sync server or read from another database,
+    /// and deletes the rows they report as deleted. With `pulled`, the rows
+    /// come from the server: they replace older copies and changes up to
+    /// that version are remembered as seen. Without it they come from another
+    /// database, and only add rows and fill in what is missing here, such as
+    /// exit statuses, since a file can be older than this database.
+    pub async fn apply_sync_changes(
+        &self,
+        changes: &SyncChanges,
+        pulled: Option<i64>,
+    ) -> Result<MergeStats, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_apply_sync_changes(changes, pulled).await {
+                Ok(stats) => return Ok(stats),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err("Failed to store synced history".into())
+    }
+
+    async fn try_apply_sync_changes(
+        &self,
+        changes: &SyncChanges,
+        pulled: Option<i64>,
+    ) -> Result<MergeStats, Box<dyn std::error::Error + Send + Sync>> {
+        let source = if pulled.is_some() { "the sync server" } else { "the other database" };
+        let conn = self.db.connect()?;
+        let tx = conn.transaction().await?;
+
+        let sessions_before = count(&tx, "SELECT COUNT(*) FROM sessions").await?;
+        let commands_before = count(&tx, "SELECT COUNT(*) FROM command_history WHERE position = 0").await?;
+
+        for row in &changes.sessions {
+            let [uuid, key, started_at, stopped_at, ended_at, exit_reason, name, hostname, machine_id, strategy, shell_pid, tty, shell, terminal] =
+                row.as_slice()
+            else {
+                return Err(format!("Malformed session from {}", source).into());
+            };
+            let (known, deleted) = {
+                let mut rows = tx
+                    .query(
+                        "SELECT EXISTS (SELECT 1 FROM sessions WHERE uuid = ?1),
+                                EXISTS (SELECT 1 FROM sync_tombstones WHERE uuid = ?1)",
+                        [uuid.clone()],
+                    )
+                    .await?;
+                match rows.next().await? {
+                    Some(row) => (row.get::<bool>(0)?, row.get::<bool>(1)?),
+                    None => (false, false),
+                }
+            };
+            if known {
+                let sql = if pulled.is_some() {
+                    "UPDATE sessions SET stopped_at = ?1, ended_at = ?2, exit_reason = ?3, name = ?4 WHERE uuid = ?5"
+                } else {
+                    // An end recorded before the session was last active here
+                    // belongs to an earlier end, since undone
+                    "UPDATE sessions SET
+                        stopped_at = CASE WHEN stopped_at IS NULL OR ?1 > stopped_at THEN ?1 ELSE stopped_at END,
+                        ended_at = CASE WHEN ended_at IS NULL AND (stopped_at IS NULL OR ?1 >= stopped_at)
+                                        THEN ?2 ELSE ended_at END,
+                        exit_reason = CASE WHEN ended_at IS NULL AND (stopped_at IS NULL OR ?1 >= stopped_at)
+                                           THEN ?3 ELSE exit_reason END,
+                        name = COALESCE(name, ?4)
+                     WHERE uuid = ?5"
+                };
+                tx.execute(
+                    sql,
+                    vec![stopped_at.clone(), ended_at.clone(), exit_reason.clone(), name.clone(), uuid.clone()],
+                )
+                .await?;
+            } else if !deleted {
+                let hostname = text_value(hostname);
+                let host_id = match hostname {
+                    Some(hostname) => Some(host_id(&tx, hostname, text_value(machine_id)).await?),
+                    None => None,
+                };
+                let key = synced_session_key(&tx, text_value(key).unwrap_or("unknown"), hostname, uuid).await?;
+                tx.execute(
+                    "INSERT INTO sessions (uuid, key, started_at, stopped_at, ended_at, exit_reason, name, hostname,
+                                           host_id, strategy, shell_pid, tty, shell, terminal)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    vec![
+                        uuid.clone(),
+                        key.into(),
+                        started_at.clone(),
+                        stopped_at.clone(),
+                        ended_at.clone(),
+                        exit_reason.clone(),
+                        name.clone(),
+                        hostname.map(str::to_string).into(),
+                        host_id.into(),
+                        strategy.clone(),
+                        shell_pid.clone(),
+                        tty.clone(),
+                        shell.clone(),
+                        terminal.clone(),
+                    ],
+                )
+                .await?;
+            }
+            if pulled.is_some() {
+                tx.execute("UPDATE sessions SET synced = 1 WHERE uuid = ?", [uuid.clone()]).await?;
+            }
+        }
+
+        for row in &changes.invocations {
+            let [uuid, session_uuid, timestamp, line] = row.as_slice() else {
+                return Err(format!("Malformed invocation from {}", source).into());
+            };
+            let conflict = if pulled.is_some() { "DO UPDATE SET line = excluded.line" } else { "DO NOTHING" };
+            tx.execute(
+                &format!(
+                    "INSERT INTO invocations (uuid, session_id, timestamp, line)
+                     SELECT ?1, id, ?2, ?3 FROM sessions
+                     WHERE uuid = ?4 AND NOT EXISTS (SELECT 1 FROM sync_tombstones WHERE uuid = ?1)
+                     ON CONFLICT (uuid) {}",
+                    conflict
+                ),
+                vec![uuid.clone(), timestamp.clone(), line.clone(), session_uuid.clone()],
+            )
+            .await?;
+            if pulled.is_some() {
+                tx.execute("UPDATE invocations SET synced = 1 WHERE uuid = ?", [uuid.clone()]).await?;
+            }
+        }
+
+        for row in &changes.commands {
+            let [uuid, session_uuid, invocation_uuid, rest @ ..] = row.as_slice() else {
+                return Err(format!("Malformed command from {}", source).into());
+            };
+            if rest.len() != SYNC_COMMAND_COLUMNS.len() - 3 {
+                return Err(format!("Malformed command from {}", source).into());
+            }
+            let conflict = if pulled.is_some() {
+                "DO UPDATE SET command = excluded.command, env = excluded.env, exit_code = excluded.exit_code,
+                    duration_ms = excluded.duration_ms, finished_at = excluded.finished_at"
+            } else {
+                "DO UPDATE SET exit_code = COALESCE(exit_code, excluded.exit_code),
+                    duration_ms = COALESCE(duration_ms, excluded.duration_ms),
+                    finished_at = COALESCE(finished_at, excluded.finished_at)"
+            };
+            let mut params = vec![uuid.clone(), invocation_uuid.clone()];
+            params.extend(rest.iter().cloned());
+            params.push(session_uuid.clone());
+            tx.execute(
+                &format!(
+                    "INSERT INTO command_history (uuid, session_id, invocation_id, position, timestamp, command, binary,
+                                                  env, wrappers, user, pwd, exit_code, duration_ms, finished_at)
+                     SELECT ?1, s.id, (SELECT id FROM invocations WHERE uuid = ?2), ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10,
+                            ?11, ?12, ?13
+                     FROM sessions s
+                     WHERE s.uuid = ?14 AND NOT EXISTS (SELECT 1 FROM sync_tombstones WHERE uuid = ?1)
+                     ON CONFLICT (uuid) {}",
+                    conflict
+                ),
+                params,
+            )
+            .await?;
+            if pulled.is_some() {
+                tx.execute("UPDATE command_history SET synced = 1 WHERE uuid = ?", [uuid.clone()]).await?;
+            }
+        }
+
+        let mut stats = MergeStats {
+            sessions: count(&tx, "SELECT COUNT(*) FROM sessions").await?.saturating_sub(sessions_before),
+            commands: count(&tx, "SELECT COUNT(*) FROM command_history WHERE position = 0")
+                .await?
+                .saturating_sub(commands_before),
+            deleted: 0,
+        };
+
+        if !changes.deleted.is_empty() {
+            let deleted = serde_json::to_string(&changes.deleted)?;
+            {
+                let mut rows = tx
+                    .query(
+                        "SELECT COUNT(*) FROM command_history
+                         WHERE position = 0 AND uuid IN (SELECT value FROM json_each(?))",
+                        [deleted.as_str()],
+                    )
+                    .await?;
+                if let Some(row) = rows.next().await? {
+                    stats.deleted = row.get::<i64>(0)? as usize;
+                }
+            }
+            tx.execute(
+                "DELETE FROM command_history WHERE uuid IN (SELECT value FROM json_each(?))",
+                [deleted.as_str()],
+            )
+            .await?;
+            tx.execute(
+                "DELETE FROM invocations WHERE uuid IN (SELECT value FROM json_each(?))",
+                [deleted.as_str()],
+            )
+            .await?;
+            tx.execute(
+                "DELETE FROM sessions WHERE uuid IN (SELECT value FROM json_each(?))
+                    AND NOT EXISTS (SELECT 1 FROM command_history h WHERE h.session_id = sessions.id)
+                    AND NOT EXISTS (SELECT 1 FROM invocations i WHERE i.session_id = sessions.id)",
+                [deleted.as_str()],
+            )
+            .await?;
+            if pulled.is_some() {
+                // The server already knows about these deletions, while a
+                // shared folder only learns them from the files
+                tx.execute(
+                    "DELETE FROM sync_tombstones WHERE uuid IN (SELECT value FROM json_each(?))
+                        AND NOT EXISTS (SELECT 1 FROM sync_state WHERE key = 'dir')",
+                    [deleted.as_str()],
+                )
+                .await?;
+            } else {
+                // Kept so that merging a file that still has these rows
+                // does not bring them back
+                tx.execute(
+                    "INSERT OR IGNORE INTO sync_tombstones (uuid) SELECT value FROM json_each(?)",
+                    [deleted.as_str()],
+                )
+                .await?;
+            }
+        }
+
+        if let Some(pulled) = pulled {
+            tx.execute(
+                "INSERT OR REPLACE INTO sync_state (key, value) VALUES ('pulled', ?), ('synced_at', ?)",
+                (pulled.to_string(), Utc::now().to_rfc3339()),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(stats)
+    }
+
+    /// Marks every segment of an invocation logged by `recall log --start` as finished.
+    pub async fn finish_command(
+        &self,
+        invocation_id: i64,
+        exit_code: i32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_finish_command(invocation_id, exit_code).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn try_finish_command(
+        &self,
+        invocation_id: i64,
+        exit_code: i32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let (started_at, session_id) = {
+            let mut rows = conn
+                .query(
+                    "SELECT timestamp, session_id FROM invocations WHERE id = ?",
+                    &[invocation_id],
+                )
+                .await?;
+
+            let Some(row) = rows.next().await? else {
+                return Err(format!("No invocation with id {}", invocation_id).into());
+            };
+            (row.get::<String>(0)?, row.get::<i64>(1)?)
+        };
+
+        let finished_at = Utc::now();
+        let duration_ms = (finished_at - DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc))
+            .num_milliseconds()
+            .max(0);
+
+        conn.execute(
+            "UPDATE command_history SET exit_code = ?, duration_ms = ?, finished_at = ?
+             WHERE invocation_id = ? AND finished_at IS NULL",
+            (
+                exit_code,
+                duration_ms,
+                finished_at.to_rfc3339().as_str(),
+                invocation_id,
+            ),
+        )
+        .await?;
+
+        self.update_session_stopped_at(session_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_recent_commands(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_fetch_recent_commands(limit).await {
+                Ok(commands) => return Ok(commands),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay * 2).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    async fn try_fetch_recent_commands(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT {} FROM {} WHERE h.position = 0 ORDER BY h.timestamp DESC LIMIT ?",
+                    ENTRY_COLUMNS, ENTRY_SOURCE
+                ),
+                &[limit],
+            )
+            .await?;
+
+        let mut commands = Vec::new();
+        while let Some(row) = rows.next().await? {
+            commands.push(entry_from_row(&row)?);
+        }
+
+        Ok(commands)
+    }
+
+    /// The entry with this id, showing the full command line it belongs to.
+    pub async fn get_entry(&self, id: i64) -> Result<Option<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let sql = format!("SELECT {} FROM {} WHERE h.id = ?", ENTRY_COLUMNS, ENTRY_SOURCE);
+        let mut rows = conn.query(&sql, [id]).await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(Some(entry_from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns one entry per invocation matching the filter, newest first.
+    pub async fn query_commands(
+        &self,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_query_commands(filter).await {
+                Ok(commands) => return Ok(commands),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay * 2).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    async fn try_query_commands(
+        &self,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let conn = self.db.connect()?;
+
+        let (conditions, mut params) = filter_conditions(filter);
+        let mut sql = format!(
+            "SELECT {} FROM {} WHERE {} ORDER BY h.timestamp DESC",
+            ENTRY_COLUMNS, ENTRY_SOURCE, conditions
+        );
+        if let Some(limit) = filter.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(limit.into());
+        }
+
+        let mut rows = conn.query(&sql, params).await?;
+
+        let mut commands = Vec::new();
+        while let Some(row) = rows.next().await? {
+            commands.push(entry_from_row(&row)?);
+        }
+
+        Ok(commands)
+    }
+
+    /// Like [`query_commands`](Self::query_commands), but hands each entry to
+    /// `each` as it is read instead of collecting them, so arbitrarily large
+    /// histories can be written out in constant memory. With `oldest_first`
+    /// the most recent `limit` entries are still the ones kept.
+    ///
+    /// Unlike the other queries this is not retried on a locked database,
+    /// since entries may already have been handed out when the error occurs.
+    pub async fn stream_commands<F>(
+        &self,
+        filter: &HistoryFilter,
+        oldest_first: bool,
+        mut each: F,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut(CommandHistoryEntry) -> Result<(), Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let conn = self.db.connect()?;
+
+        let (conditions, mut params) = filter_conditions(filter);
+        let mut sql = format!(
+            "SELECT {} FROM {} WHERE {} ORDER BY h.timestamp DESC, h.id DESC",
+            ENTRY_COLUMNS, ENTRY_SOURCE, conditions
+        );
+        if let Some(limit) = filter.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(limit.into());
+        }
+        if oldest_first {
+            sql = match filter.limit {
+                Some(_) => format!("SELECT * FROM ({}) ORDER BY timestamp, id", sql),
+                None => sql.replace("ORDER BY h.timestamp DESC, h.id DESC", "ORDER BY h.timestamp, h.id"),
+            };
+        }
+
+        let mut rows = conn.query(&sql, params).await?;
+
+        let mut count = 0;
+        while let Some(row) = rows.next().await? {
+            each(entry_from_row(&row)?)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Full-text search over every command, returning one entry per
+    /// invocation ordered by relevance.
+    ///
+    /// The query supports prefixes (`carg*`), phrases (`"git push"`) and the
+    /// boolean operators `AND`, `OR` and `NOT`; terms are implicitly ANDed.
+    pub async fn search(
+        &self,
+        query: &str,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        // Retry on database lock
+        for attempt in 1..=self.retry_attempts {
+            match self.try_search(query, filter).await {
+                Ok(commands) => return Ok(commands),
+                Err(e) if attempt < self.retry_attempts && e.to_string().contains("database is locked") => {
+                    tokio::time::sleep(self.retry_delay * 2).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    async fn try_search(
+        &self,
+        query: &str,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<CommandHistoryEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(fts_query) = build_fts_query(query) else {
+            return self.try_query_commands(filter).await;
+        };
+
+        let conn = self.db.connect()?;
+
+        let (conditions, filter_params) = filter_conditions(filter);
+        let mut sql = format!(
+            "SELECT {} FROM {}
+             JOIN (
+                 SELECT COALESCE(c.invocation_id, -c.id) AS invocation_key, MIN(command_history_fts.rank) AS rank
+                 FROM command_history_fts JOIN command_history c ON c.id = command_history_fts.rowid
+                 WHERE command_history_fts MATCH ?
+                 GROUP BY invocation_key
+             ) m ON m.invocation_key = COALESCE(h.invocation_id, -h.id)
+             WHERE {}
+             ORDER BY m.rank, h.timestamp DESC",
+            ENTRY_COLUMNS, ENTRY_SOURCE, conditions
+        );
+        let mut params: Vec<libsql::Value> = vec![fts_query.into()];
+        params.extend(filter_params);
+        if let Some(limit) = filter.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(limit.into());
+        }
+
+        let mut rows = conn.query(&sql, params).await?;
+
+        let mut commands = Vec::new();
+        while let Some(row) = rows.next().await? {
+            commands.push(entry_from_row(&row)?);
+        }
+
+        Ok(commands)
+    }
+}
+
+/// Translates a user search query into FTS5 syntax.
+///
+/// Bare terms are quoted so punctuation such as `-` or `.` cannot produce
+/// syntax errors, a trailing `*` makes a term a prefix, `"..."` is kept as a
+/// phrase, `AND`/`OR`/`NOT` pass through and `-term` excludes a term.
+/// Returns `None` when the query has no searchable terms.
+fn build_fts_query(query: &str) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut negated = false;
+        let mut term = String::new();
+        let mut phrase = false;
+
+        if c == '-' {
+            negated = true;
+            chars.next();
+        }
+        if chars.peek() == Some(&'"') {
+            phrase = true;
+            chars.next();
+            for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+                term.push(c);
+            }
+        }
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            term.push(c);
+            chars.next();
+        }
+
+        if !phrase && matches!(term.as_str(), "AND" | "OR" | "NOT") {
+            // Operators need a term on their left
+            if !parts.is_empty() && !matches!(parts.last().map(String::as_str), Some("AND" | "OR" | "NOT")) {
+                parts.push(term);
+            }
+            continue;
+        }
+
+        let prefix = term.ends_with('*');
+        let term = term.trim_end_matches('*');
+        if term.trim().is_empty() {
+            continue;
+        }
+
+        let mut quoted = format!("\"{}\"", term.replace('"', "\"\""));
+        if prefix {
+            quoted.push('*');
+        }
+        if negated {
+            if parts.is_empty() {
+                // FTS5 has no unary NOT, so a leading exclusion is dropped
+                continue;
+            }
+            if matches!(parts.last().map(String::as_str), Some("AND" | "OR" | "NOT")) {
+                parts.pop();
+            }
+            parts.push("NOT".to_string());
+        }
+        parts.push(quoted);
+    }
+
+    while matches!(parts.last().map(String::as_str), Some("AND" | "OR" | "NOT")) {
+        parts.pop();
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Inserts a command line and its segments, returning the new invocation id.
+/// The invocation takes its timestamp and session from the first segment.
+async fn insert_invocation(
+    conn: &libsql::Connection,
+    line: &str,
+    entries: &[CommandHistoryEntry],
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(first) = entries.first() else {
+        return Err("No command segments to log".into());
+    };
+
+    conn.execute(
+        "INSERT INTO invocations (timestamp, line, session_id) VALUES (?, ?, ?)",
+        (first.timestamp.to_rfc3339().as_str(), line, first.session_id),
+    )
+    .await?;
+    let invocation_id = conn.last_insert_rowid();
+
+    for (position, entry) in entries.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO command_history (timestamp, command, binary, env, wrappers, user, pwd, session_id, exit_code, duration_ms, finished_at, invocation_id, position) 
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                entry.timestamp.to_rfc3339().as_str(),
+                entry.command.as_str(),
+                entry.binary.as_str(),
+                entry.env.as_deref(),
+                entry.wrappers.as_deref(),
+                entry.user.as_str(),
+                entry.pwd.as_str(),
+                entry.session_id,
+                entry.exit_code,
+                entry.duration_ms,
+                entry.finished_at.map(|t| t.to_rfc3339()),
+                invocation_id,
+                position as i64,
+            ),
+        )
+        .await?;
+    }
+
+    Ok(invocation_id)
+}
+
+/// Deletes everything listed in the temporary `prune_targets` table of
+/// (entry_id, invocation_id, session_id) rows, then drops the table.
+async fn delete_prune_targets(conn: &libsql::Connection) -> Result<PruneStats, Box<dyn std::error::Error + Send + Sync>> {
+    let commands = count(conn, "SELECT COUNT(DISTINCT COALESCE('i' || invocation_id, 'e' || entry_id)) FROM prune_targets").await?;
+    let segments = conn
+        .execute(
+            "DELETE FROM command_history WHERE id IN (SELECT entry_id FROM prune_targets)
+                OR invocation_id IN (SELECT invocation_id FROM prune_targets)",
+            (),
+        )
+        .await? as usize;
+    conn.execute("DELETE FROM invocations WHERE id IN (SELECT invocation_id FROM prune_targets)", ())
+        .await?;
+    let sessions = conn
+        .execute(
+            "DELETE FROM sessions WHERE id IN (SELECT session_id FROM prune_targets)
+                AND NOT EXISTS (SELECT 1 FROM command_history h WHERE h.session_id = sessions.id)",
+            (),
+        )
+        .await? as usize;
+    conn.execute("DROP TABLE temp.prune_targets", ()).await?;
+
+    Ok(PruneStats { commands, segments, sessions })
+}
+
+async fn count(conn: &libsql::Connection, sql: &str) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut rows = conn.query(sql, ()).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get::<i64>(0)? as usize),
+        None => Ok(0),
+    }
+}
+
+/// Builds the `WHERE` clause for a filter over [`ENTRY_SOURCE`], selecting the
+/// first segment of each invocation as its representative row.
+fn filter_conditions(filter: &HistoryFilter) -> (String, Vec<libsql::Value>) {
+    let mut conditions = vec!["h.position = 0".to_string()];
+    let mut params: Vec<libsql::Value> = Vec::new();
+
+    if let Some(query) = &filter.query {
+        conditions.push("COALESCE(i.line, h.command) LIKE ? ESCAPE '\\'".to_string());
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        params.push(format!("%{}%", escaped).into());
+    }
+    if let Some(cwd) = &filter.cwd {
+        conditions.push("h.pwd = ?".to_string());
+        params.push(cwd.clone().into());
+    }
+    if let Some(session_id) = filter.session_id {
+        conditions.push("h.session_id = ?".to_string());
+        params.push(session_id.into());
+    }
+    if let Some(binary) = &filter.binary {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM command_history s WHERE s.binary = ? \
+             AND (s.id = h.id OR s.invocation_id = h.invocation_id))"
+                .to_string(),
+        );
+        params.push(binary.clone().into());
+    }
+    if let Some(user) = &filter.user {
+        conditions.push("h.user = ?".to_string());
+        params.push(user.clone().into());
+    }
+    if let Some(host) = &filter.host {
+        conditions.push(
+            "(sess.hostname = ? OR EXISTS (SELECT 1 FROM hosts WHERE hosts.id = sess.host_id AND hosts.machine_id = ?))"
+                .to_string(),
+        );
+        params.push(host.clone().into());
+        params.push(host.clone().into());
+    }
+    if let Some(since) = filter.since {
+        conditions.push("h.timestamp >= ?".to_string());
+        params.push(since.to_rfc3339().into());
+    }
+    if let Some(until) = filter.until {
+        conditions.push("h.timestamp <= ?".to_string());
+        params.push(until.to_rfc3339().into());
+    }
+
+    (conditions.join(" AND "), params)
+}
+
+
+/// Columns read by [`entry_from_row`], in order.
+const ENTRY_COLUMNS: &str = "h.id, h.timestamp, h.command, h.binary, h.env, h.wrappers, h.user, h.pwd, h.session_id, \
+     h.exit_code, h.duration_ms, h.finished_at, h.invocation_id, h.position, i.line, sess.hostname";
+
+/// Tables [`ENTRY_COLUMNS`] are selected from.
+const ENTRY_SOURCE: &str = "command_history h LEFT JOIN invocations i ON i.id = h.invocation_id \
+     LEFT JOIN sessions sess ON sess.id = h.session_id";
+
+fn entry_from_row(row: &libsql::Row) -> Result<CommandHistoryEntry, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(CommandHistoryEntry {
+        id: Some(row.get::<i64>(0)?),
+        timestamp: DateTime::parse_from_rfc3339(&row.get::<String>(1)?)?.with_timezone(&Utc),
+        command: row.get::<String>(2)?,
+        binary: row.get::<String>(3)?,
+        env: row.get::<Option<String>>(4)?,
+        wrappers: row.get::<Option<String>>(5)?,
+        user: row.get::<String>(6)?,
+        pwd: row.get::<String>(7)?,
+        session_id: row.get::<i64>(8)?,
+        exit_code: row.get::<Option<i32>>(9)?,
+        duration_ms: row.get::<Option<i64>>(10)?,
+        finished_at: row
+            .get::<Option<String>>(11)?
+            .map(|t| DateTime::parse_from_rfc3339(&t).map(|t| t.with_timezone(&Utc)))
+            .transpose()?,
+        invocation_id: row.get::<Option<i64>>(12)?,
+        position: row.get::<i64>(13)?,
+        line: row.get::<Option<String>>(14)?,
+        hostname: row.get::<Option<String>>(15)?,
+    })
+}
+
+/// Reads rows in the form they are synced in: all of them, or only the ones
+/// being sent when `claimed`.
+async fn sync_rows(
+    conn: &libsql::Connection,
+    claimed: bool,
+) -> Result<SyncChanges, Box<dyn std::error::Error + Send + Sync>> {
+    let claimed = |alias: &str| {
+        if claimed {
+            format!("WHERE {}.synced = 2", alias)
+        } else {
+            String::new()
+        }
+    };
+    Ok(SyncChanges {
+        sessions: value_rows(
+            conn,
+            &format!(
+                "SELECT s.uuid, s.key, s.started_at, s.stopped_at, s.ended_at, s.exit_reason, s.name, s.hostname,
+                        hosts.machine_id, s.strategy, s.shell_pid, s.tty, s.shell, s.terminal
+                 FROM sessions s LEFT JOIN hosts ON hosts.id = s.host_id {}",
+                claimed("s")
+            ),
+            (),
+        )
+        .await?,
+        invocations: value_rows(
+            conn,
+            &format!(
+                "SELECT i.uuid, s.uuid, i.timestamp, i.line
+                 FROM invocations i JOIN sessions s ON s.id = i.session_id {}",
+                claimed("i")
+            ),
+            (),
+        )
+        .await?,
+        commands: value_rows(
+            conn,
+            &format!(
+                "SELECT h.uuid, s.uuid, i.uuid, h.position, h.timestamp, h.command, h.binary, h.env, h.wrappers,
+                        h.user, h.pwd, h.exit_code, h.duration_ms, h.finished_at
+                 FROM command_history h JOIN sessions s ON s.id = h.session_id
+                 LEFT JOIN invocations i ON i.id = h.invocation_id {}",
+                claimed("h")
+            ),
+            (),
+        )
+        .await?,
+        deleted: value_rows(conn, "SELECT uuid FROM sync_tombstones", ())
+            .await?
+            .into_iter()
+            .filter_map(|row| match row.into_iter().next() {
+                Some(libsql::Value::Text(uuid)) => Some(uuid),
+                _ => None,
+            })
+            .collect(),
+    })
+}
+
+/// Reads every row of a query as plain values.
+pub async fn value_rows(
+    conn: &libsql::Connection,
+    sql: &str,
+    params: impl libsql::params::IntoParams,
+) -> Result<Vec<Vec<libsql::Value>>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut rows = conn.query(sql, params).await?;
+    let columns = rows.column_count();
+    let mut values = Vec::new();
+    while let Some(row) = rows.next().await? {
+        values.push((0..columns).map(|i| row.get_value(i)).collect::<Result<Vec<_>, _>>()?);
+    }
+    Ok(values)
+}
+
+fn text_value(value: &libsql::Value) -> Option<&str> {
+    match value {
+        libsql::Value::Text(text) => Some(text),
+        _ => None,
+    }
+}
+
+/// The key to store a session from another machine under. It is suffixed
+/// with the host so that it can never be mistaken for a terminal here.
+async fn synced_session_key(
+    conn: &libsql::Connection,
+    key: &str,
+    hostname: Option<&str>,
+    uuid: &libsql::Value,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let key = format!("{}@{}", key, hostname.unwrap_or("unknown"));
+    let mut rows = conn.query("SELECT 1 FROM sessions WHERE key = ?", [key.as_str()]).await?;
+    if rows.next().await?.is_none() {
+        return Ok(key);
+    }
+    Ok(format!("{}#{}", key, text_value(uuid).unwrap_or_default()))
+}
+
+/// The id of the host with this machine id, or failing that hostname,
+/// adding it to `hosts` when it is new.
+async fn host_id(
+    conn: &libsql::Connection,
+    hostname: &str,
+    machine_id: Option<&str>,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let now = Utc::now().to_rfc3339();
+
+    let existing = {
+        let mut rows = match machine_id {
+            // A host recorded before machine ids were is claimed by its name
+            Some(machine_id) => conn
+                .query(
+                    "SELECT id FROM hosts WHERE machine_id = ?1
+                     UNION ALL SELECT id FROM hosts WHERE machine_id IS NULL AND hostname = ?2
+                     LIMIT 1",
+                    (machine_id, hostname),
+                )
+                .await?,
+            None => {
+                conn.query(
+                    "SELECT id FROM hosts WHERE machine_id IS NULL AND hostname = ? LIMIT 1",
+                    [hostname],
+                )
+                .await?
+            }
+        };
+        match rows.next().await? {
+            Some(row) => Some(row.get::<i64>(0)?),
+            None => None,
+        }
+    };
+
+    if let Some(id) = existing {
+        conn.execute(
+            "UPDATE hosts SET hostname = ?, machine_id = COALESCE(machine_id, ?), last_seen = ? WHERE id = ?",
+            (hostname, machine_id, now.as_str(), id),
+        )
+        .await?;
+        return Ok(id);
+    }
+
+    conn.execute(
+        "INSERT INTO hosts (machine_id, hostname, first_seen, last_seen) VALUES (?, ?, ?, ?)",
+        (machine_id, hostname, now.as_str(), now.as_str()),
+    )
+    .await?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// The database location from the config, where `RECALL_DB_PATH` also
+/// overrides it.
+pub fn get_db_file_path() -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(config::get()?.database.resolved_path())
 }
@@ -0,0 +1,201 @@
+//! Encryption at rest for the history database.
+//!
+//! Encrypted databases use the AES-256 page cipher of libsql's `encryption`
+//! feature, so every query works on them exactly as on a plain file. The
+//! key comes from `RECALL_DB_KEY`, from the file in `database.key_file`, or
+//! failing both from a passphrase typed at the terminal. The shell
+//! integration records commands without a terminal to ask on, so it needs
+//! one of the first two.
+
+use crate::config;
+use libsql::{Cipher, EncryptionConfig};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// Environment variable holding the key itself.
+pub const KEY_VARIABLE: &str = "RECALL_DB_KEY";
+
+/// The first bytes of every unencrypted SQLite file. An encrypted file
+/// starts with ciphertext instead.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Whether this recall was built with encryption support.
+pub fn is_supported() -> bool {
+    cfg!(feature = "encryption")
+}
+
+/// Whether the database at `path` is encrypted. A missing or empty file is not.
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    file.take(SQLITE_HEADER.len() as u64).read_to_end(&mut header)?;
+    Ok(!header.is_empty() && header != SQLITE_HEADER)
+}
+
+/// The key set up for use without a terminal, from `RECALL_DB_KEY` or the
+/// configured key file.
+pub fn configured_key() -> Result<Option<Vec<u8>>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(key) = std::env::var_os(KEY_VARIABLE).filter(|key| !key.is_empty()) {
+        return Ok(Some(key.into_encoded_bytes()));
+    }
+    let Some(path) = config::get()?.database.resolved_key_file() else {
+        return Ok(None);
+    };
+    let mut key = std::fs::read(&path)
+        .map_err(|e| format!("Could not read the database key from {}: {}", path.display(), e))?;
+    // Editors end files with a newline that is not part of the key
+    while key.last().is_some_and(|byte| *byte == b'\n' || *byte == b'\r') {
+        key.pop();
+    }
+    if key.is_empty() {
+        return Err(format!("The database key file {} is empty", path.display()).into());
+    }
+    Ok(Some(key))
+}
+
+/// Whether a passphrase can be asked for: someone is at a terminal and can
+/// see the question.
+pub fn can_prompt() -> bool {
+    unsafe { libc::isatty(0) == 1 && libc::isatty(2) == 1 }
+}
+
+/// Asks for a passphrase on the terminal without echoing it.
+pub fn prompt_passphrase(prompt: &str) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let tty = File::options().read(true).write(true).open("/dev/tty")?;
+    let fd = std::os::unix::io::AsRawFd::as_raw_fd(&tty);
+    (&tty).write_all(prompt.as_bytes())?;
+
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut silent = saved;
+    silent.c_lflag &= !libc::ECHO;
+    silent.c_lflag |= libc::ECHONL;
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) };
+    let mut line = Vec::new();
+    let read = BufReader::new(&tty).read_until(b'\n', &mut line);
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+    read?;
+
+    while line.last().is_some_and(|byte| *byte == b'\n' || *byte == b'\r') {
+        line.pop();
+    }
+    if line.is_empty() {
+        return Err("No passphrase given".into());
+    }
+    Ok(line)
+}
+
+/// How libsql opens a database encrypted with `key`.
+pub fn encryption_config(key: Vec<u8>) -> EncryptionConfig {
+    EncryptionConfig::new(Cipher::Aes256Cbc, key.into())
+}
+
+/// `key` as the hex string taken by the `hexkey` and `hexrekey` pragmas,
+/// which use the bytes as they are, like the key given to libsql.
+pub fn hex_key(key: &[u8]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The error for an encrypted database that cannot be opened without a key.
+pub fn missing_key_error(path: &Path) -> Box<dyn std::error::Error + Send + Sync> {
+    if !is_supported() {
+        return format!(
+            "The history database at {} is encrypted, but this recall was built without encryption support; \
+             rebuild it with `cargo install --features encryption`",
+            path.display()
+        )
+        .into();
+    }
+    format!(
+        "The history database at {} is encrypted and no key was given: set {} to its key, or database.key_file \
+         to a file holding it",
+        path.display(),
+        KEY_VARIABLE
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("recall-test-{}.db", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn missing_and_empty_files_are_not_encrypted() {
+        let path = temp_path();
+        assert!(!is_encrypted(&path).unwrap());
+        std::fs::write(&path, b"").unwrap();
+        assert!(!is_encrypted(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn plain_databases_are_not_encrypted() {
+        let path = temp_path();
+        let db = libsql::Builder::new_local(&path).build().await.unwrap();
+        db.connect().unwrap().execute("CREATE TABLE t (x INTEGER)", ()).await.unwrap();
+        drop(db);
+        assert!(!is_encrypted(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn other_files_count_as_encrypted() {
+        // Ciphertext has no recognisable header, so anything that is not
+        // SQLite is taken for an encrypted database
+        let path = temp_path();
+        std::fs::write(&path, b"\x8f\x12\x00random bytes, not a database").unwrap();
+        assert!(is_encrypted(&path).unwrap());
+        std::fs::write(&path, b"SQLite").unwrap();
+        assert!(is_encrypted(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hex_key_uses_the_bytes_as_they_are() {
+        assert_eq!(hex_key(b"Ab\x00\xff"), "416200ff");
+        assert_eq!(hex_key(b""), "");
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn encrypts_and_decrypts_in_place() {
+        use crate::db::DatabaseManager;
+
+        let path = temp_path();
+        let flags = libsql::OpenFlags::default();
+        let db = DatabaseManager::open(&path).await.unwrap();
+        db.set_sync_value("dir", "/srv/shared").await.unwrap();
+        db.rekey(Some(b"correct horse")).await.unwrap();
+        drop(db);
+        assert!(is_encrypted(&path).unwrap());
+        assert!(!std::fs::read(&path).unwrap().windows(11).any(|bytes| bytes == b"/srv/shared"));
+
+        let error = DatabaseManager::open_with_key(&path, flags, Some(b"battery staple".to_vec()))
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with("Wrong key for the encrypted database"), "{}", error);
+
+        let db = DatabaseManager::open_with_key(&path, flags, Some(b"correct horse".to_vec())).await.unwrap();
+        assert_eq!(db.sync_value("dir").await.unwrap().as_deref(), Some("/srv/shared"));
+        db.rekey(None).await.unwrap();
+        drop(db);
+        assert!(!is_encrypted(&path).unwrap());
+
+        let db = DatabaseManager::open_with_key(&path, flags, None).await.unwrap();
+        assert_eq!(db.sync_value("dir").await.unwrap().as_deref(), Some("/srv/shared"));
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! Decides which commands are left out of the history, from the `[ignore]`
+//! section of the config.
+
+use crate::config::{expand_home, IgnoreConfig};
+use glob::Pattern;
+use regex::Regex;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a command was not recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IgnoreReason {
+    LeadingSpace,
+    Pattern(String),
+    Regex(String),
+    Binary(String),
+    Directory(String),
+    Duplicate,
+}
+
+impl fmt::Display for IgnoreReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgnoreReason::LeadingSpace => write!(f, "it starts with a space (ignore.leading_space)"),
+            IgnoreReason::Pattern(p) => write!(f, "it matches the pattern '{}' (ignore.patterns)", p),
+            IgnoreReason::Regex(r) => write!(f, "it matches the regex '{}' (ignore.regexes)", r),
+            IgnoreReason::Binary(b) => write!(f, "it runs '{}' (ignore.binaries)", b),
+            IgnoreReason::Directory(d) => write!(f, "it was run under '{}' (ignore.directories)", d),
+            IgnoreReason::Duplicate => {
+                write!(f, "it repeats the previous command (ignore.consecutive_duplicates)")
+            }
+        }
+    }
+}
+
+pub struct IgnoreRules {
+    leading_space: bool,
+    consecutive_duplicates: bool,
+    patterns: Vec<Pattern>,
+    regexes: Vec<Regex>,
+    binaries: Vec<String>,
+    directories: Vec<PathBuf>,
+}
+
+impl IgnoreRules {
+    pub fn from_config(config: &IgnoreConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let patterns = config
+            .patterns
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                Pattern::new(p).map_err(|e| format!("'ignore.patterns' entry {} is not a valid glob: {}", i + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        let regexes = config
+            .regexes
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                Regex::new(r).map_err(|e| format!("'ignore.regexes' entry {} is not a valid regex: {}", i + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(IgnoreRules {
+            leading_space: config.leading_space,
+            consecutive_duplicates: config.consecutive_duplicates,
+            patterns,
+            regexes,
+            binaries: config.binaries.clone(),
+            directories: config.directories.iter().map(|d| normalize_dir(d)).collect(),
+        })
+    }
+
+    pub fn ignores_duplicates(&self) -> bool {
+        self.consecutive_duplicates
+    }
+
+    /// Checks every rule that only needs the command itself: the line as
+    /// typed, the directory it ran in and the binaries it runs.
+    pub fn check(&self, line: &str, pwd: &Path, binaries: &[String]) -> Option<IgnoreReason> {
+        if self.leading_space && line.starts_with(' ') {
+            return Some(IgnoreReason::LeadingSpace);
+        }
+
+        let trimmed = line.trim();
+        if let Some(pattern) = self.patterns.iter().find(|p| p.matches(trimmed)) {
+            return Some(IgnoreReason::Pattern(pattern.as_str().to_string()));
+        }
+        if let Some(regex) = self.regexes.iter().find(|r| r.is_match(trimmed)) {
+            return Some(IgnoreReason::Regex(regex.as_str().to_string()));
+        }
+        if let Some(binary) = binaries.iter().find(|b| self.binaries.contains(b)) {
+            return Some(IgnoreReason::Binary(binary.clone()));
+        }
+        if let Some(dir) = self.directories.iter().find(|d| pwd.starts_with(d)) {
+            return Some(IgnoreReason::Directory(dir.display().to_string()));
+        }
+
+        None
+    }
+}
+
+/// Expands a leading `~` and resolves symlinks where possible, so that
+/// directories compare equal to the canonical working directory.
+fn normalize_dir(dir: &str) -> PathBuf {
+    let expanded = expand_home(dir);
+    std::fs::canonicalize(&expanded).unwrap_or(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(config: IgnoreConfig) -> IgnoreRules {
+        IgnoreRules::from_config(&config).unwrap()
+    }
+
+    fn check(rules: &IgnoreRules, line: &str) -> Option<IgnoreReason> {
+        rules.check(line, Path::new("/home/alice"), &[])
+    }
+
+    #[test]
+    fn default_patterns_skip_recall_itself() {
+        let rules = rules(IgnoreConfig::default());
+        assert_eq!(check(&rules, "recall"), Some(IgnoreReason::Pattern("recall".to_string())));
+        assert_eq!(check(&rules, "recall search git"), Some(IgnoreReason::Pattern("recall *".to_string())));
+        assert_eq!(check(&rules, "recallable"), None);
+        assert_eq!(check(&rules, "echo recall"), None);
+        assert_eq!(check(&rules, "git status"), None);
+    }
+
+    #[test]
+    fn leading_space_is_skipped_when_enabled() {
+        assert_eq!(check(&rules(IgnoreConfig::default()), " export TOKEN=x"), Some(IgnoreReason::LeadingSpace));
+
+        let config = IgnoreConfig { leading_space: false, ..IgnoreConfig::default() };
+        assert_eq!(check(&rules(config), " export TOKEN=x"), None);
+    }
+
+    #[test]
+    fn configured_patterns_and_regexes_match_the_trimmed_line() {
+        let config = IgnoreConfig {
+            leading_space: false,
+            patterns: vec!["ls*".to_string(), "cd".to_string()],
+            regexes: vec![r"^vault\s+(read|write)".to_string()],
+            ..IgnoreConfig::default()
+        };
+        let rules = rules(config);
+        assert_eq!(check(&rules, "  ls -la  "), Some(IgnoreReason::Pattern("ls*".to_string())));
+        assert_eq!(check(&rules, "cd"), Some(IgnoreReason::Pattern("cd".to_string())));
+        assert_eq!(check(&rules, "cd /tmp"), None);
+        assert_eq!(
+            check(&rules, "vault read secret/db"),
+            Some(IgnoreReason::Regex(r"^vault\s+(read|write)".to_string()))
+        );
+        assert_eq!(check(&rules, "vault status"), None);
+        // The config replaces the default patterns
+        assert_eq!(check(&rules, "recall search"), None);
+    }
+
+    #[test]
+    fn binaries_and_directories() {
+        let config = IgnoreConfig {
+            binaries: vec!["pass".to_string()],
+            directories: vec!["/nonexistent/secrets".to_string()],
+            ..IgnoreConfig::default()
+        };
+        let rules = rules(config);
+        let binaries = ["git".to_string(), "pass".to_string()];
+        assert_eq!(
+            rules.check("git log | pass insert x", Path::new("/tmp"), &binaries),
+            Some(IgnoreReason::Binary("pass".to_string()))
+        );
+        assert_eq!(
+            rules.check("ls", Path::new("/nonexistent/secrets/aws"), &[]),
+            Some(IgnoreReason::Directory("/nonexistent/secrets".to_string()))
+        );
+        assert_eq!(rules.check("ls", Path::new("/nonexistent/secretsdir"), &[]), None);
+    }
+
+    #[test]
+    fn invalid_globs_and_regexes_are_errors() {
+        let config = IgnoreConfig { patterns: vec!["[".to_string()], ..IgnoreConfig::default() };
+        assert!(IgnoreRules::from_config(&config).is_err());
+        let config = IgnoreConfig { regexes: vec!["(".to_string()], ..IgnoreConfig::default() };
+        assert!(IgnoreRules::from_config(&config).is_err());
+    }
+}
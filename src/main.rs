@@ -1,8 +1,20 @@
 use clap::{Parser, Subcommand};
+use output::OutputFormat;
+use std::path::PathBuf;
 use std::process::exit;
 
 mod commands;
+mod config;
 mod db;
+mod encryption;
+mod ignore;
+mod migrations;
+mod output;
+mod parser;
+mod redact;
+mod runbook;
+mod sync;
+mod timespec;
 mod ui;
 
 #[derive(Parser)]
@@ -15,19 +27,348 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Record a command line in the history
     Log {
+        /// The command line as typed in the shell
+        #[arg(required_unless_present = "finish", conflicts_with = "finish", allow_hyphen_values = true)]
+        command: Option<String>,
+        /// Print the id of the logged command so it can be finished later
+        #[arg(long)]
+        start: bool,
+        /// Mark the command started with the given id as finished
+        #[arg(long, value_name = "ID", requires = "exit_code")]
+        finish: Option<i64>,
+        /// Exit status of the finished command
+        #[arg(long = "exit", value_name = "CODE", requires = "finish", allow_negative_numbers = true)]
+        exit_code: Option<i32>,
+    },
+    /// Print matching commands without opening the interface
+    Search {
+        /// Text to look for in the command line
+        query: Option<String>,
+        #[command(flatten)]
+        filter: commands::FilterArgs,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+    },
+    /// Write history out for backups or other tools
+    Export {
+        #[command(flatten)]
+        filter: commands::FilterArgs,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = commands::ExportFormat::Json)]
+        format: commands::ExportFormat,
+    },
+    /// Choose a command from history and print it
+    ///
+    /// Enter exits with status 0 and Tab with status 10, so shell key bindings
+    /// can tell running the command apart from editing it. Cancelling exits
+    /// with status 1 and prints nothing.
+    Pick {
+        /// Start with this search
+        #[arg(long)]
+        query: Option<String>,
+        /// Write the command to this file instead of stdout
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Print the shell integration, e.g. `eval "$(recall init zsh)"`
+    Init {
+        #[arg(value_enum)]
+        shell: commands::Shell,
+        #[command(flatten)]
+        keys: commands::InitArgs,
+    },
+    /// Import history from another shell
+    Import {
+        /// Where to import from
+        #[arg(value_enum)]
+        source: commands::ImportSource,
+        /// History file to read instead of the shell's default location
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+    /// Delete specific commands, e.g. one that exposed a password
+    Forget {
+        #[command(flatten)]
+        target: commands::ForgetTarget,
+        /// Show what would be forgotten without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Delete old or unwanted commands from the history
+    Prune {
+        #[command(flatten)]
+        args: commands::PruneArgs,
+    },
+    /// Scrub secrets from commands already in the history
+    Redact {
+        /// Show what would be redacted without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List, inspect and name terminal sessions
+    #[command(alias = "session")]
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommands,
+    },
+    /// Exchange history with the sync server or shared folder set in the config
+    Sync {
+        /// Sync through this shared folder instead, e.g. one kept in step by Syncthing
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
+        /// Print nothing unless the sync fails
+        #[arg(long)]
+        quiet: bool,
+    },
+    /// Merge the history of another recall database into this one
+    Merge {
+        /// The database file to merge, which is only read
+        path: PathBuf,
+    },
+    /// Read and change settings in config.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Check the ignore rules from the config
+    Ignore {
+        #[command(subcommand)]
+        command: IgnoreCommands,
+    },
+    /// Inspect and maintain the history database
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommands {
+    /// Print every session, newest first
+    List {
+        /// Only sessions started after this time (e.g. 2h, yesterday, 2024-05-01)
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+    },
+    /// Print a session's details and every command run in it
+    Show {
+        /// Session id, session key, or `current` for this terminal
+        session: String,
+    },
+    /// Label a session; an empty label removes it
+    Name {
+        /// Session id, session key, or `current` for this terminal
+        session: String,
+        label: String,
+    },
+    /// Print the session key of this terminal
+    Current,
+    /// Record that this terminal's shell started (run by `recall init`)
+    Start,
+    /// Record that this terminal's shell exited (run by `recall init`)
+    End {
+        /// Why the shell exited, e.g. `exit` or `hangup`
+        #[arg(long, default_value = "exit")]
+        reason: String,
+    },
+    /// Print a session's commands as a replayable script or a runbook
+    Export {
+        /// Session id, session key, or `current` for this terminal
+        session: String,
+        #[arg(long = "as", value_enum, default_value_t = runbook::RunbookFormat::Sh)]
+        format: runbook::RunbookFormat,
+        /// Note each command's exit status next to its timestamp
+        #[arg(long)]
+        exit_codes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print every setting, including defaults and environment overrides
+    Show,
+    /// Print the location of the config file
+    Path,
+    /// Print one setting, e.g. `recall config get ui.history_limit`
+    Get {
+        key: String,
+    },
+    /// Change one setting in the config file
+    Set {
+        key: String,
+        /// The new value, written as in TOML; text is taken as a string
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+    },
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+}
+
+#[derive(Subcommand)]
+enum IgnoreCommands {
+    /// Show whether a command would be recorded, and which rule skips it
+    Test {
+        /// The command line as it would be typed
+        #[arg(allow_hyphen_values = true)]
         command: String,
     },
 }
 
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Apply pending schema migrations
+    Migrate,
+    /// Show the schema version and any pending migrations
+    Status,
+    /// Encrypt the database with the configured key or a new passphrase
+    Encrypt,
+    /// Store the database unencrypted again
+    Decrypt,
+}
+
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
 
     match &cli.command {
-        Some(Commands::Log { command }) => {
-            if let Err(e) = commands::log_command(command).await {
-                eprintln!("Error logging command: {}", e);
+        Some(Commands::Log { command, start, finish, exit_code }) => {
+            if let (Some(id), Some(code)) = (finish, exit_code) {
+                if let Err(e) = commands::finish_command(*id, *code).await {
+                    eprintln!("Error finishing command: {}", e);
+                    exit(1);
+                }
+            } else if let Some(command) = command {
+                match commands::log_command(command).await {
+                    Ok(Some(id)) => {
+                        if *start {
+                            println!("{}", id);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("Error logging command: {}", e);
+                        exit(1);
+                    }
+                }
+            }
+        }
+        Some(Commands::Search { query, filter, format }) => {
+            if let Err(e) = commands::search_commands(query.as_deref(), filter, *format).await {
+                eprintln!("Error searching command history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Export { filter, format }) => {
+            if let Err(e) = commands::export_commands(filter, *format).await {
+                eprintln!("Error exporting command history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Pick { query, output }) => {
+            match commands::pick_command(query.as_deref(), output.as_deref()).await {
+                Ok(Some(ui::PickAction::Execute)) => {}
+                Ok(Some(ui::PickAction::Edit)) => exit(commands::EDIT_EXIT_CODE),
+                Ok(None) => exit(1),
+                Err(e) => {
+                    eprintln!("Error picking command: {}", e);
+                    exit(2);
+                }
+            }
+        }
+        Some(Commands::Init { shell, keys }) => commands::print_init(*shell, keys),
+        Some(Commands::Import { source, file }) => {
+            if let Err(e) = commands::import_history(*source, file.as_deref()).await {
+                eprintln!("Error importing history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Forget { target, dry_run }) => {
+            if let Err(e) = commands::forget_commands(target, *dry_run).await {
+                eprintln!("Error forgetting commands: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Prune { args }) => {
+            if let Err(e) = commands::prune_history(args).await {
+                eprintln!("Error pruning command history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Redact { dry_run }) => {
+            if let Err(e) = commands::redact_history(*dry_run).await {
+                eprintln!("Error redacting command history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Sessions { command }) => {
+            let result = match command {
+                SessionsCommands::List { since, format } => commands::list_sessions(since.as_deref(), *format).await,
+                SessionsCommands::Show { session } => commands::show_session(session).await,
+                SessionsCommands::Name { session, label } => commands::name_session(session, label).await,
+                SessionsCommands::Export { session, format, exit_codes } => {
+                    commands::export_session(session, *format, *exit_codes).await
+                }
+                SessionsCommands::Start => commands::start_session().await,
+                SessionsCommands::End { reason } => commands::end_session(reason).await,
+                SessionsCommands::Current => {
+                    commands::print_current_session();
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Sync { dir, quiet }) => {
+            if let Err(e) = commands::sync_history(dir.as_deref(), *quiet).await {
+                eprintln!("Error syncing history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Merge { path }) => {
+            if let Err(e) = commands::merge_database(path).await {
+                eprintln!("Error merging history: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Config { command }) => {
+            let result = match command {
+                ConfigCommands::Show => commands::show_config(),
+                ConfigCommands::Path => {
+                    commands::print_config_path();
+                    Ok(())
+                }
+                ConfigCommands::Get { key } => commands::get_config_value(key),
+                ConfigCommands::Set { key, value } => commands::set_config_value(key, value),
+                ConfigCommands::Edit => commands::edit_config(),
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Ignore { command: IgnoreCommands::Test { command } }) => {
+            if let Err(e) = commands::test_ignore(command).await {
+                eprintln!("Error checking ignore rules: {}", e);
+                exit(1);
+            }
+        }
+        Some(Commands::Db { command }) => {
+            let result = match command {
+                DbCommands::Migrate => commands::migrate_database().await,
+                DbCommands::Status => commands::database_status().await,
+                DbCommands::Encrypt => commands::encrypt_database().await,
+                DbCommands::Decrypt => commands::decrypt_database().await,
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
                 exit(1);
             }
         }
@@ -0,0 +1,586 @@
+//! Versioned schema migrations.
+//!
+//! The schema version is stored in `PRAGMA user_version`. Each migration runs
+//! in its own transaction together with the version bump, so a database is
+//! always at exactly one known version. Migrations only ever move forward:
+//! add a new entry to [`MIGRATIONS`] instead of editing an existing one.
+
+use libsql::{Connection, TransactionBehavior};
+
+pub enum Step {
+    Sql(&'static str),
+    /// `ALTER TABLE ... ADD COLUMN`, skipped when the column already exists.
+    /// Databases created before versioning may already have some columns.
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+/// SQL for a random version 4 uuid, as a literal so it can be `concat!`ed
+/// into migration steps.
+macro_rules! random_uuid {
+    () => {
+        "lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-' \
+         || substr('89ab', 1 + abs(random() % 4), 1) || substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)))"
+    };
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS sessions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    key TEXT NOT NULL UNIQUE,
+                    started_at TEXT NOT NULL,
+                    stopped_at TEXT
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS command_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    command TEXT NOT NULL,
+                    binary TEXT NOT NULL,
+                    user TEXT NOT NULL,
+                    pwd TEXT NOT NULL,
+                    session_id INTEGER NOT NULL,
+                    FOREIGN KEY (session_id) REFERENCES sessions(id)
+                )",
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_timestamp ON command_history(timestamp DESC)"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_session_key ON sessions(key)"),
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "exit status and duration",
+        steps: &[
+            Step::AddColumn { table: "command_history", column: "exit_code", definition: "INTEGER" },
+            Step::AddColumn { table: "command_history", column: "duration_ms", definition: "INTEGER" },
+            Step::AddColumn { table: "command_history", column: "finished_at", definition: "TEXT" },
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "env assignments and wrappers",
+        steps: &[
+            Step::AddColumn { table: "command_history", column: "env", definition: "TEXT" },
+            Step::AddColumn { table: "command_history", column: "wrappers", definition: "TEXT" },
+        ],
+    },
+    Migration {
+        version: 4,
+        name: "invocations",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS invocations (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    line TEXT NOT NULL,
+                    session_id INTEGER NOT NULL,
+                    FOREIGN KEY (session_id) REFERENCES sessions(id)
+                )",
+            ),
+            Step::AddColumn {
+                table: "command_history",
+                column: "invocation_id",
+                definition: "INTEGER REFERENCES invocations(id)",
+            },
+            Step::AddColumn {
+                table: "command_history",
+                column: "position",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_invocation ON command_history(invocation_id)"),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "full-text search index",
+        steps: &[
+            Step::Sql(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS command_history_fts USING fts5(
+                    command,
+                    binary,
+                    pwd,
+                    content = 'command_history',
+                    content_rowid = 'id'
+                )",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS command_history_fts_insert AFTER INSERT ON command_history BEGIN
+                    INSERT INTO command_history_fts (rowid, command, binary, pwd)
+                    VALUES (new.id, new.command, new.binary, new.pwd);
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS command_history_fts_delete AFTER DELETE ON command_history BEGIN
+                    INSERT INTO command_history_fts (command_history_fts, rowid, command, binary, pwd)
+                    VALUES ('delete', old.id, old.command, old.binary, old.pwd);
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS command_history_fts_update AFTER UPDATE OF command, binary, pwd ON command_history BEGIN
+                    INSERT INTO command_history_fts (command_history_fts, rowid, command, binary, pwd)
+                    VALUES ('delete', old.id, old.command, old.binary, old.pwd);
+                    INSERT INTO command_history_fts (rowid, command, binary, pwd)
+                    VALUES (new.id, new.command, new.binary, new.pwd);
+                END",
+            ),
+            // Index rows logged before the index existed
+            Step::Sql("INSERT INTO command_history_fts (command_history_fts) VALUES ('rebuild')"),
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "invocation lookup by session",
+        steps: &[Step::Sql(
+            "CREATE INDEX IF NOT EXISTS idx_invocation_session ON invocations(session_id, timestamp)",
+        )],
+    },
+    Migration {
+        version: 7,
+        name: "session host",
+        steps: &[Step::AddColumn { table: "sessions", column: "hostname", definition: "TEXT" }],
+    },
+    Migration {
+        version: 8,
+        name: "session names",
+        steps: &[Step::AddColumn { table: "sessions", column: "name", definition: "TEXT" }],
+    },
+    Migration {
+        version: 9,
+        name: "session shell details",
+        steps: &[
+            Step::AddColumn { table: "sessions", column: "strategy", definition: "TEXT" },
+            Step::AddColumn { table: "sessions", column: "shell_pid", definition: "INTEGER" },
+            Step::AddColumn { table: "sessions", column: "tty", definition: "TEXT" },
+            Step::AddColumn { table: "sessions", column: "shell", definition: "TEXT" },
+            Step::AddColumn { table: "sessions", column: "terminal", definition: "TEXT" },
+        ],
+    },
+    Migration {
+        version: 10,
+        name: "session lifecycle",
+        steps: &[
+            Step::AddColumn { table: "sessions", column: "ended_at", definition: "TEXT" },
+            Step::AddColumn { table: "sessions", column: "exit_reason", definition: "TEXT" },
+            // Nothing recorded the end of older sessions; a shell that is
+            // still running reopens its session with the next command
+            Step::Sql("UPDATE sessions SET ended_at = COALESCE(stopped_at, started_at) WHERE ended_at IS NULL"),
+        ],
+    },
+    Migration {
+        version: 11,
+        name: "hosts",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS hosts (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    machine_id TEXT UNIQUE,
+                    hostname TEXT NOT NULL,
+                    first_seen TEXT NOT NULL,
+                    last_seen TEXT NOT NULL
+                )",
+            ),
+            Step::AddColumn { table: "sessions", column: "host_id", definition: "INTEGER REFERENCES hosts(id)" },
+            // Older sessions only have a hostname; the first session with a
+            // machine id claims the matching host
+            Step::Sql(
+                "INSERT INTO hosts (hostname, first_seen, last_seen)
+                 SELECT hostname, MIN(started_at), MAX(COALESCE(stopped_at, started_at)) FROM sessions
+                 WHERE hostname IS NOT NULL AND host_id IS NULL
+                   AND hostname NOT IN (SELECT hostname FROM hosts)
+                 GROUP BY hostname",
+            ),
+            Step::Sql(
+                "UPDATE sessions SET host_id = (SELECT id FROM hosts WHERE hosts.hostname = sessions.hostname)
+                 WHERE host_id IS NULL AND hostname IS NOT NULL",
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_session_host ON sessions(host_id)"),
+        ],
+    },
+    Migration {
+        version: 12,
+        name: "sync ids",
+        steps: &[
+            // Ids differ between machines, so synced rows are matched by uuid.
+            // `synced` is 0 for rows changed since the last sync, 1 for rows
+            // the server has and 2 while a sync is sending them.
+            Step::AddColumn { table: "sessions", column: "uuid", definition: "TEXT" },
+            Step::AddColumn { table: "invocations", column: "uuid", definition: "TEXT" },
+            Step::AddColumn { table: "command_history", column: "uuid", definition: "TEXT" },
+            Step::AddColumn { table: "sessions", column: "synced", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "invocations", column: "synced", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "command_history", column: "synced", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::Sql(concat!("UPDATE sessions SET uuid = ", random_uuid!(), " WHERE uuid IS NULL")),
+            Step::Sql(concat!("UPDATE invocations SET uuid = ", random_uuid!(), " WHERE uuid IS NULL")),
+            Step::Sql(concat!("UPDATE command_history SET uuid = ", random_uuid!(), " WHERE uuid IS NULL")),
+            Step::Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_session_uuid ON sessions(uuid)"),
+            Step::Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_invocation_uuid ON invocations(uuid)"),
+            Step::Sql("CREATE UNIQUE INDEX IF NOT EXISTS idx_command_uuid ON command_history(uuid)"),
+            Step::Sql(concat!(
+                "CREATE TRIGGER IF NOT EXISTS sessions_uuid_insert AFTER INSERT ON sessions WHEN new.uuid IS NULL BEGIN
+                    UPDATE sessions SET uuid = ",
+                random_uuid!(),
+                " WHERE id = new.id;
+                END"
+            )),
+            Step::Sql(concat!(
+                "CREATE TRIGGER IF NOT EXISTS invocations_uuid_insert AFTER INSERT ON invocations WHEN new.uuid IS NULL BEGIN
+                    UPDATE invocations SET uuid = ",
+                random_uuid!(),
+                " WHERE id = new.id;
+                END"
+            )),
+            Step::Sql(concat!(
+                "CREATE TRIGGER IF NOT EXISTS command_history_uuid_insert AFTER INSERT ON command_history WHEN new.uuid IS NULL BEGIN
+                    UPDATE command_history SET uuid = ",
+                random_uuid!(),
+                " WHERE id = new.id;
+                END"
+            )),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS sessions_sync_update AFTER UPDATE OF stopped_at, ended_at, exit_reason, name ON sessions BEGIN
+                    UPDATE sessions SET synced = 0 WHERE id = new.id;
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS invocations_sync_update AFTER UPDATE OF line ON invocations BEGIN
+                    UPDATE invocations SET synced = 0 WHERE id = new.id;
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS command_history_sync_update
+                 AFTER UPDATE OF command, env, exit_code, duration_ms, finished_at ON command_history BEGIN
+                    UPDATE command_history SET synced = 0 WHERE id = new.id;
+                END",
+            ),
+            Step::Sql("CREATE TABLE IF NOT EXISTS sync_state (key TEXT PRIMARY KEY, value TEXT NOT NULL)"),
+            // Deletions are only remembered once a sync server is set up
+            Step::Sql("CREATE TABLE IF NOT EXISTS sync_tombstones (uuid TEXT PRIMARY KEY)"),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS sessions_sync_delete AFTER DELETE ON sessions
+                 WHEN old.uuid IS NOT NULL AND EXISTS (SELECT 1 FROM sync_state WHERE key = 'remote') BEGIN
+                    INSERT OR IGNORE INTO sync_tombstones (uuid) VALUES (old.uuid);
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS invocations_sync_delete AFTER DELETE ON invocations
+                 WHEN old.uuid IS NOT NULL AND EXISTS (SELECT 1 FROM sync_state WHERE key = 'remote') BEGIN
+                    INSERT OR IGNORE INTO sync_tombstones (uuid) VALUES (old.uuid);
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS command_history_sync_delete AFTER DELETE ON command_history
+                 WHEN old.uuid IS NOT NULL AND EXISTS (SELECT 1 FROM sync_state WHERE key = 'remote') BEGIN
+                    INSERT OR IGNORE INTO sync_tombstones (uuid) VALUES (old.uuid);
+                END",
+            ),
+        ],
+    },
+    Migration {
+        version: 13,
+        name: "shared folder sync",
+        steps: &[
+            // Merging leaves most rows as they are, which must not mark them
+            // as changed, and a shared folder needs deletions remembered too
+            Step::Sql("DROP TRIGGER IF EXISTS sessions_sync_update"),
+            Step::Sql("DROP TRIGGER IF EXISTS invocations_sync_update"),
+            Step::Sql("DROP TRIGGER IF EXISTS command_history_sync_update"),
+            Step::Sql("DROP TRIGGER IF EXISTS sessions_sync_delete"),
+            Step::Sql("DROP TRIGGER IF EXISTS invocations_sync_delete"),
+            Step::Sql("DROP TRIGGER IF EXISTS command_history_sync_delete"),
+            Step::Sql(
+                "CREATE TRIGGER sessions_sync_update AFTER UPDATE OF stopped_at, ended_at, exit_reason, name ON sessions
+                 WHEN old.stopped_at IS NOT new.stopped_at OR old.ended_at IS NOT new.ended_at
+                    OR old.exit_reason IS NOT new.exit_reason OR old.name IS NOT new.name BEGIN
+                    UPDATE sessions SET synced = 0 WHERE id = new.id;
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER invocations_sync_update AFTER UPDATE OF line ON invocations
+                 WHEN old.line IS NOT new.line BEGIN
+                    UPDATE invocations SET synced = 0 WHERE id = new.id;
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER command_history_sync_update
+                 AFTER UPDATE OF command, env, exit_code, duration_ms, finished_at ON command_history
+                 WHEN old.command IS NOT new.command OR old.env IS NOT new.env OR old.exit_code IS NOT new.exit_code
+                    OR old.duration_ms IS NOT new.duration_ms OR old.finished_at IS NOT new.finished_at BEGIN
+                    UPDATE command_history SET synced = 0 WHERE id = new.id;
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER sessions_sync_delete AFTER DELETE ON sessions
+                 WHEN old.uuid IS NOT NULL AND EXISTS (SELECT 1 FROM sync_state WHERE key IN ('remote', 'dir')) BEGIN
+                    INSERT OR IGNORE INTO sync_tombstones (uuid) VALUES (old.uuid);
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER invocations_sync_delete AFTER DELETE ON invocations
+                 WHEN old.uuid IS NOT NULL AND EXISTS (SELECT 1 FROM sync_state WHERE key IN ('remote', 'dir')) BEGIN
+                    INSERT OR IGNORE INTO sync_tombstones (uuid) VALUES (old.uuid);
+                END",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER command_history_sync_delete AFTER DELETE ON command_history
+                 WHEN old.uuid IS NOT NULL AND EXISTS (SELECT 1 FROM sync_state WHERE key IN ('remote', 'dir')) BEGIN
+                    INSERT OR IGNORE INTO sync_tombstones (uuid) VALUES (old.uuid);
+                END",
+            ),
+        ],
+    },
+    Migration {
+        version: 14,
+        name: "import positions",
+        steps: &[
+            // Where an imported line without a timestamp sat in its history
+            // file, which tells repeats of a command apart on re-import
+            Step::AddColumn { table: "invocations", column: "source_position", definition: "INTEGER" },
+        ],
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub async fn current_version(conn: &Connection) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let mut rows = conn.query("PRAGMA user_version", ()).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get::<i64>(0)?),
+        None => Ok(0),
+    }
+}
+
+/// Migrations that have not been applied to the database yet, in order.
+pub async fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, Box<dyn std::error::Error + Send + Sync>> {
+    let version = current_version(conn).await?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > version).collect())
+}
+
+/// Applies every pending migration and returns the ones that were applied.
+pub async fn run(conn: &Connection) -> Result<Vec<&'static Migration>, Box<dyn std::error::Error + Send + Sync>> {
+    let version = current_version(conn).await?;
+    if version > latest_version() {
+        return Err(format!(
+            "Database schema version {} is newer than this version of recall supports ({}); please upgrade recall",
+            version,
+            latest_version()
+        )
+        .into());
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        if apply(conn, migration).await? {
+            applied.push(migration);
+        }
+    }
+
+    Ok(applied)
+}
+
+/// Runs one migration in an immediate transaction. Returns `false` when another
+/// process applied it first.
+async fn apply(conn: &Connection, migration: &Migration) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).await?;
+
+    if current_version(&tx).await? >= migration.version {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+
+    for step in migration.steps {
+        let result = match step {
+            Step::Sql(sql) => tx.execute(sql, ()).await.map(|_| ()).map_err(Into::into),
+            Step::AddColumn { table, column, definition } => add_column_if_missing(&tx, table, column, definition).await,
+        };
+        if let Err(e) = result {
+            tx.rollback().await?;
+            return Err(format!("Migration {} ({}) failed: {}", migration.version, migration.name, e).into());
+        }
+    }
+
+    tx.execute(&format!("PRAGMA user_version = {}", migration.version), ())
+        .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+async fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut rows = conn
+        .query(&format!("SELECT name FROM pragma_table_info('{}')", table), ())
+        .await?;
+
+    while let Some(row) = rows.next().await? {
+        if row.get::<String>(0)? == column {
+            return Ok(());
+        }
+    }
+    drop(rows);
+
+    conn.execute(
+        &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+        (),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DatabaseManager;
+    use libsql::Builder;
+    use std::path::PathBuf;
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("recall-test-{}.db", uuid::Uuid::new_v4()))
+    }
+
+    /// The schema as created by recall before migrations existed.
+    async fn create_unversioned_database(path: &PathBuf) {
+        let db = Builder::new_local(path).build().await.unwrap();
+        let conn = db.connect().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                key TEXT NOT NULL UNIQUE,
+                started_at TEXT NOT NULL,
+                stopped_at TEXT
+            );
+            CREATE TABLE command_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                command TEXT NOT NULL,
+                binary TEXT NOT NULL,
+                user TEXT NOT NULL,
+                pwd TEXT NOT NULL,
+                session_id INTEGER NOT NULL,
+                FOREIGN KEY (session_id) REFERENCES sessions(id)
+            );
+            CREATE INDEX idx_timestamp ON command_history(timestamp DESC);
+            CREATE INDEX idx_session_key ON sessions(key);
+            INSERT INTO sessions (key, started_at) VALUES ('term_pts_1', '2024-01-01T10:00:00+00:00');
+            INSERT INTO command_history (timestamp, command, binary, user, pwd, session_id)
+            VALUES ('2024-01-01T10:00:05+00:00', 'cargo build --release', 'cargo', 'alice', '/home/alice/recall', 1);",
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut rows = conn
+            .query(&format!("SELECT name FROM pragma_table_info('{}')", table), ())
+            .await
+            .unwrap();
+        let mut columns = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            columns.push(row.get::<String>(0).unwrap());
+        }
+        columns
+    }
+
+    #[test]
+    fn versions_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i64 + 1, "migration '{}'", migration.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn upgrades_database_created_by_unversioned_schema() {
+        let path = temp_db_path();
+        create_unversioned_database(&path).await;
+
+        let manager = DatabaseManager::open(&path).await.unwrap();
+        assert_eq!(manager.schema_version().await.unwrap(), latest_version());
+
+        let db = Builder::new_local(&path).build().await.unwrap();
+        let conn = db.connect().unwrap();
+        let history_columns = columns(&conn, "command_history").await;
+        for column in ["exit_code", "duration_ms", "finished_at", "env", "wrappers", "invocation_id", "position"] {
+            assert!(history_columns.iter().any(|c| c == column), "missing column {}", column);
+        }
+        assert!(!columns(&conn, "invocations").await.is_empty());
+
+        let entries = manager.fetch_recent_commands(10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].command, "cargo build --release");
+        assert_eq!(entries[0].position, 0);
+        assert_eq!(entries[0].exit_code, None);
+
+        // Rows from before the index existed are searchable
+        let results = manager.search("cargo", &Default::default()).await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn tolerates_columns_added_before_versioning() {
+        let path = temp_db_path();
+        create_unversioned_database(&path).await;
+        {
+            let db = Builder::new_local(&path).build().await.unwrap();
+            let conn = db.connect().unwrap();
+            conn.execute("ALTER TABLE command_history ADD COLUMN exit_code INTEGER", ())
+                .await
+                .unwrap();
+        }
+
+        let manager = DatabaseManager::open(&path).await.unwrap();
+        assert_eq!(manager.schema_version().await.unwrap(), latest_version());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn migrating_an_up_to_date_database_is_a_no_op() {
+        let path = temp_db_path();
+
+        let manager = DatabaseManager::open(&path).await.unwrap();
+        assert!(manager.pending_migrations().await.unwrap().is_empty());
+        assert!(manager.migrate().await.unwrap().is_empty());
+        assert_eq!(manager.schema_version().await.unwrap(), latest_version());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn refuses_databases_from_newer_versions() {
+        let path = temp_db_path();
+        {
+            let db = Builder::new_local(&path).build().await.unwrap();
+            let conn = db.connect().unwrap();
+            conn.execute(&format!("PRAGMA user_version = {}", latest_version() + 1), ())
+                .await
+                .unwrap();
+        }
+
+        assert!(DatabaseManager::open(&path).await.is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
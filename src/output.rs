@@ -0,0 +1,225 @@
+use crate::db::{CommandHistoryEntry, SessionSummary};
+use crate::ui::format_duration;
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One command line per line
+    Plain,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    Csv,
+    Tsv,
+}
+
+const TABLE_COLUMNS: [&str; 9] = [
+    "id",
+    "timestamp",
+    "session_id",
+    "user",
+    "pwd",
+    "binary",
+    "exit_code",
+    "duration_ms",
+    "command",
+];
+
+/// Writes history entries one at a time, so callers can stream rows straight
+/// from the database without collecting them first.
+pub struct EntryWriter<W: Write> {
+    out: W,
+    format: OutputFormat,
+    written: usize,
+}
+
+impl<W: Write> EntryWriter<W> {
+    pub fn new(out: W, format: OutputFormat) -> Self {
+        EntryWriter {
+            out,
+            format,
+            written: 0,
+        }
+    }
+
+    pub fn begin(&mut self) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => write!(self.out, "["),
+            OutputFormat::Csv => writeln!(self.out, "{}", TABLE_COLUMNS.join(",")),
+            OutputFormat::Tsv => writeln!(self.out, "{}", TABLE_COLUMNS.join("\t")),
+            OutputFormat::Plain | OutputFormat::Ndjson => Ok(()),
+        }
+    }
+
+    pub fn write(&mut self, entry: &CommandHistoryEntry) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Plain => writeln!(self.out, "{}", entry.full_command())?,
+            OutputFormat::Json => {
+                if self.written > 0 {
+                    write!(self.out, ",")?;
+                }
+                write!(self.out, "\n  ")?;
+                serde_json::to_writer(&mut self.out, entry)?;
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut self.out, entry)?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Csv => {
+                let fields: Vec<String> = table_fields(entry).iter().map(|f| csv_field(f)).collect();
+                writeln!(self.out, "{}", fields.join(","))?;
+            }
+            OutputFormat::Tsv => {
+                let fields: Vec<String> = table_fields(entry).iter().map(|f| tsv_field(f)).collect();
+                writeln!(self.out, "{}", fields.join("\t"))?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            if self.written > 0 {
+                writeln!(self.out)?;
+            }
+            writeln!(self.out, "]")?;
+        }
+        self.out.flush()
+    }
+}
+
+const SESSION_COLUMNS: [&str; 16] = [
+    "id",
+    "name",
+    "key",
+    "hostname",
+    "started_at",
+    "stopped_at",
+    "ended_at",
+    "exit_reason",
+    "duration_ms",
+    "commands",
+    "cwd",
+    "strategy",
+    "shell",
+    "shell_pid",
+    "tty",
+    "terminal",
+];
+
+/// Writes a list of sessions. The plain format is an aligned table meant
+/// for reading; the others carry every column for scripts.
+pub fn write_sessions<W: Write>(mut out: W, format: OutputFormat, sessions: &[SessionSummary]) -> io::Result<()> {
+    match format {
+        OutputFormat::Plain => {
+            let local = |time: DateTime<chrono::Utc>| DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M").to_string();
+            let mut rows = vec![["ID", "NAME", "STARTED", "ENDED", "DURATION", "COMMANDS", "HOST", "DIRECTORY"].map(String::from)];
+            for session in sessions {
+                rows.push([
+                    session.id.to_string(),
+                    session.name.clone().unwrap_or_default(),
+                    local(session.started_at),
+                    if session.is_open() {
+                        "open".to_string()
+                    } else {
+                        session.exit_reason.clone().unwrap_or_else(|| "yes".to_string())
+                    },
+                    session.duration().map(|d| format_duration(d.num_milliseconds())).unwrap_or_default(),
+                    session.commands.to_string(),
+                    session.details.hostname.clone().unwrap_or_default(),
+                    session.cwd.clone().unwrap_or_default(),
+                ]);
+            }
+            let mut widths = [0; 8];
+            for row in &rows {
+                for (width, field) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(field.chars().count());
+                }
+            }
+            for row in &rows {
+                let line: Vec<String> = row.iter().zip(widths).map(|(field, width)| format!("{:<width$}", field)).collect();
+                writeln!(out, "{}", line.join("  ").trim_end())?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, sessions)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            for session in sessions {
+                serde_json::to_writer(&mut out, session)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "{}", SESSION_COLUMNS.join(","))?;
+            for session in sessions {
+                let fields: Vec<String> = session_fields(session).iter().map(|f| csv_field(f)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+        OutputFormat::Tsv => {
+            writeln!(out, "{}", SESSION_COLUMNS.join("\t"))?;
+            for session in sessions {
+                let fields: Vec<String> = session_fields(session).iter().map(|f| tsv_field(f)).collect();
+                writeln!(out, "{}", fields.join("\t"))?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn session_fields(session: &SessionSummary) -> [String; 16] {
+    [
+        session.id.to_string(),
+        session.name.clone().unwrap_or_default(),
+        session.key.clone(),
+        session.details.hostname.clone().unwrap_or_default(),
+        session.started_at.to_rfc3339(),
+        session.stopped_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        session.ended_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        session.exit_reason.clone().unwrap_or_default(),
+        session.duration().map(|d| d.num_milliseconds().to_string()).unwrap_or_default(),
+        session.commands.to_string(),
+        session.cwd.clone().unwrap_or_default(),
+        session.details.strategy.clone().unwrap_or_default(),
+        session.details.shell.clone().unwrap_or_default(),
+        session.details.shell_pid.map(|pid| pid.to_string()).unwrap_or_default(),
+        session.details.tty.clone().unwrap_or_default(),
+        session.details.terminal.clone().unwrap_or_default(),
+    ]
+}
+
+fn table_fields(entry: &CommandHistoryEntry) -> [String; 9] {
+    [
+        entry.id.map(|id| id.to_string()).unwrap_or_default(),
+        entry.timestamp.to_rfc3339(),
+        entry.session_id.to_string(),
+        entry.user.clone(),
+        entry.pwd.clone(),
+        entry.binary.clone(),
+        entry.exit_code.map(|c| c.to_string()).unwrap_or_default(),
+        entry.duration_ms.map(|d| d.to_string()).unwrap_or_default(),
+        entry.full_command().to_string(),
+    ]
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn tsv_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
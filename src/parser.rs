@@ -0,0 +1,942 @@
+//! A small POSIX-ish shell parser.
+//!
+//! It only needs to understand enough of the shell grammar to split a command
+//! line into the commands it runs, so it never fails: unterminated quotes or
+//! stray parentheses are tolerated and parsed on a best-effort basis.
+//!
+//! The tree keeps the list operators, words and redirections as well, for
+//! callers that need more than the commands. Recall itself does not read them
+//! yet, so they are only checked for use in the tests.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListOp {
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `;` or a newline
+    Sequence,
+    /// `&`
+    Background,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub pipeline: Pipeline,
+    /// Operator separating this pipeline from the next one
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub op: Option<ListOp>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// `( ... )`
+    Subshell(Script),
+    /// `{ ...; }`
+    Group(Script),
+}
+
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    /// Source text of the command, including any heredoc bodies
+    pub text: String,
+    /// Leading `NAME=value` assignments, including those given to wrappers
+    pub assignments: Vec<String>,
+    /// Wrappers such as `sudo -E` or `nohup` that run the actual binary
+    pub wrappers: Vec<String>,
+    pub binary: Option<String>,
+    /// Every word of the command after any leading keyword, redirections excluded
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub words: Vec<Word>,
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    /// The word exactly as typed
+    pub raw: String,
+    /// The word with quotes and escapes removed; substitutions are kept verbatim
+    pub value: String,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// The operator with any file descriptor, such as `2>` or `<<-`
+    pub op: String,
+    pub target: Word,
+    /// The body of a heredoc
+    pub heredoc: Option<String>,
+}
+
+impl Script {
+    /// Every simple command in the script, in source order, including those
+    /// nested in subshells and groups.
+    pub fn simple_commands(&self) -> Vec<&SimpleCommand> {
+        let mut commands = Vec::new();
+        self.collect_simple_commands(&mut commands);
+        commands
+    }
+
+    fn collect_simple_commands<'a>(&'a self, out: &mut Vec<&'a SimpleCommand>) {
+        for item in &self.items {
+            for command in &item.pipeline.commands {
+                match command {
+                    Command::Simple(simple) => out.push(simple),
+                    Command::Subshell(script) | Command::Group(script) => {
+                        script.collect_simple_commands(out)
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Script {
+    let tokens = Lexer::new(source).tokenize();
+    let mut parser = Parser {
+        source,
+        tokens,
+        pos: 0,
+    };
+    parser.parse_script(Terminator::Eof)
+}
+
+#[derive(Debug, Clone)]
+enum TokenKind {
+    Word(Word),
+    Operator(&'static str),
+    Redirect(String),
+    Newline,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+    /// Raw heredoc body and delimiter line, attached to the delimiter word
+    heredoc: Option<String>,
+}
+
+const OPERATORS: [&str; 8] = ["&&", "||", "|&", ";;", "|", ";", "&", "("];
+const REDIRECTS: [&str; 12] = [
+    "&>>", "<<<", "<<-", "&>", "<<", "<>", "<&", ">>", ">&", ">|", "<", ">",
+];
+
+struct PendingHeredoc {
+    token: usize,
+    delimiter: String,
+    strip_tabs: bool,
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    tokens: Vec<Token>,
+    pending_heredocs: Vec<PendingHeredoc>,
+    /// Set after `<<` or `<<-`: the next word is a heredoc delimiter
+    expect_delimiter: Option<bool>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Lexer {
+            src,
+            bytes: src.as_bytes(),
+            pos: 0,
+            tokens: Vec::new(),
+            pending_heredocs: Vec::new(),
+            expect_delimiter: None,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(self.pos + offset).copied()
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize) {
+        self.tokens.push(Token {
+            kind,
+            start,
+            end: self.pos,
+            heredoc: None,
+        });
+    }
+
+    fn tokenize(mut self) -> Vec<Token> {
+        while let Some(c) = self.peek(0) {
+            let start = self.pos;
+            match c {
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                b'\\' if self.peek(1) == Some(b'\n') => self.pos += 2,
+                b'#' => {
+                    while self.peek(0).is_some_and(|c| c != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                b'\n' => {
+                    self.pos += 1;
+                    self.push(TokenKind::Newline, start);
+                    self.read_heredoc_bodies();
+                }
+                b')' => {
+                    self.pos += 1;
+                    self.push(TokenKind::Operator(")"), start);
+                }
+                _ => {
+                    if let Some(op) = self.redirect_operator() {
+                        self.pos += op.len();
+                        self.expect_delimiter = match op.trim_start_matches(|c: char| c.is_ascii_digit()) {
+                            "<<-" => Some(true),
+                            "<<" => Some(false),
+                            _ => None,
+                        };
+                        self.push(TokenKind::Redirect(op), start);
+                    } else if let Some(op) = self.operator() {
+                        self.pos += op.len();
+                        let op = if op == ";;" { ";" } else { op };
+                        self.push(TokenKind::Operator(op), start);
+                    } else {
+                        let word = self.word();
+                        self.push(TokenKind::Word(word.clone()), start);
+                        if let Some(strip_tabs) = self.expect_delimiter.take() {
+                            self.pending_heredocs.push(PendingHeredoc {
+                                token: self.tokens.len() - 1,
+                                delimiter: word.value,
+                                strip_tabs,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        self.tokens
+    }
+
+    fn operator(&self) -> Option<&'static str> {
+        let rest = &self.src[self.pos..];
+        OPERATORS.iter().copied().find(|op| rest.starts_with(op))
+    }
+
+    /// Matches a redirection operator with an optional file descriptor prefix.
+    /// `<(` and `>(` are process substitutions and lex as words instead.
+    fn redirect_operator(&self) -> Option<String> {
+        let rest = &self.src[self.pos..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let after_fd = &rest[digits..];
+        if after_fd.starts_with("<(") || after_fd.starts_with(">(") {
+            return None;
+        }
+        let op = REDIRECTS.iter().find(|op| after_fd.starts_with(*op))?;
+        if digits > 0 && op.starts_with('&') {
+            return None;
+        }
+        Some(rest[..digits + op.len()].to_string())
+    }
+
+    fn word(&mut self) -> Word {
+        let start = self.pos;
+        let mut value = Vec::new();
+
+        while let Some(c) = self.peek(0) {
+            match c {
+                b' ' | b'\t' | b'\r' | b'\n' | b';' | b'|' | b'&' | b')' => break,
+                b'(' if self.pos == start => break,
+                b'<' | b'>' => {
+                    if self.peek(1) != Some(b'(') {
+                        break;
+                    }
+                    let end = self.balanced(self.pos + 1);
+                    value.extend_from_slice(&self.bytes[self.pos..end]);
+                    self.pos = end;
+                }
+                b'\\' => {
+                    match self.peek(1) {
+                        Some(b'\n') => {}
+                        Some(next) => value.push(next),
+                        None => value.push(c),
+                    }
+                    self.pos = (self.pos + 2).min(self.bytes.len());
+                }
+                b'\'' => {
+                    self.pos += 1;
+                    while let Some(c) = self.peek(0) {
+                        self.pos += 1;
+                        if c == b'\'' {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                b'"' => {
+                    self.pos += 1;
+                    self.double_quoted(&mut value);
+                }
+                b'$' => {
+                    let end = match self.peek(1) {
+                        Some(b'(') => self.balanced(self.pos + 1),
+                        Some(b'{') => self.braced(self.pos + 1),
+                        _ => self.pos + 1,
+                    };
+                    value.extend_from_slice(&self.bytes[self.pos..end]);
+                    self.pos = end;
+                }
+                b'`' => {
+                    let end = self.backticked(self.pos);
+                    value.extend_from_slice(&self.bytes[self.pos..end]);
+                    self.pos = end;
+                }
+                _ => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Word {
+            raw: self.src[start..self.pos].to_string(),
+            value: String::from_utf8_lossy(&value).into_owned(),
+        }
+    }
+
+    /// Consumes a double-quoted string whose opening quote was already consumed.
+    fn double_quoted(&mut self, value: &mut Vec<u8>) {
+        while let Some(c) = self.peek(0) {
+            match c {
+                b'"' => {
+                    self.pos += 1;
+                    return;
+                }
+                b'\\' => {
+                    match self.peek(1) {
+                        Some(next @ (b'"' | b'\\' | b'$' | b'`')) => value.push(next),
+                        Some(b'\n') => {}
+                        Some(next) => value.extend_from_slice(&[c, next]),
+                        None => value.push(c),
+                    }
+                    self.pos = (self.pos + 2).min(self.bytes.len());
+                }
+                b'$' if matches!(self.peek(1), Some(b'(') | Some(b'{')) => {
+                    let end = if self.peek(1) == Some(b'(') {
+                        self.balanced(self.pos + 1)
+                    } else {
+                        self.braced(self.pos + 1)
+                    };
+                    value.extend_from_slice(&self.bytes[self.pos..end]);
+                    self.pos = end;
+                }
+                b'`' => {
+                    let end = self.backticked(self.pos);
+                    value.extend_from_slice(&self.bytes[self.pos..end]);
+                    self.pos = end;
+                }
+                _ => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the position just past the parenthesis matching the one at `open`,
+    /// skipping over quoted text. Used for `$(...)`, `$((...))` and `<(...)`.
+    fn balanced(&self, open: usize) -> usize {
+        let mut depth = 0;
+        let mut i = open;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+                b'\\' => i += 1,
+                b'\'' => {
+                    i += 1;
+                    while i < self.bytes.len() && self.bytes[i] != b'\'' {
+                        i += 1;
+                    }
+                }
+                b'"' => {
+                    i += 1;
+                    while i < self.bytes.len() && self.bytes[i] != b'"' {
+                        if self.bytes[i] == b'\\' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        self.bytes.len()
+    }
+
+    /// Returns the position just past the brace matching the one at `open` (`${...}`).
+    fn braced(&self, open: usize) -> usize {
+        let mut depth = 0;
+        let mut i = open;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+                b'\\' => i += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        self.bytes.len()
+    }
+
+    /// Returns the position just past the backtick closing the one at `open`.
+    fn backticked(&self, open: usize) -> usize {
+        let mut i = open + 1;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'`' => return i + 1,
+                b'\\' => i += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        self.bytes.len()
+    }
+
+    /// Reads the bodies of heredocs opened on the line that just ended.
+    fn read_heredoc_bodies(&mut self) {
+        for heredoc in std::mem::take(&mut self.pending_heredocs) {
+            let body_start = self.pos;
+            let mut body_end = self.pos;
+            while self.pos < self.bytes.len() {
+                let line_end = self.src[self.pos..]
+                    .find('\n')
+                    .map_or(self.bytes.len(), |i| self.pos + i);
+                let line = &self.src[self.pos..line_end];
+                let line = if heredoc.strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line
+                };
+                body_end = line_end;
+                self.pos = (line_end + 1).min(self.bytes.len());
+                if line == heredoc.delimiter {
+                    break;
+                }
+            }
+            self.tokens[heredoc.token].heredoc = Some(self.src[body_start..body_end].to_string());
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Terminator {
+    Eof,
+    /// `)` closing a subshell
+    Paren,
+    /// `}` closing a group
+    Brace,
+}
+
+/// Reserved words that may precede a command without being its binary.
+const PREFIX_KEYWORDS: [&str; 8] = ["if", "then", "else", "elif", "do", "while", "until", "!"];
+/// Reserved words that start or end a compound command rather than run anything.
+const SKIPPED_KEYWORDS: [&str; 8] = ["fi", "done", "esac", "for", "case", "select", "function", "}"];
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn at_operator(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Operator(o), .. }) if *o == op)
+    }
+
+    fn at_word(&self, raw: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Word(w), .. }) if w.raw == raw)
+    }
+
+    fn parse_script(&mut self, terminator: Terminator) -> Script {
+        let mut script = Script::default();
+
+        loop {
+            while matches!(
+                self.peek().map(|t| &t.kind),
+                Some(TokenKind::Newline) | Some(TokenKind::Operator(";")) | Some(TokenKind::Operator("&"))
+            ) {
+                self.pos += 1;
+            }
+
+            let Some(token) = self.peek() else {
+                break;
+            };
+            match (&token.kind, terminator) {
+                (TokenKind::Operator(")"), Terminator::Paren) => break,
+                (TokenKind::Word(w), Terminator::Brace) if w.raw == "}" => break,
+                (TokenKind::Operator(")"), _)
+                | (TokenKind::Operator("&&"), _)
+                | (TokenKind::Operator("||"), _)
+                | (TokenKind::Operator("|"), _)
+                | (TokenKind::Operator("|&"), _) => {
+                    // Stray operator with nothing before it
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let pipeline = self.parse_pipeline(terminator);
+            let op = match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::Operator("&&")) => Some(ListOp::And),
+                Some(TokenKind::Operator("||")) => Some(ListOp::Or),
+                Some(TokenKind::Operator(";")) | Some(TokenKind::Newline) => Some(ListOp::Sequence),
+                Some(TokenKind::Operator("&")) => Some(ListOp::Background),
+                _ => None,
+            };
+            if op.is_some() {
+                self.pos += 1;
+            }
+            if !pipeline.commands.is_empty() {
+                script.items.push(ListItem { pipeline, op });
+            }
+        }
+
+        script
+    }
+
+    fn parse_pipeline(&mut self, terminator: Terminator) -> Pipeline {
+        let mut pipeline = Pipeline::default();
+        if self.at_word("!") {
+            pipeline.negated = true;
+            self.pos += 1;
+        }
+
+        loop {
+            if let Some(command) = self.parse_command(terminator) {
+                pipeline.commands.push(command);
+            }
+            if self.at_operator("|") || self.at_operator("|&") {
+                self.pos += 1;
+                while matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Newline)) {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+
+        pipeline
+    }
+
+    fn parse_command(&mut self, terminator: Terminator) -> Option<Command> {
+        if self.at_operator("(") {
+            self.pos += 1;
+            let script = self.parse_script(Terminator::Paren);
+            if self.at_operator(")") {
+                self.pos += 1;
+            }
+            self.skip_redirects();
+            return Some(Command::Subshell(script));
+        }
+        if self.at_word("{") {
+            self.pos += 1;
+            let script = self.parse_script(Terminator::Brace);
+            if self.at_word("}") {
+                self.pos += 1;
+            }
+            self.skip_redirects();
+            return Some(Command::Group(script));
+        }
+        self.parse_simple_command(terminator).map(Command::Simple)
+    }
+
+    fn skip_redirects(&mut self) {
+        while let Some(Token { kind: TokenKind::Redirect(_), .. }) = self.peek() {
+            self.pos += 1;
+            if let Some(Token { kind: TokenKind::Word(_), .. }) = self.peek() {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn parse_simple_command(&mut self, terminator: Terminator) -> Option<SimpleCommand> {
+        let mut words: Vec<(Word, usize)> = Vec::new();
+        let mut redirects = Vec::new();
+        let mut start = None;
+        let mut end = 0;
+        let mut heredocs = Vec::new();
+
+        while let Some(token) = self.peek().cloned() {
+            match token.kind {
+                TokenKind::Word(word) => {
+                    // A closing brace only ends a group in command position
+                    if terminator == Terminator::Brace && word.raw == "}" && words.is_empty() {
+                        break;
+                    }
+                    self.pos += 1;
+                    start.get_or_insert(token.start);
+                    end = token.end;
+                    words.push((word, token.start));
+                }
+                TokenKind::Redirect(op) => {
+                    self.pos += 1;
+                    start.get_or_insert(token.start);
+                    end = token.end;
+                    let mut target = Word {
+                        raw: String::new(),
+                        value: String::new(),
+                    };
+                    let mut heredoc = None;
+                    if let Some(Token { kind: TokenKind::Word(word), end: word_end, heredoc: body, .. }) = self.peek().cloned() {
+                        self.pos += 1;
+                        end = word_end;
+                        target = word;
+                        if let Some(body) = body {
+                            heredocs.push(body.clone());
+                            heredoc = Some(body);
+                        }
+                    }
+                    redirects.push(Redirect { op, target, heredoc });
+                }
+                _ => break,
+            }
+        }
+
+        // Leading keywords such as `then` or `do` are not part of the command itself
+        let keywords = words
+            .iter()
+            .take_while(|(w, _)| PREFIX_KEYWORDS.contains(&w.raw.as_str()))
+            .count();
+        if keywords > 0 {
+            if keywords == words.len() {
+                return None;
+            }
+            start = Some(words[keywords].1);
+            words.drain(..keywords);
+        }
+
+        let start = start?;
+        if words
+            .first()
+            .is_some_and(|(w, _)| SKIPPED_KEYWORDS.contains(&w.raw.as_str()))
+        {
+            return None;
+        }
+
+        let mut text = self.source[start..end].trim().to_string();
+        for body in heredocs {
+            text.push('\n');
+            text.push_str(&body);
+        }
+
+        let words: Vec<Word> = words.into_iter().map(|(w, _)| w).collect();
+        let (assignments, wrappers, binary) = resolve_binary(&words);
+
+        Some(SimpleCommand {
+            text,
+            assignments,
+            wrappers,
+            binary,
+            words,
+            redirects,
+        })
+    }
+}
+
+fn is_assignment(word: &Word) -> bool {
+    let Some((name, _)) = word.raw.split_once('=') else {
+        return false;
+    };
+    let name = name.strip_suffix('+').unwrap_or(name);
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// How a wrapper command takes its options: short options that consume an
+/// argument, long options that consume an argument, and how many positional
+/// arguments precede the wrapped command.
+struct WrapperSpec {
+    name: &'static str,
+    short_args: &'static str,
+    long_args: &'static [&'static str],
+    positionals: usize,
+    accepts_assignments: bool,
+}
+
+const WRAPPERS: [WrapperSpec; 12] = [
+    WrapperSpec { name: "sudo", short_args: "ugCDhprtUT", long_args: &["--user", "--group", "--close-from", "--chdir", "--host", "--prompt", "--role", "--type", "--other-user", "--command-timeout"], positionals: 0, accepts_assignments: true },
+    WrapperSpec { name: "doas", short_args: "uC", long_args: &[], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "env", short_args: "uCS", long_args: &["--unset", "--chdir", "--split-string"], positionals: 0, accepts_assignments: true },
+    WrapperSpec { name: "nice", short_args: "n", long_args: &["--adjustment"], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "ionice", short_args: "cnpPtu", long_args: &["--class", "--classdata"], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "nohup", short_args: "", long_args: &[], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "time", short_args: "fo", long_args: &["--format", "--output"], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "timeout", short_args: "sk", long_args: &["--signal", "--kill-after"], positionals: 1, accepts_assignments: false },
+    WrapperSpec { name: "stdbuf", short_args: "ioe", long_args: &["--input", "--output", "--error"], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "exec", short_args: "a", long_args: &[], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "command", short_args: "", long_args: &[], positionals: 0, accepts_assignments: false },
+    WrapperSpec { name: "builtin", short_args: "", long_args: &[], positionals: 0, accepts_assignments: false },
+];
+
+/// Splits the words of a simple command into environment assignments, wrapper
+/// invocations and the binary that is ultimately run.
+fn resolve_binary(words: &[Word]) -> (Vec<String>, Vec<String>, Option<String>) {
+    let mut assignments = Vec::new();
+    let mut wrappers = Vec::new();
+    let mut i = 0;
+
+    while i < words.len() && is_assignment(&words[i]) {
+        assignments.push(words[i].raw.clone());
+        i += 1;
+    }
+
+    while let Some(word) = words.get(i) {
+        let name = word.value.rsplit('/').next().unwrap_or(&word.value);
+        let Some(spec) = WRAPPERS.iter().find(|spec| spec.name == name) else {
+            break;
+        };
+
+        let wrapper_start = i;
+        i += 1;
+        while let Some(option) = words.get(i) {
+            let option = option.value.as_str();
+            if option == "--" {
+                i += 1;
+                break;
+            }
+            if !option.starts_with('-') || option == "-" {
+                break;
+            }
+            i += 1;
+            if let Some(long) = option.strip_prefix("--") {
+                if !long.contains('=') && spec.long_args.contains(&option) {
+                    i += 1;
+                }
+            } else if let Some(idx) = option[1..].find(|c| spec.short_args.contains(c)) {
+                // The option's argument is the next word unless it was attached (`-uroot`)
+                if idx + 2 == option.len() {
+                    i += 1;
+                }
+            }
+        }
+        i = (i + spec.positionals).min(words.len());
+        let wrapper_end = i;
+
+        if spec.accepts_assignments {
+            while i < words.len() && is_assignment(&words[i]) {
+                assignments.push(words[i].raw.clone());
+                i += 1;
+            }
+        }
+
+        if i >= words.len() {
+            // Nothing is wrapped (`sudo -v`), so the wrapper is the binary
+            return (assignments, wrappers, Some(words[wrapper_start].value.clone()));
+        }
+
+        let wrapper: Vec<&str> = words[wrapper_start..wrapper_end]
+            .iter()
+            .map(|w| w.raw.as_str())
+            .collect();
+        wrappers.push(wrapper.join(" "));
+    }
+
+    let binary = words.get(i).map(|w| w.value.clone());
+    (assignments, wrappers, binary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The binary and text of every simple command in `source`.
+    fn commands(source: &str) -> Vec<(Option<String>, String)> {
+        parse(source)
+            .simple_commands()
+            .into_iter()
+            .map(|command| (command.binary.clone(), command.text.clone()))
+            .collect()
+    }
+
+    fn binaries(source: &str) -> Vec<String> {
+        commands(source)
+            .into_iter()
+            .map(|(binary, _)| binary.unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn splits_lists_and_pipelines() {
+        assert_eq!(
+            binaries("make && ./run || echo failed; ls | grep x |& tee log & wait\ndate"),
+            ["make", "./run", "echo", "ls", "grep", "tee", "wait", "date"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_operators_between_pipelines() {
+        let ops = |source: &str| parse(source).items.iter().map(|item| item.op).collect::<Vec<_>>();
+        assert_eq!(
+            ops("a && b || c; d &"),
+            [Some(ListOp::And), Some(ListOp::Or), Some(ListOp::Sequence), Some(ListOp::Background)]
+        );
+        assert_eq!(ops("a\nb | c"), [Some(ListOp::Sequence), None]);
+        assert_ne!(ops("a && b"), ops("a; b"));
+
+        let script = parse("(a || b) && c");
+        let Command::Subshell(inner) = &script.items[0].pipeline.commands[0] else {
+            panic!("expected a subshell");
+        };
+        assert_eq!(inner.items.iter().map(|item| item.op).collect::<Vec<_>>(), [Some(ListOp::Or), None]);
+        assert_eq!(script.items[0].op, Some(ListOp::And));
+    }
+
+    #[test]
+    fn quotes_and_escapes_are_removed_from_the_binary() {
+        assert_eq!(binaries(r#"'my prog' a"#), ["my prog"]);
+        assert_eq!(binaries(r#""git" status"#), ["git"]);
+        assert_eq!(binaries(r"g\it status"), ["git"]);
+        assert_eq!(binaries(r#"echo "a \"b\" c""#), ["echo"]);
+    }
+
+    #[test]
+    fn operators_inside_quotes_do_not_split() {
+        assert_eq!(
+            commands(r#"echo "a | b && c" 'd || e; f' g\;h"#),
+            [(Some("echo".to_string()), r#"echo "a | b && c" 'd || e; f' g\;h"#.to_string())]
+        );
+    }
+
+    #[test]
+    fn substitutions_stay_inside_their_word() {
+        assert_eq!(binaries("echo $(ls | wc -l; date)"), ["echo"]);
+        assert_eq!(binaries("echo `ls | wc -l`"), ["echo"]);
+        assert_eq!(binaries(r#"echo "$(cat "a b" | head)""#), ["echo"]);
+        assert_eq!(binaries("diff <(sort a) <(sort b) > out"), ["diff"]);
+        assert_eq!(binaries("echo ${x:-a;b} $((1 + 2))"), ["echo"]);
+    }
+
+    #[test]
+    fn heredoc_bodies_belong_to_their_command() {
+        let source = "cat <<EOF | grep a\nline | one; two\nEOF\nls";
+        assert_eq!(
+            commands(source),
+            [
+                (Some("cat".to_string()), "cat <<EOF\nline | one; two\nEOF".to_string()),
+                (Some("grep".to_string()), "grep a".to_string()),
+                (Some("ls".to_string()), "ls".to_string()),
+            ]
+        );
+
+        let stripped = commands("cat <<-'END'\n\tbody\n\tEND\necho done");
+        assert_eq!(stripped.len(), 2);
+        assert_eq!(stripped[1].0.as_deref(), Some("echo"));
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        assert_eq!(binaries("ls # && rm -rf /"), ["ls"]);
+        assert_eq!(binaries("# just a note"), Vec::<String>::new());
+        assert_eq!(binaries("echo a#b"), ["echo"]);
+        assert_eq!(commands("echo a#b")[0].1, "echo a#b");
+    }
+
+    #[test]
+    fn subshells_and_groups_are_descended_into() {
+        assert_eq!(binaries("(cd src && make) | tee log"), ["cd", "make", "tee"]);
+        assert_eq!(binaries("{ echo a; echo b; } > out"), ["echo", "echo"]);
+    }
+
+    #[test]
+    fn keywords_are_not_binaries() {
+        assert_eq!(
+            binaries("if test -f x; then cat x; else touch x; fi"),
+            ["test", "cat", "touch"]
+        );
+        assert_eq!(binaries("for f in *; do rm $f; done"), ["rm"]);
+        assert_eq!(binaries("! grep -q a b"), ["grep"]);
+    }
+
+    #[test]
+    fn assignments_and_wrappers_are_split_from_the_binary() {
+        let script = parse("FOO=1 sudo -E make install");
+        let command = script.simple_commands()[0];
+        assert_eq!(command.assignments, ["FOO=1"]);
+        assert_eq!(command.wrappers, ["sudo -E"]);
+        assert_eq!(command.binary.as_deref(), Some("make"));
+
+        let script = parse("sudo -u root env A=b nice -n 5 /usr/bin/python3 x.py");
+        let command = script.simple_commands()[0];
+        assert_eq!(command.assignments, ["A=b"]);
+        assert_eq!(command.wrappers, ["sudo -u root", "env", "nice -n 5"]);
+        assert_eq!(command.binary.as_deref(), Some("/usr/bin/python3"));
+
+        assert_eq!(binaries("timeout -s KILL 10 curl x"), ["curl"]);
+        assert_eq!(binaries("sudo -v"), ["sudo"]);
+        assert_eq!(binaries("FOO=1"), [""]);
+    }
+
+    #[test]
+    fn redirections_are_not_words() {
+        let script = parse("2>/dev/null grep -r x . >> out");
+        let command = script.simple_commands()[0];
+        assert_eq!(command.binary.as_deref(), Some("grep"));
+        assert_eq!(command.text, "2>/dev/null grep -r x . >> out");
+        let values: Vec<&str> = command.words.iter().map(|word| word.value.as_str()).collect();
+        assert_eq!(values, ["grep", "-r", "x", "."]);
+        let redirects: Vec<(&str, &str)> = command
+            .redirects
+            .iter()
+            .map(|redirect| (redirect.op.as_str(), redirect.target.value.as_str()))
+            .collect();
+        assert_eq!(redirects, [("2>", "/dev/null"), (">>", "out")]);
+        assert!(command.redirects.iter().all(|redirect| redirect.heredoc.is_none()));
+
+        let script = parse("cat <<'EOF' > \"my file\"\nbody\nEOF");
+        let command = script.simple_commands()[0];
+        assert_eq!(command.words.iter().map(|word| word.raw.as_str()).collect::<Vec<_>>(), ["cat"]);
+        assert_eq!(command.redirects[0].op, "<<");
+        assert_eq!(command.redirects[0].target.raw, "'EOF'");
+        assert_eq!(command.redirects[0].heredoc.as_deref(), Some("body\nEOF"));
+        assert_eq!(command.redirects[1].target.value, "my file");
+    }
+
+    #[test]
+    fn malformed_input_is_tolerated() {
+        assert_eq!(binaries("echo 'unterminated | rest"), ["echo"]);
+        assert_eq!(binaries("echo $(unclosed"), ["echo"]);
+        assert_eq!(binaries(") && ls"), ["ls"]);
+        assert_eq!(binaries(""), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::db::{CommandHistoryEntry, SessionSummary};
+use crate::redact::Redactor;
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RunbookFormat {
+    /// A shell script that replays the commands
+    Sh,
+    /// A Markdown document with one numbered step per command
+    Markdown,
+}
+
+/// Turns commands of a session, oldest first, into a script or runbook.
+/// A `cd` is inserted before the first command and whenever the working
+/// directory changed, and every command goes through `redactor` first.
+pub fn render<W: Write>(
+    mut out: W,
+    format: RunbookFormat,
+    session: &SessionSummary,
+    entries: &[CommandHistoryEntry],
+    redactor: &Redactor,
+    exit_codes: bool,
+) -> io::Result<()> {
+    let title = match &session.name {
+        Some(name) => format!("Session {}: {}", session.id, name),
+        None => format!("Session {}", session.id),
+    };
+    let host = session.details.hostname.as_deref().unwrap_or("an unknown host");
+    let period = match (entries.first(), entries.last()) {
+        (Some(first), Some(last)) => format!("from {} to {}", local(first.timestamp), local(last.timestamp)),
+        _ => "with no commands".to_string(),
+    };
+
+    match format {
+        RunbookFormat::Sh => {
+            writeln!(out, "#!/bin/sh")?;
+            writeln!(out, "# {}, recorded on {} {}", title, host, period)?;
+        }
+        RunbookFormat::Markdown => {
+            writeln!(out, "# {}", title)?;
+            writeln!(out)?;
+            writeln!(out, "Recorded on `{}` {}.", host, period)?;
+        }
+    }
+
+    let mut pwd: Option<&str> = None;
+    for (step, entry) in entries.iter().enumerate() {
+        let cd = (pwd != Some(entry.pwd.as_str())).then(|| format!("cd {}", shell_quote(&entry.pwd)));
+        pwd = Some(&entry.pwd);
+        let command = redactor.redact(entry.full_command()).text;
+        let mut note = local(entry.timestamp);
+        if let Some(code) = entry.exit_code.filter(|_| exit_codes) {
+            note.push_str(&format!(" (exit {})", code));
+        }
+
+        match format {
+            RunbookFormat::Sh => {
+                writeln!(out)?;
+                writeln!(out, "# {}", note)?;
+                if let Some(cd) = cd {
+                    writeln!(out, "{}", cd)?;
+                }
+                writeln!(out, "{}", command)?;
+            }
+            RunbookFormat::Markdown => {
+                let lines: Vec<&str> = cd.iter().map(String::as_str).chain(command.lines()).collect();
+                let fence = fence_for(&lines);
+                writeln!(out)?;
+                writeln!(out, "{}. {}", step + 1, note)?;
+                writeln!(out)?;
+                writeln!(out, "   {}sh", fence)?;
+                for line in lines {
+                    writeln!(out, "   {}", line)?;
+                }
+                writeln!(out, "   {}", fence)?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+/// A Markdown code fence longer than any run of backticks in `lines`, so a
+/// command using backticks cannot close its own block.
+fn fence_for(lines: &[&str]) -> String {
+    let longest = lines
+        .iter()
+        .flat_map(|line| line.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn local(time: DateTime<Utc>) -> String {
+    DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Quotes `text` for a POSIX shell, leaving plain paths as they are.
+fn shell_quote(text: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+=:,@%~".contains(c);
+    if !text.is_empty() && text.chars().all(plain) && !text.starts_with('~') {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactConfig;
+    use crate::db::SessionDetails;
+    use chrono::TimeZone;
+
+    fn session() -> SessionSummary {
+        SessionSummary {
+            id: 7,
+            name: Some("deploy".to_string()),
+            key: "term".to_string(),
+            started_at: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            stopped_at: None,
+            ended_at: None,
+            exit_reason: None,
+            details: SessionDetails {
+                hostname: Some("build01".to_string()),
+                ..SessionDetails::default()
+            },
+            commands: 0,
+            cwd: None,
+        }
+    }
+
+    fn entry(line: &str, pwd: &str, exit_code: Option<i32>) -> CommandHistoryEntry {
+        CommandHistoryEntry {
+            id: None,
+            timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            command: String::new(),
+            binary: String::new(),
+            env: None,
+            wrappers: None,
+            user: "alice".to_string(),
+            pwd: pwd.to_string(),
+            session_id: 7,
+            exit_code,
+            duration_ms: None,
+            finished_at: None,
+            invocation_id: None,
+            position: 0,
+            line: Some(line.to_string()),
+            hostname: None,
+        }
+    }
+
+    fn render_to_string(format: RunbookFormat, entries: &[CommandHistoryEntry], exit_codes: bool) -> String {
+        let redactor = Redactor::from_config(&RedactConfig::default()).unwrap();
+        let mut out = Vec::new();
+        render(&mut out, format, &session(), entries, &redactor, exit_codes).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The lines of `text` that are not comments, blank or fences.
+    fn commands(text: &str) -> Vec<&str> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("```"))
+            .filter(|line| !line.starts_with(|c: char| c.is_ascii_digit()) && !line.starts_with("Recorded"))
+            .collect()
+    }
+
+    #[test]
+    fn inserts_cd_when_the_directory_changes() {
+        let entries = [
+            entry("make", "/srv/app", None),
+            entry("make test", "/srv/app", None),
+            entry("ls", "/srv/my app", None),
+            entry("make", "/srv/app", None),
+        ];
+        let script = render_to_string(RunbookFormat::Sh, &entries, false);
+        assert!(script.starts_with("#!/bin/sh\n# Session 7: deploy, recorded on build01 from "));
+        assert_eq!(
+            commands(&script),
+            ["cd /srv/app", "make", "make test", "cd '/srv/my app'", "ls", "cd /srv/app", "make"]
+        );
+    }
+
+    #[test]
+    fn redacts_commands() {
+        let entries = [entry("mysql -u root -phunter2 shop", "/srv", None)];
+        for format in [RunbookFormat::Sh, RunbookFormat::Markdown] {
+            let text = render_to_string(format, &entries, false);
+            assert!(!text.contains("hunter2"), "{}", text);
+            assert!(text.contains("mysql -u root -p<redacted> shop"), "{}", text);
+        }
+    }
+
+    #[test]
+    fn notes_exit_codes_only_when_asked() {
+        let entries = [entry("false", "/srv", Some(1)), entry("true", "/srv", Some(0)), entry("sleep 1", "/srv", None)];
+        let with_codes = render_to_string(RunbookFormat::Sh, &entries, true);
+        assert_eq!(with_codes.matches("(exit 1)").count(), 1);
+        assert_eq!(with_codes.matches("(exit 0)").count(), 1);
+        assert_eq!(with_codes.matches("(exit ").count(), 2);
+
+        let without = render_to_string(RunbookFormat::Markdown, &entries, false);
+        assert!(!without.contains("(exit "), "{}", without);
+        let with_codes = render_to_string(RunbookFormat::Markdown, &entries, true);
+        assert!(with_codes.contains("1. ") && with_codes.contains("(exit 1)"), "{}", with_codes);
+    }
+
+    #[test]
+    fn markdown_numbers_steps_in_fenced_blocks() {
+        let entries = [entry("cat <<EOF\nhello\nEOF", "/srv", None), entry("ls", "/srv", None)];
+        let markdown = render_to_string(RunbookFormat::Markdown, &entries, false);
+        assert!(markdown.starts_with("# Session 7: deploy\n\nRecorded on `build01` from "));
+        assert!(markdown.contains("\n\n   ```sh\n   cd /srv\n   cat <<EOF\n   hello\n   EOF\n   ```\n"), "{}", markdown);
+        assert!(markdown.contains("\n2. "), "{}", markdown);
+    }
+
+    #[test]
+    fn markdown_fences_outgrow_backticks_in_the_command() {
+        let entries = [entry("echo `date` ```not a fence````", "/srv", None)];
+        let markdown = render_to_string(RunbookFormat::Markdown, &entries, false);
+        assert!(markdown.contains("   `````sh\n"), "{}", markdown);
+        assert!(markdown.ends_with("   `````\n"), "{}", markdown);
+
+        assert_eq!(fence_for(&["ls"]), "```");
+        assert_eq!(fence_for(&["echo `a`"]), "```");
+        assert_eq!(fence_for(&["a ``` b"]), "````");
+    }
+}
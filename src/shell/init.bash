@@ -0,0 +1,97 @@
+# recall shell integration for bash
+# Add to ~/.bashrc:  eval "$(recall init bash)"
+#
+# bash has no preexec hook, so like bash-preexec this uses a DEBUG trap for
+# the start of a command and PROMPT_COMMAND for its end. When bash-preexec
+# is already loaded its hook arrays are used instead.
+
+_recall_start() {
+    [[ -z "$1" ]] && return
+    RECALL_COMMAND_ID=$(command recall log --start -- "$1" 2>/dev/null)
+}
+
+_recall_precmd() {
+    local exit_code=$?
+    if [[ -n "${RECALL_COMMAND_ID:-}" ]]; then
+        (command recall log --finish "$RECALL_COMMAND_ID" --exit "$exit_code" >/dev/null 2>&1 &)
+        unset RECALL_COMMAND_ID
+    fi
+    return $exit_code
+}
+
+# The number of the newest history entry, and the entry itself.
+_recall_read_history() {
+    local entry pattern='^[[:space:]]*([0-9]+)[* ] (.*)$'
+    entry=$(HISTTIMEFORMAT= builtin history 1)
+    [[ $entry =~ $pattern ]] || return 1
+    _recall_history_number=${BASH_REMATCH[1]}
+    _recall_history_line=${BASH_REMATCH[2]}
+}
+
+# The DEBUG trap runs before every simple command. Only the first one after
+# the prompt was drawn is the line the user typed, and it is only logged when
+# bash added it to the history (so `ignorespace` and friends are respected).
+_recall_debug_trap() {
+    [[ -n "${COMP_LINE:-}" || "${_recall_at_prompt:-0}" != 1 ]] && return
+    _recall_at_prompt=0
+
+    local previous=${_recall_history_number:-}
+    _recall_read_history || return
+    [[ "$_recall_history_number" == "$previous" ]] && return
+    _recall_start "$_recall_history_line"
+}
+
+if [[ -n "${bash_preexec_imported:-}${__bp_imported:-}" ]]; then
+    preexec_functions+=(_recall_start)
+    precmd_functions=(_recall_precmd "${precmd_functions[@]}")
+elif [[ "${PROMPT_COMMAND[*]:-}" != *_recall_precmd* ]]; then
+    _recall_read_history
+    trap '_recall_debug_trap' DEBUG
+    PROMPT_COMMAND="_recall_precmd"$'\n'"${PROMPT_COMMAND:+$PROMPT_COMMAND$'\n'}_recall_at_prompt=1"
+fi
+
+# Record the end of the session: `exit` and end of input run the EXIT trap,
+# a closed terminal sends SIGHUP first. Traps set before the integration was
+# loaded still run after ours.
+_recall_session_end() {
+    local exit_status=$?
+    command recall session end --reason "$1" >/dev/null 2>&1
+    return $exit_status
+}
+
+# The command of the trap currently set for a signal, empty if none.
+_recall_trap_command() {
+    local -a words
+    eval "words=($(trap -p "$1"))"
+    printf '%s' "${words[2]:-}"
+}
+
+if [[ "$(trap -p EXIT)" != *_recall_session_end* ]]; then
+    _recall_exit_trap=$(_recall_trap_command EXIT)
+    _recall_hup_trap=$(_recall_trap_command HUP)
+    trap '_recall_session_end exit; eval "$_recall_exit_trap"' EXIT
+    trap '_recall_session_end hangup; trap -- "$_recall_exit_trap" EXIT; eval "$_recall_hup_trap"; exit 129' HUP
+fi
+
+# Choosing a command with `recall pick`: Enter runs it, Tab leaves it on the
+# command line for editing. `bind -x` cannot accept the line itself, so the
+# bound keys expand to two hidden sequences: the first runs the picker and
+# rebinds the second to either accept-line or a harmless redraw.
+_recall_pick() {
+    local output ret
+    output=$(command recall pick --query "$READLINE_LINE" </dev/tty)
+    ret=$?
+    if [[ -n "$output" ]]; then
+        READLINE_LINE=$output
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+    local action=redraw-current-line
+    [[ -n "$output" && $ret -eq 0 ]] && action=accept-line
+    bind -m emacs "\"\\C-x\\C-r\\C-a\": $action"
+    bind -m vi-insert "\"\\C-x\\C-r\\C-a\": $action"
+}
+
+for _recall_keymap in emacs vi-insert; do
+    bind -m "$_recall_keymap" -x '"\C-x\C-r\C-p": _recall_pick'
+done
+unset _recall_keymap
@@ -0,0 +1,39 @@
+# recall shell integration for fish
+# Add to ~/.config/fish/config.fish:  recall init fish | source
+
+function _recall_preexec --on-event fish_preexec
+    if test -z "$argv[1]"
+        return
+    end
+    set -g RECALL_COMMAND_ID (command recall log --start -- $argv[1] 2>/dev/null)
+end
+
+function _recall_postexec --on-event fish_postexec
+    set -l exit_code $status
+    if set -q RECALL_COMMAND_ID
+        command recall log --finish $RECALL_COMMAND_ID --exit $exit_code >/dev/null 2>&1 &
+        disown 2>/dev/null
+        set -e RECALL_COMMAND_ID
+    end
+end
+
+# Record the end of the session when fish exits
+function _recall_session_end --on-event fish_exit
+    command recall session end --reason exit >/dev/null 2>&1
+end
+
+# Choosing a command with `recall pick`: Enter runs it, Tab leaves it on the
+# command line for editing.
+function _recall_pick
+    # Quoted so an empty or multi-line buffer is still exactly one argument
+    set -l query (commandline -b | string collect)
+    set -l output (command recall pick --query "$query" </dev/tty)
+    set -l ret $status
+    commandline -f repaint
+    if test -n "$output"
+        commandline -r -- (string join \n -- $output)
+        if test $ret -eq 0
+            commandline -f execute
+        end
+    end
+end
@@ -0,0 +1,346 @@
+//! Sharing history between machines through a sqld server or a shared folder.
+//!
+//! Commands are still recorded in the local database first, so logging works
+//! offline and stays fast. A sync sends the rows changed since the last one
+//! to the server and then fetches what other machines sent. Rows are matched
+//! by the uuid they get when they are created, so the same row is never
+//! stored twice and merging needs no coordination between machines. Every
+//! write to the server takes the next value of a counter, which lets each
+//! machine ask for just the changes it has not seen yet.
+//!
+//! Without a server, machines share a folder that a tool such as Syncthing
+//! keeps in step. SQLite files must never be written from two machines, so
+//! each machine only ever replaces its own copy of its database in the
+//! folder, and merges the copies of the others into its own database by uuid.
+
+use crate::config::SyncConfig;
+use crate::db::{
+    value_rows, DatabaseManager, MergeStats, SyncChanges, SYNC_COMMAND_COLUMNS, SYNC_INVOCATION_COLUMNS,
+    SYNC_SESSION_COLUMNS,
+};
+use libsql::{Builder, Connection};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Rows sent in one `INSERT`, keeping well below SQLite's parameter limit.
+const ROWS_PER_STATEMENT: usize = 100;
+
+/// The first schema version with the uuids that merging matches rows by.
+const SYNC_IDS_VERSION: i64 = 12;
+
+/// What a sync sent and received.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncStats {
+    pub sent_commands: usize,
+    pub sent_sessions: usize,
+    pub received_commands: usize,
+    pub received_sessions: usize,
+    /// Command lines removed here because another machine deleted them
+    pub deleted: usize,
+}
+
+impl std::ops::AddAssign for SyncStats {
+    fn add_assign(&mut self, other: SyncStats) {
+        self.sent_commands += other.sent_commands;
+        self.sent_sessions += other.sent_sessions;
+        self.received_commands += other.received_commands;
+        self.received_sessions += other.received_sessions;
+        self.deleted += other.deleted;
+    }
+}
+
+/// Sends local changes to the server in the config and applies the changes
+/// other machines made.
+pub async fn sync(
+    db_manager: &DatabaseManager,
+    config: &SyncConfig,
+) -> Result<SyncStats, Box<dyn std::error::Error + Send + Sync>> {
+    let url = config
+        .url
+        .as_deref()
+        .ok_or("Sync is not set up: set sync.url in the config to the address of a sqld server, or sync.dir to a shared folder")?;
+    let remote = connect(url, config.auth_token.as_deref().unwrap_or(""))
+        .await
+        .map_err(|e| format!("Could not reach the sync server at {}: {}", url, e))?;
+    let state = db_manager.sync_state(url).await?;
+
+    let sent = db_manager.claim_sync_changes().await?;
+    if !sent.is_empty() {
+        push(&remote, &state.replica, &sent).await?;
+    }
+    db_manager.finish_sync_push(&sent).await?;
+
+    let (received, version) = pull(&remote, &state.replica, state.pulled).await?;
+    let merged = db_manager.apply_sync_changes(&received, Some(version)).await?;
+
+    Ok(SyncStats {
+        sent_commands: sent.command_lines(),
+        sent_sessions: sent.sessions.len(),
+        received_commands: merged.commands,
+        received_sessions: merged.sessions,
+        deleted: merged.deleted,
+    })
+}
+
+/// Merges the copies other machines left in the shared folder `dir` into
+/// this database, then replaces this machine's own copy there. The copy of
+/// an encrypted database is encrypted with the same key, so only machines
+/// holding that key can merge it.
+pub async fn sync_dir(
+    db_manager: &DatabaseManager,
+    dir: &Path,
+) -> Result<SyncStats, Box<dyn std::error::Error + Send + Sync>> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()).into());
+    }
+    // From now on deletions are remembered, so that they reach the folder
+    db_manager.set_sync_value("dir", &dir.to_string_lossy()).await?;
+    let replica = db_manager.replica_id().await?;
+    let own = format!("recall-{}.db", replica);
+
+    let mut files = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with("recall-") && name.ends_with(".db") && name != own
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|entry| entry.file_name());
+
+    let mut stats = SyncStats::default();
+    let mut failed = Vec::new();
+    for entry in files {
+        let path = entry.path();
+        // A copy that has not changed since the last merge has nothing new
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let fingerprint = format!("{}:{}", metadata.len(), modified.as_nanos());
+        let key = format!("merged:{}", entry.file_name().to_string_lossy());
+        if db_manager.sync_value(&key).await?.as_deref() == Some(fingerprint.as_str()) {
+            continue;
+        }
+
+        match merge_file(db_manager, &path).await {
+            Ok(merged) => {
+                stats.received_commands += merged.commands;
+                stats.received_sessions += merged.sessions;
+                stats.deleted += merged.deleted;
+                db_manager.set_sync_value(&key, &fingerprint).await?;
+            }
+            // One unreadable copy should not keep this machine's history
+            // from reaching the others
+            Err(e) => failed.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    // Written next to its final name and renamed, so that readers and the
+    // folder sync tool only ever see a complete file
+    let partial = dir.join(format!(".{}.partial", own));
+    if partial.exists() {
+        std::fs::remove_file(&partial)?;
+    }
+    db_manager.export_snapshot(&partial).await?;
+    std::fs::rename(&partial, dir.join(&own))?;
+
+    if !failed.is_empty() {
+        return Err(format!("Could not merge {}", failed.join("; ")).into());
+    }
+    Ok(stats)
+}
+
+/// Merges every row of the recall database at `path` into this one. Rows
+/// already here are matched by uuid, so merging the same file again only
+/// adds what is new in it.
+pub async fn merge_file(
+    db_manager: &DatabaseManager,
+    path: &Path,
+) -> Result<MergeStats, Box<dyn std::error::Error + Send + Sync>> {
+    let other = DatabaseManager::open_read_only(path).await?;
+    let version = other.schema_version().await?;
+    if version < SYNC_IDS_VERSION {
+        return Err(format!(
+            "{} was last opened by an older recall (schema version {}); upgrade it first with \
+             `RECALL_DB_PATH={} recall db migrate`",
+            path.display(),
+            version,
+            path.display()
+        )
+        .into());
+    }
+
+    let changes = other.all_sync_rows().await?;
+    db_manager.apply_sync_changes(&changes, None).await
+}
+
+/// Connects to the server and creates the tables recall keeps there.
+async fn connect(url: &str, auth_token: &str) -> Result<Connection, Box<dyn std::error::Error + Send + Sync>> {
+    let db = Builder::new_remote(url.to_string(), auth_token.to_string()).build().await?;
+    let conn = db.connect()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recall_clock (id INTEGER PRIMARY KEY CHECK (id = 1), version INTEGER NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute("INSERT OR IGNORE INTO recall_clock (id, version) VALUES (1, 0)", ())
+        .await?;
+    for (table, columns) in tables() {
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (uuid TEXT PRIMARY KEY, {}, version INTEGER NOT NULL, origin TEXT NOT NULL)",
+                table,
+                columns[1..].join(", ")
+            ),
+            (),
+        )
+        .await?;
+        conn.execute(
+            &format!("CREATE INDEX IF NOT EXISTS {0}_version ON {0}(version)", table),
+            (),
+        )
+        .await?;
+    }
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recall_tombstones (uuid TEXT PRIMARY KEY, version INTEGER NOT NULL, origin TEXT NOT NULL)",
+        (),
+    )
+    .await?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS recall_tombstones_version ON recall_tombstones(version)",
+        (),
+    )
+    .await?;
+
+    Ok(conn)
+}
+
+/// The server tables, each mirroring the rows of one local table.
+fn tables() -> [(&'static str, &'static [&'static str]); 3] {
+    [
+        ("recall_sessions", SYNC_SESSION_COLUMNS),
+        ("recall_invocations", SYNC_INVOCATION_COLUMNS),
+        ("recall_commands", SYNC_COMMAND_COLUMNS),
+    ]
+}
+
+/// The latest version written to the server.
+async fn clock(conn: &Connection) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    match value_rows(conn, "SELECT version FROM recall_clock WHERE id = 1", ())
+        .await?
+        .first()
+        .and_then(|row| row.first())
+    {
+        Some(libsql::Value::Integer(version)) => Ok(*version),
+        _ => Err("The sync server has no version counter".into()),
+    }
+}
+
+/// Writes `changes` to the server in one transaction under a new version.
+async fn push(
+    conn: &Connection,
+    replica: &str,
+    changes: &SyncChanges,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tx = conn.transaction().await?;
+    tx.execute("UPDATE recall_clock SET version = version + 1 WHERE id = 1", ())
+        .await?;
+    let version = clock(&tx).await?;
+
+    let rows = [&changes.sessions, &changes.invocations, &changes.commands];
+    for ((table, columns), rows) in tables().into_iter().zip(rows) {
+        // A session's key is how its own machine finds it, so it never changes
+        let updates = columns
+            .iter()
+            .filter(|column| !["uuid", "key"].contains(column))
+            .chain(&["version", "origin"])
+            .map(|column| format!("{0} = excluded.{0}", column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for chunk in rows.chunks(ROWS_PER_STATEMENT) {
+            let placeholders = format!("({})", vec!["?"; columns.len() + 2].join(", "));
+            let sql = format!(
+                "INSERT INTO {} ({}, version, origin) VALUES {} ON CONFLICT (uuid) DO UPDATE SET {}",
+                table,
+                columns.join(", "),
+                vec![placeholders; chunk.len()].join(", "),
+                updates
+            );
+            let mut params = Vec::with_capacity(chunk.len() * (columns.len() + 2));
+            for row in chunk {
+                params.extend(row.iter().cloned());
+                params.push(version.into());
+                params.push(replica.into());
+            }
+            tx.execute(&sql, libsql::params_from_iter(params)).await?;
+        }
+    }
+
+    for chunk in changes.deleted.chunks(ROWS_PER_STATEMENT) {
+        let sql = format!(
+            "INSERT INTO recall_tombstones (uuid, version, origin) VALUES {} ON CONFLICT (uuid) DO NOTHING",
+            vec!["(?, ?, ?)"; chunk.len()].join(", ")
+        );
+        let mut params: Vec<libsql::Value> = Vec::with_capacity(chunk.len() * 3);
+        for uuid in chunk {
+            params.extend([uuid.as_str().into(), version.into(), replica.into()]);
+        }
+        tx.execute(&sql, libsql::params_from_iter(params)).await?;
+    }
+    // Drop what was just deleted, and anything just sent that another
+    // machine had already deleted
+    for (table, _) in tables() {
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE uuid IN (SELECT uuid FROM recall_tombstones WHERE version = ?)
+                    OR (version = ? AND uuid IN (SELECT uuid FROM recall_tombstones))",
+                table
+            ),
+            (version, version),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Reads the changes other machines made after version `since`, returning
+/// them with the version they bring this machine up to.
+async fn pull(
+    conn: &Connection,
+    replica: &str,
+    since: i64,
+) -> Result<(SyncChanges, i64), Box<dyn std::error::Error + Send + Sync>> {
+    let version = clock(conn).await?;
+    if version <= since {
+        return Ok((SyncChanges::default(), since));
+    }
+
+    let [sessions, invocations, commands] = tables().map(|(table, columns)| {
+        format!(
+            "SELECT {} FROM {} WHERE version > ? AND version <= ? AND origin <> ?",
+            columns.join(", "),
+            table
+        )
+    });
+    let params = || (since, version, replica.to_string());
+    let changes = SyncChanges {
+        sessions: value_rows(conn, &sessions, params()).await?,
+        invocations: value_rows(conn, &invocations, params()).await?,
+        commands: value_rows(conn, &commands, params()).await?,
+        deleted: value_rows(
+            conn,
+            "SELECT uuid FROM recall_tombstones WHERE version > ? AND version <= ? AND origin <> ?",
+            params(),
+        )
+        .await?
+        .into_iter()
+        .filter_map(|row| match row.into_iter().next() {
+            Some(libsql::Value::Text(uuid)) => Some(uuid),
+            _ => None,
+        })
+        .collect(),
+    };
+
+    Ok((changes, version))
+}
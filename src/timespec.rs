@@ -0,0 +1,151 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+/// Parses a point in time given on the command line.
+///
+/// Accepts relative offsets (`30m`, `2h`, `7d`, `2 weeks ago`), the words
+/// `now`, `today` and `yesterday`, dates (`2024-05-01`), local date-times
+/// (`2024-05-01 13:45`) and RFC 3339 timestamps.
+pub fn parse_time(input: &str) -> Result<DateTime<Utc>, Box<dyn std::error::Error + Send + Sync>> {
+    let input = input.trim();
+    let now = Utc::now();
+
+    match input.to_lowercase().as_str() {
+        "now" => return Ok(now),
+        "today" => return start_of_day(Local::now().date_naive()),
+        "yesterday" => return start_of_day(Local::now().date_naive() - Duration::days(1)),
+        _ => {}
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(input, format) {
+            return local_to_utc(time);
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return start_of_day(date);
+    }
+
+    let offset = input
+        .strip_suffix("ago")
+        .map(str::trim_end)
+        .unwrap_or(input);
+    match parse_duration(offset) {
+        Ok(duration) => now
+            .checked_sub_signed(duration)
+            .ok_or_else(|| format!("Invalid time '{}': too far in the past", input).into()),
+        Err(_) => Err(format!(
+            "Invalid time '{}': expected e.g. 2h, 3d, yesterday, 2024-05-01 or an RFC 3339 timestamp",
+            input
+        )
+        .into()),
+    }
+}
+
+/// Parses a length of time such as `90s`, `15m`, `2h`, `180d`, `2w` or `3 days`.
+pub fn parse_duration(input: &str) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}': expected a number followed by a unit", input))?;
+
+    let duration = match unit.trim() {
+        "s" | "sec" | "secs" | "second" | "seconds" => Duration::try_seconds(amount),
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::try_minutes(amount),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Duration::try_hours(amount),
+        "d" | "day" | "days" => Duration::try_days(amount),
+        "w" | "week" | "weeks" => Duration::try_weeks(amount),
+        "mo" | "month" | "months" => amount.checked_mul(30).and_then(Duration::try_days),
+        "y" | "year" | "years" => amount.checked_mul(365).and_then(Duration::try_days),
+        unit => {
+            return Err(format!(
+                "Invalid duration '{}': unknown unit '{}' (use s, m, h, d, w, mo or y)",
+                input, unit
+            )
+            .into())
+        }
+    };
+
+    duration.ok_or_else(|| format!("Invalid duration '{}': too long", input).into())
+}
+
+fn start_of_day(date: NaiveDate) -> Result<DateTime<Utc>, Box<dyn std::error::Error + Send + Sync>> {
+    local_to_utc(date.and_hms_opt(0, 0, 0).ok_or("Invalid date")?)
+}
+
+fn local_to_utc(time: NaiveDateTime) -> Result<DateTime<Utc>, Box<dyn std::error::Error + Send + Sync>> {
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or_else(|| format!("Time '{}' does not exist in the local timezone", time).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_unit() {
+        let cases = [
+            ("90s", Duration::seconds(90)),
+            ("5 sec", Duration::seconds(5)),
+            ("15m", Duration::minutes(15)),
+            ("2 minutes", Duration::minutes(2)),
+            ("2h", Duration::hours(2)),
+            ("1 hr", Duration::hours(1)),
+            ("180d", Duration::days(180)),
+            ("3 days", Duration::days(3)),
+            ("2w", Duration::weeks(2)),
+            ("1 week", Duration::weeks(1)),
+            ("6mo", Duration::days(180)),
+            ("1 month", Duration::days(30)),
+            ("2y", Duration::days(730)),
+            ("1 year", Duration::days(365)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_duration(input).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for input in ["", "h", "-2h", "2", "2x", "2 fortnights", "1.5h"] {
+            assert!(parse_duration(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        for input in ["99999999999999y", "9999999999999999mo", "99999999999999999w", "99999999999999999999s"] {
+            assert!(parse_duration(input).is_err(), "{}", input);
+        }
+        assert!(parse_time("99999999999d ago").is_err());
+    }
+
+    #[test]
+    fn parses_relative_and_absolute_times() {
+        let before = Utc::now();
+        let time = parse_time("2 hours ago").unwrap();
+        assert!(time <= before - Duration::hours(2) + Duration::seconds(1));
+        assert!(time >= before - Duration::hours(2) - Duration::seconds(5));
+
+        assert_eq!(
+            parse_time("2024-05-01T12:00:00Z").unwrap(),
+            Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+        );
+        assert!(parse_time("yesterday").unwrap() < parse_time("today").unwrap());
+        assert!(parse_time("2024-05-01").is_ok());
+        assert!(parse_time("2024-05-01 13:45").is_ok());
+        assert!(parse_time("next tuesday").is_err());
+    }
+}
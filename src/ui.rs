@@ -1,8 +1,12 @@
-use crate::db::CommandHistoryEntry;
+use crate::commands::log::hostname;
+use crate::config;
+use crate::db::{CommandHistoryEntry, DatabaseManager, HistoryFilter, SessionSummary};
+use crate::redact::Redactor;
+use crate::runbook::{self, RunbookFormat};
 use chrono::{DateTime, Local};
 use chrono_humanize::HumanTime;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -11,33 +15,165 @@ use ratatui::{
     layout::{Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    widgets::{Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap},
     Frame, Terminal,
 };
-use std::io;
+use base64::prelude::*;
+use std::io::{self, Write};
+
+/// Maximum number of full-text search results shown at once.
+const SEARCH_RESULT_LIMIT: i64 = 500;
 
 fn get_session_color(session_id: i64) -> Color {
-    let colors = [
-        Color::Red,
-        Color::Green,
-        Color::Yellow,
-        Color::Blue,
-        Color::Magenta,
-        Color::Cyan,
-        Color::LightRed,
-        Color::LightGreen,
-        Color::LightYellow,
-        Color::LightBlue,
-        Color::LightMagenta,
-        Color::LightCyan,
-        Color::DarkGray,
-        Color::Gray,
-    ];
-    
+    let colors = &config::get_or_default().ui.session_colors;
     let index = (session_id as usize) % colors.len();
     colors[index]
 }
 
+/// What the shell should do with a command chosen in `recall pick`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickAction {
+    /// Put the command on the command line and run it
+    Execute,
+    /// Put the command on the command line for editing
+    Edit,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pick {
+    pub command: String,
+    pub action: PickAction,
+}
+
+/// The column the sessions screen is ordered by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionSort {
+    Started,
+    Duration,
+    Commands,
+    Host,
+    Directory,
+}
+
+impl SessionSort {
+    fn next(self) -> SessionSort {
+        match self {
+            SessionSort::Started => SessionSort::Duration,
+            SessionSort::Duration => SessionSort::Commands,
+            SessionSort::Commands => SessionSort::Host,
+            SessionSort::Host => SessionSort::Directory,
+            SessionSort::Directory => SessionSort::Started,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SessionSort::Started => "start time",
+            SessionSort::Duration => "duration",
+            SessionSort::Commands => "command count",
+            SessionSort::Host => "host",
+            SessionSort::Directory => "directory",
+        }
+    }
+
+    /// Times and counts read best largest first, names alphabetically.
+    fn descending_by_default(self) -> bool {
+        matches!(self, SessionSort::Started | SessionSort::Duration | SessionSort::Commands)
+    }
+}
+
+/// State of the sessions screen, which lists every recorded session.
+pub struct SessionsScreen {
+    /// Every session, in the current order
+    pub sessions: Vec<SessionSummary>,
+    /// Indexes into `sessions` of the ones matching the search
+    pub visible: Vec<usize>,
+    pub table_state: TableState,
+    pub sort: SessionSort,
+    pub descending: bool,
+    pub search_mode: bool,
+    pub search_query: String,
+}
+
+impl SessionsScreen {
+    pub fn new(sessions: Vec<SessionSummary>) -> SessionsScreen {
+        let mut screen = SessionsScreen {
+            sessions,
+            visible: Vec::new(),
+            table_state: TableState::default(),
+            sort: SessionSort::Started,
+            descending: true,
+            search_mode: false,
+            search_query: String::new(),
+        };
+        screen.apply_sort();
+        screen
+    }
+
+    pub fn selected(&self) -> Option<&SessionSummary> {
+        let index = *self.visible.get(self.table_state.selected()?)?;
+        self.sessions.get(index)
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.descending = self.sort.descending_by_default();
+        self.apply_sort();
+    }
+
+    pub fn reverse(&mut self) {
+        self.descending = !self.descending;
+        self.apply_sort();
+    }
+
+    fn apply_sort(&mut self) {
+        let selected = self.selected().map(|session| session.id);
+        match self.sort {
+            SessionSort::Started => self.sessions.sort_by_key(|s| (s.started_at, s.id)),
+            SessionSort::Duration => self.sessions.sort_by_key(|s| (s.duration(), s.id)),
+            SessionSort::Commands => self.sessions.sort_by_key(|s| (s.commands, s.id)),
+            SessionSort::Host => self.sessions.sort_by(|a, b| a.details.hostname.cmp(&b.details.hostname).then(a.id.cmp(&b.id))),
+            SessionSort::Directory => self.sessions.sort_by(|a, b| a.cwd.cmp(&b.cwd).then(a.id.cmp(&b.id))),
+        }
+        if self.descending {
+            self.sessions.reverse();
+        }
+        self.apply_search(selected);
+    }
+
+    /// Shows the sessions whose id, name, host, directory or key contain the
+    /// search text, keeping `selected` highlighted if it is still shown.
+    fn apply_search(&mut self, selected: Option<i64>) {
+        let query = self.search_query.to_lowercase();
+        let matches = |session: &SessionSummary| {
+            query.is_empty()
+                || session.id.to_string() == query
+                || session.name.as_deref().is_some_and(|n| n.to_lowercase().contains(&query))
+                || session.key.to_lowercase().contains(&query)
+                || session.details.hostname.as_deref().is_some_and(|h| h.to_lowercase().contains(&query))
+                || session.cwd.as_deref().is_some_and(|c| c.to_lowercase().contains(&query))
+        };
+        self.visible = (0..self.sessions.len()).filter(|&i| matches(&self.sessions[i])).collect();
+
+        let position = selected
+            .and_then(|id| self.visible.iter().position(|&i| self.sessions[i].id == id))
+            .unwrap_or(0);
+        self.table_state.select((!self.visible.is_empty()).then_some(position));
+    }
+
+    pub fn next(&mut self) {
+        if let Some(i) = self.table_state.selected() {
+            self.table_state.select(Some((i + 1).min(self.visible.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Some(i) = self.table_state.selected() {
+            self.table_state.select(Some(i.saturating_sub(1)));
+        }
+    }
+}
+
 pub struct App {
     pub commands: Vec<CommandHistoryEntry>,
     pub list_state: ListState,
@@ -48,10 +184,28 @@ pub struct App {
     pub search_mode: bool,
     pub search_query: String,
     pub filtered_commands: Vec<CommandHistoryEntry>,
+    pub status_message: Option<String>,
+    pub db_manager: DatabaseManager,
+    /// Text waiting to be sent to the clipboard through the terminal, and
+    /// what it is for the status message
+    pub clipboard: Option<(String, &'static str)>,
+    /// Whether the interface was opened by `recall pick` to choose a command
+    pub picking: bool,
+    pub picked: Option<Pick>,
+    /// Command waiting for the user to confirm its deletion
+    pub pending_delete: Option<CommandHistoryEntry>,
+    /// The sessions screen, while it is open
+    pub sessions: Option<SessionsScreen>,
+    /// Where the range marked with `v` in the session view starts
+    pub range_start: Option<usize>,
+    /// This machine's hostname, so commands from other hosts can be marked
+    pub local_host: Option<String>,
+    /// Host the list is limited to, toggled with `H`
+    pub host_filter: Option<String>,
 }
 
 impl App {
-    pub fn new(commands: Vec<CommandHistoryEntry>) -> App {
+    pub fn new(commands: Vec<CommandHistoryEntry>, db_manager: DatabaseManager) -> App {
         let mut reversed_commands = commands.clone();
         reversed_commands.reverse();
         
@@ -65,6 +219,16 @@ impl App {
             search_mode: false,
             search_query: String::new(),
             filtered_commands: reversed_commands,
+            status_message: None,
+            db_manager,
+            clipboard: None,
+            picking: false,
+            picked: None,
+            pending_delete: None,
+            sessions: None,
+            range_start: None,
+            local_host: hostname(),
+            host_filter: None,
         };
         if !app.commands.is_empty() {
             app.list_state.select(Some(app.commands.len() - 1));
@@ -75,20 +239,85 @@ impl App {
     pub fn enter_session_view(&mut self) {
         if let Some(selected) = self.list_state.selected() {
             if let Some(cmd) = self.commands.get(selected) {
-                let session_id = cmd.session_id;
-                let mut session_commands: Vec<CommandHistoryEntry> = self.all_commands
-                    .iter()
-                    .cloned()
-                    .filter(|c| c.session_id == session_id)
-                    .collect();
-                session_commands.reverse();
-                self.commands = session_commands;
-                self.session_view = Some(session_id);
-                self.list_state = ListState::default();
-                if !self.commands.is_empty() {
-                    self.list_state.select(Some(self.commands.len() - 1));
+                self.open_session(cmd.session_id);
+            }
+        }
+    }
+
+    /// Shows every command of a session, loaded from the database rather
+    /// than taken from the recent commands in memory.
+    pub fn open_session(&mut self, session_id: i64) {
+        let filter = HistoryFilter {
+            session_id: Some(session_id),
+            ..Default::default()
+        };
+        let mut session_commands = match block_on(self.db_manager.query_commands(&filter)) {
+            Ok(commands) => commands,
+            Err(e) => {
+                self.status_message = Some(format!("Failed to load session: {}", e));
+                return;
+            }
+        };
+        session_commands.reverse();
+        self.commands = session_commands;
+        self.session_view = Some(session_id);
+        self.range_start = None;
+        self.list_state = ListState::default();
+        if !self.commands.is_empty() {
+            self.list_state.select(Some(self.commands.len() - 1));
+        }
+    }
+
+    pub fn open_sessions(&mut self) {
+        match block_on(self.db_manager.list_sessions(None)) {
+            Ok(sessions) => self.sessions = Some(SessionsScreen::new(sessions)),
+            Err(e) => self.status_message = Some(format!("Failed to load sessions: {}", e)),
+        }
+    }
+
+    pub fn handle_sessions_key(&mut self, code: KeyCode) {
+        let Some(screen) = self.sessions.as_mut() else {
+            return;
+        };
+
+        if screen.search_mode {
+            match code {
+                KeyCode::Char(c) => {
+                    screen.search_query.push(c);
+                    screen.apply_search(None);
+                }
+                KeyCode::Backspace => {
+                    screen.search_query.pop();
+                    screen.apply_search(None);
+                }
+                KeyCode::Esc => {
+                    screen.search_mode = false;
+                    screen.search_query.clear();
+                    screen.apply_search(None);
+                }
+                KeyCode::Enter => screen.search_mode = false,
+                KeyCode::Down => screen.next(),
+                KeyCode::Up => screen.previous(),
+                _ => {}
+            }
+            return;
+        }
+
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => screen.next(),
+            KeyCode::Up | KeyCode::Char('k') => screen.previous(),
+            KeyCode::Enter => {
+                if let Some(id) = screen.selected().map(|session| session.id) {
+                    self.open_session(id);
                 }
             }
+            KeyCode::Char('o') => screen.cycle_sort(),
+            KeyCode::Char('r') => screen.reverse(),
+            KeyCode::Char('/') => screen.search_mode = true,
+            KeyCode::Char('h') | KeyCode::Char('?') => self.show_help = true,
+            KeyCode::Char('q') => self.should_quit = true,
+            KeyCode::Char('b') | KeyCode::Esc => self.sessions = None,
+            _ => {}
         }
     }
 
@@ -98,6 +327,7 @@ impl App {
         self.commands = reversed_commands.clone();
         self.filtered_commands = reversed_commands;
         self.session_view = None;
+        self.range_start = None;
         self.search_mode = false;
         self.search_query.clear();
         self.list_state = ListState::default();
@@ -135,16 +365,28 @@ impl App {
             self.commands = reversed_commands.clone();
             self.filtered_commands = reversed_commands;
         } else {
-            let query = self.search_query.to_lowercase();
-            let filtered: Vec<CommandHistoryEntry> = self.all_commands
-                .iter()
-                .filter(|cmd| cmd.command.to_lowercase().contains(&query))
-                .cloned()
-                .collect();
-            let mut reversed_filtered = filtered.clone();
-            reversed_filtered.reverse();
-            self.commands = reversed_filtered.clone();
-            self.filtered_commands = reversed_filtered;
+            let mut query = self.search_query.clone();
+            // Treat the word being typed as a prefix so results update as you type
+            if !query.ends_with(char::is_whitespace) && !query.ends_with('"') {
+                query.push('*');
+            }
+            let filter = HistoryFilter {
+                host: self.host_filter.clone(),
+                limit: Some(SEARCH_RESULT_LIMIT),
+                ..Default::default()
+            };
+            match block_on(self.db_manager.search(&query, &filter)) {
+                Ok(mut results) => {
+                    // Best match at the bottom, where the selection starts
+                    results.reverse();
+                    self.commands = results.clone();
+                    self.filtered_commands = results;
+                }
+                Err(e) => {
+                    self.status_message = Some(format!("Search failed: {}", e));
+                    return;
+                }
+            }
         }
         self.list_state = ListState::default();
         if !self.commands.is_empty() {
@@ -152,6 +394,36 @@ impl App {
         }
     }
 
+    /// Switches the list between commands from every host and commands
+    /// from this machine only.
+    pub fn toggle_host_filter(&mut self) {
+        let host_filter = match (&self.host_filter, &self.local_host) {
+            (Some(_), _) => None,
+            (None, Some(local_host)) => Some(local_host.clone()),
+            (None, None) => {
+                self.status_message = Some("Could not determine this machine's hostname".to_string());
+                return;
+            }
+        };
+        let filter = HistoryFilter {
+            host: host_filter.clone(),
+            limit: Some(config::get_or_default().ui.history_limit),
+            ..Default::default()
+        };
+        match block_on(self.db_manager.query_commands(&filter)) {
+            Ok(commands) => {
+                self.all_commands = commands;
+                self.status_message = Some(match &host_filter {
+                    Some(host) => format!("Showing commands from {}", host),
+                    None => "Showing commands from all hosts".to_string(),
+                });
+                self.host_filter = host_filter;
+                self.apply_search_filter();
+            }
+            Err(e) => self.status_message = Some(format!("Failed to load commands: {}", e)),
+        }
+    }
+
     pub fn next(&mut self) {
         if self.commands.is_empty() {
             return;
@@ -191,20 +463,165 @@ impl App {
     pub fn toggle_help(&mut self) {
         self.show_help = !self.show_help;
     }
+
+    pub fn copy_selected(&mut self) {
+        let Some(cmd) = self.list_state.selected().and_then(|i| self.commands.get(i)) else {
+            return;
+        };
+        self.clipboard = Some((cmd.full_command().to_string(), "command"));
+    }
+
+    /// Starts a range at the selected command of the session view, or
+    /// clears the one already started.
+    pub fn toggle_range(&mut self) {
+        self.range_start = match self.range_start {
+            Some(_) => None,
+            None => self.list_state.selected(),
+        };
+    }
+
+    /// The marked range of the session view up to the selected command,
+    /// or just the selected command when no range is marked.
+    pub fn selected_range(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        let cursor = self.list_state.selected()?;
+        let start = self.range_start.unwrap_or(cursor);
+        Some(start.min(cursor)..=start.max(cursor))
+    }
+
+    /// Copies the selected range of the session as a script or runbook,
+    /// redacted like `recall sessions export`.
+    pub fn export_range(&mut self, format: RunbookFormat) {
+        let (Some(session_id), Some(range)) = (self.session_view, self.selected_range()) else {
+            return;
+        };
+        let Some(entries) = self.commands.get(range) else {
+            return;
+        };
+
+        let result = (|| -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+            let redactor = Redactor::from_config(&config::get_or_default().redact)?;
+            let session = block_on(self.db_manager.get_session(session_id))?.ok_or("The session no longer exists")?;
+            let mut out = Vec::new();
+            runbook::render(&mut out, format, &session, entries, &redactor, true)?;
+            Ok(String::from_utf8(out)?)
+        })();
+        match result {
+            Ok(text) => {
+                let what = match format {
+                    RunbookFormat::Sh => "shell script",
+                    RunbookFormat::Markdown => "runbook",
+                };
+                self.clipboard = Some((text, what));
+                self.range_start = None;
+            }
+            Err(e) => self.status_message = Some(format!("Failed to export session: {}", e)),
+        }
+    }
+
+    pub fn pick_selected(&mut self, action: PickAction) {
+        let Some(cmd) = self.list_state.selected().and_then(|i| self.commands.get(i)) else {
+            return;
+        };
+        self.picked = Some(Pick {
+            command: cmd.full_command().to_string(),
+            action,
+        });
+        self.should_quit = true;
+    }
+
+    pub fn request_delete(&mut self) {
+        self.pending_delete = self.list_state.selected().and_then(|i| self.commands.get(i)).cloned();
+    }
+
+    /// Deletes the command awaiting confirmation, with its whole command
+    /// line, or forgets about it when `confirmed` is false.
+    pub fn resolve_delete(&mut self, confirmed: bool) {
+        let Some(entry) = self.pending_delete.take() else {
+            return;
+        };
+        let Some(id) = entry.id.filter(|_| confirmed) else {
+            return;
+        };
+
+        let result = block_on(async {
+            self.db_manager.delete_entries(&[id]).await?;
+            // Vacuum so the deleted text is not left in free pages
+            self.db_manager.vacuum(false).await
+        });
+        if let Err(e) = result {
+            self.status_message = Some(format!("Failed to delete command: {}", e));
+            return;
+        }
+
+        let same_line = |c: &CommandHistoryEntry| match entry.invocation_id {
+            Some(invocation_id) => c.invocation_id == Some(invocation_id),
+            None => c.id == entry.id,
+        };
+        self.all_commands.retain(|c| !same_line(c));
+        self.commands.retain(|c| !same_line(c));
+        self.filtered_commands.retain(|c| !same_line(c));
+
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state
+            .select((!self.commands.is_empty()).then(|| selected.min(self.commands.len() - 1)));
+        self.range_start = None;
+        self.status_message = Some("Deleted command from history".to_string());
+    }
+}
+
+/// Runs a database future from the synchronous event loop.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(future))
+}
+
+/// Copies text to the system clipboard through the terminal using OSC 52,
+/// which also works over SSH.
+fn copy_to_clipboard<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    write!(out, "\x1b]52;c;{}\x07", BASE64_STANDARD.encode(text))?;
+    out.flush()
 }
 
 pub fn run_tui(
     commands: Vec<CommandHistoryEntry>,
+    db_manager: DatabaseManager,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let app = App::new(commands, db_manager);
+    run_on(io::stdout(), app)?;
+    Ok(())
+}
+
+/// Runs the interface on the controlling terminal so stdout stays free for
+/// the result, and returns the command the user chose, if any. Typing starts
+/// a search right away, beginning with `query`.
+pub fn run_picker(
+    commands: Vec<CommandHistoryEntry>,
+    db_manager: DatabaseManager,
+    query: Option<&str>,
+) -> Result<Option<Pick>, Box<dyn std::error::Error + Send + Sync>> {
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| format!("Could not open the terminal: {}", e))?;
+
+    let mut app = App::new(commands, db_manager);
+    app.picking = true;
+    app.search_mode = true;
+    if let Some(query) = query.filter(|q| !q.is_empty()) {
+        app.search_query = query.to_string();
+        app.apply_search_filter();
+    }
+
+    Ok(run_on(tty, app)?.picked)
+}
+
+fn run_on<W: Write>(mut out: W, app: App) -> Result<App, Box<dyn std::error::Error + Send + Sync>> {
     // Setup terminal
     enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    execute!(out, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(out);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app and run it
-    let app = App::new(commands);
     let res = run_app(&mut terminal, app);
 
     // Restore terminal
@@ -216,43 +633,54 @@ pub fn run_tui(
     )?;
     terminal.show_cursor()?;
 
-    if let Err(err) = res {
-        println!("{:?}", err);
-    }
-
-    Ok(())
+    Ok(res?)
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+fn run_app<B: Backend + Write>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<App> {
     loop {
         terminal.draw(|f| ui(f, &mut app))?;
 
         if let Event::Key(key) = event::read()? {
+            app.status_message = None;
             match key.code {
-                KeyCode::Char('q') => {
-                    if !app.search_mode {
-                        app.should_quit = true;
-                    }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app.should_quit = true;
                 }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if !app.search_mode {
-                        app.next();
-                    }
+                _ if app.pending_delete.is_some() => {
+                    app.resolve_delete(key.code == KeyCode::Char('y'));
                 }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if !app.search_mode {
-                        app.previous();
-                    }
+                _ if app.sessions.is_some() && app.session_view.is_none() && !app.show_help => {
+                    app.handle_sessions_key(key.code);
                 }
-                KeyCode::Enter => {
-                    if app.session_view.is_none() && !app.search_mode {
-                        app.enter_session_view();
-                    }
+                KeyCode::Enter if app.picking => {
+                    app.pick_selected(PickAction::Execute);
                 }
-                KeyCode::Backspace => {
-                    if app.search_mode {
-                        app.remove_search_char();
-                    }
+                KeyCode::Tab if app.picking => {
+                    app.pick_selected(PickAction::Edit);
+                }
+                KeyCode::Esc if app.picking && !app.show_help && app.session_view.is_none() => {
+                    app.should_quit = true;
+                }
+                KeyCode::Down if app.search_mode => {
+                    app.next();
+                }
+                KeyCode::Up if app.search_mode => {
+                    app.previous();
+                }
+                KeyCode::Char('q') if !app.search_mode => {
+                    app.should_quit = true;
+                }
+                KeyCode::Down | KeyCode::Char('j') if !app.search_mode => {
+                    app.next();
+                }
+                KeyCode::Up | KeyCode::Char('k') if !app.search_mode => {
+                    app.previous();
+                }
+                KeyCode::Enter if app.session_view.is_none() && !app.search_mode => {
+                    app.enter_session_view();
+                }
+                KeyCode::Backspace if app.search_mode => {
+                    app.remove_search_char();
                 }
                 KeyCode::Char(c) => {
                     if app.search_mode {
@@ -263,6 +691,20 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                         app.toggle_help();
                     } else if c == '/' && app.session_view.is_none() && !app.search_mode {
                         app.toggle_search();
+                    } else if c == 'y' {
+                        app.copy_selected();
+                    } else if c == 'd' {
+                        app.request_delete();
+                    } else if c == 's' && app.session_view.is_none() && !app.picking {
+                        app.open_sessions();
+                    } else if c == 'H' && app.session_view.is_none() {
+                        app.toggle_host_filter();
+                    } else if c == 'v' && app.session_view.is_some() {
+                        app.toggle_range();
+                    } else if c == 'e' && app.session_view.is_some() {
+                        app.export_range(RunbookFormat::Sh);
+                    } else if c == 'E' && app.session_view.is_some() {
+                        app.export_range(RunbookFormat::Markdown);
                     } else if c == 'j' && !app.search_mode {
                         app.next();
                     } else if c == 'k' && !app.search_mode {
@@ -294,14 +736,36 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
             }
         }
 
+        if let Some((text, what)) = app.clipboard.take() {
+            app.status_message = Some(match copy_to_clipboard(terminal.backend_mut(), &text) {
+                Ok(()) => format!("Copied {} to clipboard", what),
+                Err(e) => format!("Failed to copy {}: {}", what, e),
+            });
+        }
+
         if app.should_quit {
             break;
         }
     }
-    Ok(())
+    Ok(app)
 }
 
 fn ui(f: &mut Frame, app: &mut App) {
+    if app.session_view.is_none() && app.sessions.is_some() {
+        render_sessions(f, app);
+    } else {
+        render_commands(f, app);
+    }
+
+    if app.show_help {
+        render_help(f);
+    }
+    if let Some(entry) = &app.pending_delete {
+        render_delete_confirmation(f, entry);
+    }
+}
+
+fn render_commands(f: &mut Frame, app: &mut App) {
     let chunks = if app.session_view.is_none() {
         // All commands page with search bar
         Layout::default()
@@ -332,8 +796,10 @@ fn ui(f: &mut Frame, app: &mut App) {
             .split(f.area())
     };
 
-    let title_text = if let Some(_session_id) = app.session_view {
-        "Session".to_string()
+    let title_text = if let Some(session_id) = app.session_view {
+        format!("Session {}", session_id)
+    } else if let Some(host) = &app.host_filter {
+        format!("Commands on {}", host)
     } else {
         "All Commands".to_string()
     };
@@ -348,9 +814,11 @@ fn ui(f: &mut Frame, app: &mut App) {
 
     let items: Vec<ListItem> = if app.session_view.is_some() {
         // Session view: show pwd and binary
+        let range = app.range_start.and(app.selected_range());
         app.commands
             .iter()
-            .map(|cmd| {
+            .enumerate()
+            .map(|(i, cmd)| {
                 let local_time: DateTime<Local> = cmd.timestamp.into();
                 let human_time = HumanTime::from(local_time);
                 let time_str = human_time.to_string();
@@ -362,6 +830,47 @@ fn ui(f: &mut Frame, app: &mut App) {
                 };
 
                 let session_color = get_session_color(cmd.session_id);
+
+                let mut binary_line = vec![
+                    Span::styled("  Binary: ", Style::default().fg(Color::Gray)),
+                    Span::styled(&cmd.binary, Style::default().fg(Color::Yellow)),
+                ];
+                if let Some(wrappers) = &cmd.wrappers {
+                    binary_line.push(Span::styled(" via ", Style::default().fg(Color::Gray)));
+                    binary_line.push(Span::styled(wrappers, Style::default().fg(Color::LightRed)));
+                }
+                if let Some(env) = &cmd.env {
+                    binary_line.push(Span::styled(" • Env: ", Style::default().fg(Color::Gray)));
+                    binary_line.push(Span::styled(env, Style::default().fg(Color::Cyan)));
+                }
+                binary_line.push(Span::styled(" • PWD: ", Style::default().fg(Color::Gray)));
+                binary_line.push(Span::styled(&cmd.pwd, Style::default().fg(Color::Blue)));
+
+                let mut status_line = vec![
+                    Span::styled("  ", Style::default()),
+                    Span::styled(display_time, Style::default().fg(Color::Green)),
+                ];
+                if let Some(duration_ms) = cmd.duration_ms {
+                    status_line.push(Span::styled(" • Took: ", Style::default().fg(Color::Gray)));
+                    status_line.push(Span::styled(
+                        format_duration(duration_ms),
+                        Style::default().fg(Color::Magenta),
+                    ));
+                }
+                match cmd.exit_code {
+                    Some(0) => {
+                        status_line.push(Span::styled(" • Exit: ", Style::default().fg(Color::Gray)));
+                        status_line.push(Span::styled("0", Style::default().fg(Color::Green)));
+                    }
+                    Some(code) => {
+                        status_line.push(Span::styled(" • Exit: ", Style::default().fg(Color::Gray)));
+                        status_line.push(Span::styled(
+                            code.to_string(),
+                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    None => {}
+                }
                 
                 let content = vec![
                     Line::from(vec![
@@ -371,26 +880,23 @@ fn ui(f: &mut Frame, app: &mut App) {
                                 .fg(session_color)
                                 .add_modifier(Modifier::BOLD),
                         ),
+                        failure_marker(cmd),
                         Span::styled(
-                            &cmd.command,
+                            cmd.full_command(),
                             Style::default()
                                 .fg(Color::White)
                                 .add_modifier(Modifier::BOLD),
                         ),
                     ]),
-                    Line::from(vec![
-                        Span::styled("  Binary: ", Style::default().fg(Color::Gray)),
-                        Span::styled(&cmd.binary, Style::default().fg(Color::Yellow)),
-                        Span::styled(" • PWD: ", Style::default().fg(Color::Gray)),
-                        Span::styled(&cmd.pwd, Style::default().fg(Color::Blue)),
-                    ]),
-                    Line::from(vec![
-                        Span::styled("  ", Style::default()),
-                        Span::styled(display_time, Style::default().fg(Color::Green)),
-                    ]),
+                    Line::from(binary_line),
+                    Line::from(status_line),
                     Line::from(""),
                 ];
-                ListItem::new(content)
+                if range.as_ref().is_some_and(|range| range.contains(&i)) {
+                    ListItem::new(content).style(Style::default().bg(Color::DarkGray))
+                } else {
+                    ListItem::new(content)
+                }
             })
             .collect()
     } else {
@@ -409,25 +915,31 @@ fn ui(f: &mut Frame, app: &mut App) {
                 };
 
                 let session_color = get_session_color(cmd.session_id);
-                
-                let content = vec![
-                    Line::from(vec![
-                        Span::styled(
-                            "● ",
-                            Style::default()
-                                .fg(session_color)
-                                .add_modifier(Modifier::BOLD),
-                        ),
-                        Span::styled(display_time, Style::default().fg(session_color)),
-                        Span::styled(" → ", Style::default().fg(Color::Gray)),
-                        Span::styled(
-                            &cmd.command,
-                            Style::default()
-                                .fg(Color::White)
-                                .add_modifier(Modifier::BOLD),
-                        ),
-                    ]),
+
+                let mut line = vec![
+                    Span::styled(
+                        "● ",
+                        Style::default()
+                            .fg(session_color)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(display_time, Style::default().fg(session_color)),
                 ];
+                // Commands merged in from other machines say where they ran
+                if let Some(host) = cmd.hostname.as_ref().filter(|host| Some(*host) != app.local_host.as_ref()) {
+                    line.push(Span::styled(format!(" @{}", host), Style::default().fg(Color::LightMagenta)));
+                }
+                line.extend([
+                    Span::styled(" → ", Style::default().fg(Color::Gray)),
+                    failure_marker(cmd),
+                    Span::styled(
+                        cmd.full_command(),
+                        Style::default()
+                            .fg(Color::White)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                ]);
+                let content = vec![Line::from(line)];
                 ListItem::new(content)
             })
             .collect()
@@ -446,14 +958,18 @@ fn ui(f: &mut Frame, app: &mut App) {
     f.render_stateful_widget(list, chunks[1], &mut app.list_state);
 
     // Instructions
-    let instructions = if app.commands.is_empty() {
+    let instructions = if let Some(message) = &app.status_message {
+        Paragraph::new(message.as_str())
+    } else if app.commands.is_empty() {
         Paragraph::new("No commands found")
     } else if app.session_view.is_some() {
-        Paragraph::new("Viewing session • ↑/↓ or j/k to navigate • b/Esc to go back • q to quit")
+        Paragraph::new("Viewing session • ↑/↓ or j/k to navigate • y to copy • v to mark a range • e/E to copy as script/runbook • d to delete • b/Esc to go back")
+    } else if app.picking {
+        Paragraph::new("Type to search • ↑/↓ to navigate • Enter to run • Tab to edit • Esc to cancel")
     } else if app.search_mode {
         Paragraph::new("Search mode • Type to search • Esc to exit search • q to quit")
     } else {
-        Paragraph::new("Use ↑/↓ or j/k to navigate • Enter to view session • / to search • h/? for help • q/Esc to quit")
+        Paragraph::new("Use ↑/↓ or j/k to navigate • Enter to view session • / to search • d to delete • H for this host only • h/? for help • q/Esc to quit")
     };
 
     let instructions = instructions
@@ -484,49 +1000,249 @@ fn ui(f: &mut Frame, app: &mut App) {
         f.render_widget(search_bar, chunks[3]);
     }
 
-    // Help popup (unchanged)
-    if app.show_help {
-        let help_text = vec![
-            Line::from(""),
-            Line::from(vec![Span::styled(
-                "Navigation:",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from("  ↑/k        Move up"),
-            Line::from("  ↓/j        Move down"),
-            Line::from(""),
-            Line::from(vec![Span::styled(
-                "Actions:",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from("  Enter      View session details"),
-            Line::from("  /          Search commands"),
-            Line::from("  h/?        Show/hide this help"),
-            Line::from("  b/Esc      Go back/quit"),
-            Line::from("  q          Quit application"),
-            Line::from(""),
-            Line::from(vec![Span::styled(
-                "Info:",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from("  Commands are sorted by recency (newest at bottom)"),
-            Line::from("  Colored circles (●) represent different sessions"),
-            Line::from(""),
-        ];
-
-        let help_paragraph = Paragraph::new(help_text)
-            .block(Block::default().title("Help").borders(Borders::ALL))
-            .style(Style::default().fg(Color::White).bg(Color::Black));
-
-        let area = centered_rect(60, 70, f.area());
-        f.render_widget(Clear, area);
-        f.render_widget(help_paragraph, area);
+}
+
+fn render_sessions(f: &mut Frame, app: &mut App) {
+    let Some(screen) = app.sessions.as_mut() else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(3),
+            Constraint::Length(3),
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new(format!("Sessions ({})", screen.visible.len()))
+        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let arrow = if screen.descending { " ▼" } else { " ▲" };
+    let header_cell = |name: &str, sort: Option<SessionSort>| {
+        let text = match sort {
+            Some(sort) if sort == screen.sort => format!("{}{}", name, arrow),
+            _ => name.to_string(),
+        };
+        Cell::from(text)
+    };
+    let header = Row::new(vec![
+        header_cell("Session", None),
+        header_cell("Started", Some(SessionSort::Started)),
+        header_cell("Ended", None),
+        header_cell("Duration", Some(SessionSort::Duration)),
+        header_cell("Commands", Some(SessionSort::Commands)),
+        header_cell("Host", Some(SessionSort::Host)),
+        header_cell("Directory", Some(SessionSort::Directory)),
+    ])
+    .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+
+    let format_time = |time: DateTime<chrono::Utc>| DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M").to_string();
+    let rows: Vec<Row> = screen
+        .visible
+        .iter()
+        .map(|&i| {
+            let session = &screen.sessions[i];
+            let color = get_session_color(session.id);
+            Row::new(vec![
+                Cell::from(Line::from(vec![
+                    Span::styled("● ", Style::default().fg(color).add_modifier(Modifier::BOLD)),
+                    Span::raw(session.id.to_string()),
+                    Span::styled(
+                        session.name.as_deref().map(|name| format!(" {}", name)).unwrap_or_default(),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                ])),
+                Cell::from(format_time(session.started_at)).style(Style::default().fg(Color::Green)),
+                if session.is_open() {
+                    Cell::from("● open").style(Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                } else {
+                    Cell::from(format!(
+                        "{} {}",
+                        session.ended_at.map(format_time).unwrap_or_default(),
+                        session.exit_reason.as_deref().unwrap_or("")
+                    ))
+                    .style(Style::default().fg(Color::DarkGray))
+                },
+                Cell::from(
+                    session
+                        .duration()
+                        .map(|d| format_duration(d.num_milliseconds()))
+                        .unwrap_or_else(|| "—".to_string()),
+                )
+                .style(Style::default().fg(Color::Magenta)),
+                Cell::from(session.commands.to_string()),
+                Cell::from(session.details.hostname.clone().unwrap_or_else(|| "—".to_string())),
+                Cell::from(session.cwd.clone().unwrap_or_else(|| "—".to_string()))
+                    .style(Style::default().fg(Color::Blue)),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(18),
+            Constraint::Length(17),
+            Constraint::Length(24),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(16),
+            Constraint::Min(10),
+        ],
+    )
+    .header(header)
+    .block(Block::default().borders(Borders::ALL))
+    .row_highlight_style(
+        Style::default()
+            .bg(Color::LightBlue)
+            .fg(Color::Black)
+            .add_modifier(Modifier::BOLD),
+    )
+    .highlight_symbol("→ ");
+    f.render_stateful_widget(table, chunks[1], &mut screen.table_state);
+
+    let instructions = if let Some(message) = &app.status_message {
+        message.clone()
+    } else if screen.search_mode {
+        "Type to filter by id, name, host, directory or key • Enter to keep • Esc to clear".to_string()
+    } else {
+        format!(
+            "↑/↓ or j/k to navigate • Enter to open session • o to sort (by {}) • r to reverse • / to search • b/Esc to go back",
+            screen.sort.label()
+        )
+    };
+    let instructions = Paragraph::new(instructions)
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(instructions, chunks[2]);
+
+    let (search_text, search_title, search_style) = if screen.search_mode {
+        (format!("Search: {}_", screen.search_query), "Search (active)", Style::default().fg(Color::White))
+    } else if screen.search_query.is_empty() {
+        ("Press / to search...".to_string(), "Search", Style::default().fg(Color::DarkGray))
+    } else {
+        (format!("Search: {}", screen.search_query), "Search", Style::default().fg(Color::DarkGray))
+    };
+    let search_bar = Paragraph::new(search_text)
+        .style(search_style)
+        .block(Block::default().borders(Borders::ALL).title(search_title));
+    f.render_widget(search_bar, chunks[3]);
+}
+
+fn render_help(f: &mut Frame) {
+    let help_text = vec![
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "Navigation:",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        Line::from("  ↑/k        Move up"),
+        Line::from("  ↓/j        Move down"),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "Actions:",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        Line::from("  Enter      View session details"),
+        Line::from("  /          Search commands"),
+        Line::from("  y          Copy full command line"),
+        Line::from("  d          Delete command line from history"),
+        Line::from("  s          Browse all sessions"),
+        Line::from("  H          Show this host only/all hosts"),
+        Line::from("  v          Mark a range in a session"),
+        Line::from("  e/E        Copy the range as a shell script/runbook"),
+        Line::from("  h/?        Show/hide this help"),
+        Line::from("  b/Esc      Go back/quit"),
+        Line::from("  q          Quit application"),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "Sessions:",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        Line::from("  Enter      Open the session's full history"),
+        Line::from("  o          Change the sort column"),
+        Line::from("  r          Reverse the sort order"),
+        Line::from("  /          Filter by id, name, host, directory or key"),
+        Line::from(""),
+        Line::from(vec![Span::styled(
+            "Info:",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        Line::from("  Commands are sorted by recency (newest at bottom)"),
+        Line::from("  Search covers all history: \"phrase\", prefix*, AND/OR/NOT, -exclude"),
+        Line::from("  Colored circles (●) represent different sessions"),
+        Line::from("  A red ✗ marks commands that exited with an error"),
+        Line::from(""),
+    ];
+
+    let help_paragraph = Paragraph::new(help_text)
+        .block(Block::default().title("Help").borders(Borders::ALL))
+        .style(Style::default().fg(Color::White).bg(Color::Black));
+
+    let area = centered_rect(60, 70, f.area());
+    f.render_widget(Clear, area);
+    f.render_widget(help_paragraph, area);
+}
+
+/// Asks whether to delete the selected command line.
+fn render_delete_confirmation(f: &mut Frame, entry: &CommandHistoryEntry) {
+    let text = vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            entry.full_command(),
+            Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from("The whole command line is removed from the history and the search index."),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+            Span::raw(" to delete • any other key to cancel"),
+        ]),
+    ];
+
+    let popup = Paragraph::new(text)
+        .block(Block::default().title("Delete command?").borders(Borders::ALL))
+        .style(Style::default().fg(Color::White).bg(Color::Black))
+        .wrap(Wrap { trim: false });
+
+    let area = centered_rect(60, 40, f.area());
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+fn failure_marker(cmd: &CommandHistoryEntry) -> Span<'static> {
+    match cmd.exit_code {
+        Some(code) if code != 0 => Span::styled(
+            "✗ ",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        _ => Span::raw(""),
+    }
+}
+
+pub fn format_duration(duration_ms: i64) -> String {
+    if duration_ms < 1000 {
+        format!("{}ms", duration_ms)
+    } else if duration_ms < 60_000 {
+        format!("{:.1}s", duration_ms as f64 / 1000.0)
+    } else if duration_ms < 3_600_000 {
+        format!("{}m {}s", duration_ms / 60_000, (duration_ms % 60_000) / 1000)
+    } else {
+        format!("{}h {}m", duration_ms / 3_600_000, (duration_ms % 3_600_000) / 60_000)
     }
 }
 
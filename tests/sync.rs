@@ -0,0 +1,469 @@
+//! Syncs recall databases through a stand-in for sqld: a small HTTP server
+//! that speaks the Hrana 3 protocol libsql's remote client uses, backed by a
+//! local database. Each "machine" is a recall process with its own database.
+
+use libsql::{Builder, Connection, Database, Value};
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+const TOKEN: &str = "sync-test-token";
+
+struct Server {
+    db: Database,
+    /// Open streams by baton, each with its own connection so that
+    /// transactions of different clients stay apart
+    streams: Mutex<HashMap<String, Connection>>,
+    batons: AtomicU64,
+}
+
+impl Server {
+    async fn stream(&self, baton: Option<&str>) -> (String, Connection) {
+        if let Some(baton) = baton {
+            if let Some(conn) = self.streams.lock().await.remove(baton) {
+                return (baton.to_string(), conn);
+            }
+        }
+        let baton = format!("baton-{}", self.batons.fetch_add(1, Ordering::SeqCst));
+        (baton, self.db.connect().unwrap())
+    }
+
+    async fn pipeline(&self, request: &Json) -> String {
+        let (baton, conn) = self.stream(request["baton"].as_str()).await;
+        let mut open = true;
+        let mut results = Vec::new();
+        for req in request["requests"].as_array().unwrap() {
+            let response = match req["type"].as_str().unwrap() {
+                "execute" => execute(&conn, &req["stmt"])
+                    .await
+                    .map(|result| json!({ "type": "execute", "result": result })),
+                "batch" => Ok(json!({ "type": "batch", "result": batch(&conn, &req["batch"]).await })),
+                "get_autocommit" => Ok(json!({ "type": "get_autocommit", "is_autocommit": conn.is_autocommit() })),
+                "close" => {
+                    open = false;
+                    Ok(json!({ "type": "close" }))
+                }
+                other => Err(format!("unsupported request '{}'", other)),
+            };
+            results.push(match response {
+                Ok(response) => json!({ "type": "ok", "response": response }),
+                Err(message) => json!({ "type": "error", "error": { "message": message, "code": "SQLITE_ERROR" } }),
+            });
+        }
+
+        let baton = open.then_some(baton);
+        if let Some(baton) = &baton {
+            self.streams.lock().await.insert(baton.clone(), conn);
+        }
+        json!({ "baton": baton, "base_url": null, "results": results }).to_string()
+    }
+
+    /// Answers a cursor request with one JSON entry per line.
+    async fn cursor(&self, request: &Json) -> String {
+        let (baton, conn) = self.stream(request["baton"].as_str()).await;
+        let mut lines = vec![json!({ "baton": baton, "base_url": null })];
+        for (step, entry) in request["batch"]["steps"].as_array().unwrap().iter().enumerate() {
+            match execute(&conn, &entry["stmt"]).await {
+                Ok(result) => {
+                    lines.push(json!({ "type": "step_begin", "step": step, "cols": result["cols"] }));
+                    for row in result["rows"].as_array().unwrap() {
+                        lines.push(json!({ "type": "row", "row": row }));
+                    }
+                    lines.push(json!({
+                        "type": "step_end",
+                        "affected_row_count": result["affected_row_count"],
+                        "last_inserted_rowid": result["last_insert_rowid"],
+                    }));
+                }
+                Err(message) => lines.push(json!({
+                    "type": "step_error",
+                    "step": step,
+                    "error": { "message": message, "code": "SQLITE_ERROR" },
+                })),
+            }
+        }
+        self.streams.lock().await.insert(baton, conn);
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+/// Runs a statement, waiting for other streams' transactions to finish the
+/// way sqld queues writers. The wait is asynchronous, since SQLite's own busy
+/// timeout would hold up the thread serving the transaction being waited on.
+async fn execute(conn: &Connection, stmt: &Json) -> Result<Json, String> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        match try_execute(conn, stmt).await {
+            Err(message) if message.contains("database is locked") && Instant::now() < deadline => {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn try_execute(conn: &Connection, stmt: &Json) -> Result<Json, String> {
+    let sql = stmt["sql"].as_str().ok_or("stored statements are not supported")?;
+    let args: Vec<Value> = stmt["args"]
+        .as_array()
+        .map(|args| args.iter().map(from_json).collect())
+        .unwrap_or_default();
+
+    let mut rows = conn.query(sql, args).await.map_err(|e| e.to_string())?;
+    let columns = rows.column_count();
+    let cols: Vec<Json> = (0..columns)
+        .map(|i| json!({ "name": rows.column_name(i), "decltype": null }))
+        .collect();
+    let mut values = Vec::new();
+    while let Some(row) = rows.next().await.map_err(|e| e.to_string())? {
+        values.push((0..columns).map(|i| to_json(row.get_value(i).unwrap())).collect::<Vec<_>>());
+    }
+
+    Ok(json!({
+        "cols": cols,
+        "rows": values,
+        "affected_row_count": conn.changes(),
+        "last_insert_rowid": conn.last_insert_rowid().to_string(),
+    }))
+}
+
+async fn batch(conn: &Connection, batch: &Json) -> Json {
+    let mut step_results = Vec::new();
+    let mut step_errors = Vec::new();
+    for step in batch["steps"].as_array().unwrap() {
+        assert!(step["condition"].is_null(), "conditional batch steps are not supported");
+        match execute(conn, &step["stmt"]).await {
+            Ok(result) => {
+                step_results.push(result);
+                step_errors.push(Json::Null);
+            }
+            Err(message) => {
+                step_results.push(Json::Null);
+                step_errors.push(json!({ "message": message, "code": "SQLITE_ERROR" }));
+            }
+        }
+    }
+    json!({ "step_results": step_results, "step_errors": step_errors })
+}
+
+fn from_json(value: &Json) -> Value {
+    match value["type"].as_str() {
+        Some("integer") => Value::Integer(value["value"].as_str().unwrap().parse().unwrap()),
+        Some("float") => Value::Real(value["value"].as_f64().unwrap()),
+        Some("text") => Value::Text(value["value"].as_str().unwrap().to_string()),
+        Some("null") => Value::Null,
+        other => panic!("unsupported value type {:?}", other),
+    }
+}
+
+fn to_json(value: Value) -> Json {
+    match value {
+        Value::Null => json!({ "type": "null" }),
+        Value::Integer(i) => json!({ "type": "integer", "value": i.to_string() }),
+        Value::Real(f) => json!({ "type": "float", "value": f }),
+        Value::Text(text) => json!({ "type": "text", "value": text }),
+        Value::Blob(_) => panic!("recall does not sync blobs"),
+    }
+}
+
+/// Handles the HTTP/1.1 requests of one client connection.
+async fn serve(server: Arc<Server>, socket: TcpStream) {
+    let mut reader = BufReader::new(socket);
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+        let mut length = 0;
+        let mut authorized = false;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            let Some((name, value)) = header.trim_end().split_once(':') else { break };
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => length = value.trim().parse().unwrap(),
+                "authorization" => authorized = value.trim() == format!("Bearer {}", TOKEN),
+                _ => {}
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.unwrap();
+
+        let (status, body) = if !authorized {
+            ("401 Unauthorized", r#"{"message":"Unauthorized"}"#.to_string())
+        } else {
+            let request: Json = serde_json::from_slice(&body).unwrap();
+            match path.as_str() {
+                "/v3/pipeline" => ("200 OK", server.pipeline(&request).await),
+                "/v3/cursor" => ("200 OK", server.cursor(&request).await),
+                _ => ("404 Not Found", String::new()),
+            }
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        if reader.get_mut().write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Starts the stand-in on its own thread and returns its URL.
+fn start_server(dir: &Path) -> String {
+    let path = dir.join("server.db");
+    let (address_tx, address_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        tokio::runtime::Runtime::new().unwrap().block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            address_tx.send(listener.local_addr().unwrap()).unwrap();
+            let server = Arc::new(Server {
+                db: Builder::new_local(path).build().await.unwrap(),
+                streams: Mutex::new(HashMap::new()),
+                batons: AtomicU64::new(0),
+            });
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve(server.clone(), socket));
+            }
+        })
+    });
+    format!("http://{}", address_rx.recv().unwrap())
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("recall-sync-{}-{}", name, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A recall installation with its own database and terminal session.
+struct Machine {
+    dir: PathBuf,
+    url: String,
+    token: String,
+    every: i64,
+}
+
+impl Machine {
+    fn new(root: &Path, name: &str, url: &str) -> Self {
+        let dir = root.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        Machine { dir, url: url.to_string(), token: TOKEN.to_string(), every: 0 }
+    }
+
+    fn recall(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_recall"))
+            .args(args)
+            .current_dir(&self.dir)
+            .env("HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.join("config"))
+            .env("RECALL_DB_PATH", self.dir.join("recall.db"))
+            .env("RECALL_SESSION", self.dir.file_name().unwrap())
+            .env("RECALL_SYNC_URL", &self.url)
+            .env("RECALL_SYNC_AUTH_TOKEN", &self.token)
+            .env("RECALL_SYNC_EVERY", self.every.to_string())
+            .output()
+            .unwrap()
+    }
+
+    fn run(&self, args: &[&str]) -> String {
+        let output = self.recall(args);
+        assert!(
+            output.status.success(),
+            "recall {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Every command line in the history, oldest first.
+    fn commands(&self) -> Vec<String> {
+        let entries: Vec<Json> = serde_json::from_str(&self.run(&["search", "--format", "json"])).unwrap();
+        entries
+            .iter()
+            .map(|entry| entry["line"].as_str().or(entry["command"].as_str()).unwrap().to_string())
+            .collect()
+    }
+}
+
+#[test]
+fn commands_reach_every_machine_once() {
+    let root = temp_dir("merge");
+    let url = start_server(&root);
+    let laptop = Machine::new(&root, "laptop", &url);
+    let desktop = Machine::new(&root, "desktop", &url);
+
+    let id = laptop.run(&["log", "--start", "cargo build --release"]);
+    laptop.run(&["log", "--finish", id.trim(), "--exit", "101"]);
+    laptop.run(&["log", "git status"]);
+    desktop.run(&["log", "make test | tee out.log"]);
+
+    assert!(laptop.run(&["sync"]).contains("Sent 2 commands"));
+    assert!(desktop.run(&["sync"]).contains("received 2 commands"));
+    laptop.run(&["sync"]);
+
+    for machine in [&laptop, &desktop] {
+        let mut commands = machine.commands();
+        commands.sort();
+        assert_eq!(commands, ["cargo build --release", "git status", "make test | tee out.log"]);
+    }
+
+    // The exit status recorded on the laptop came along
+    let entries: Vec<Json> =
+        serde_json::from_str(&desktop.run(&["search", "cargo", "--format", "json"])).unwrap();
+    assert_eq!(entries[0]["exit_code"], 101);
+
+    // Nothing is sent or stored twice
+    assert!(laptop.run(&["sync"]).contains("Already up to date"));
+    assert!(desktop.run(&["sync"]).contains("Already up to date"));
+    assert_eq!(desktop.commands().len(), 3);
+
+    // The other machine's session keeps its own identity
+    let sessions: Vec<Json> = serde_json::from_str(&desktop.run(&["sessions", "list", "--format", "json"])).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions.iter().any(|s| s["key"].as_str().unwrap().starts_with("recall:laptop@")));
+
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn deletions_reach_every_machine() {
+    let root = temp_dir("forget");
+    let url = start_server(&root);
+    let laptop = Machine::new(&root, "laptop", &url);
+    let desktop = Machine::new(&root, "desktop", &url);
+
+    laptop.run(&["log", "ls -la"]);
+    laptop.run(&["log", "echo hunter2"]);
+    laptop.run(&["sync"]);
+    desktop.run(&["sync"]);
+    assert_eq!(desktop.commands(), ["ls -la", "echo hunter2"]);
+
+    desktop.run(&["forget", "--match", "hunter2"]);
+    desktop.run(&["sync"]);
+    assert!(laptop.run(&["sync"]).contains("Removed 1 command"));
+    assert_eq!(laptop.commands(), ["ls -la"]);
+
+    // A machine that still had it does not bring it back
+    laptop.run(&["sync"]);
+    desktop.run(&["sync"]);
+    assert_eq!(desktop.commands(), ["ls -la"]);
+
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn syncs_in_the_background_after_enough_commands() {
+    let root = temp_dir("background");
+    let url = start_server(&root);
+    let mut laptop = Machine::new(&root, "laptop", &url);
+    let desktop = Machine::new(&root, "desktop", &url);
+
+    laptop.every = 2;
+    laptop.run(&["log", "uptime"]);
+    laptop.run(&["log", "df -h"]);
+
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        desktop.run(&["sync"]);
+        if desktop.commands() == ["uptime", "df -h"] {
+            break;
+        }
+        assert!(Instant::now() < deadline, "the background sync never arrived");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn reports_a_rejected_token() {
+    let root = temp_dir("token");
+    let url = start_server(&root);
+    let mut laptop = Machine::new(&root, "laptop", &url);
+    laptop.token = "wrong".to_string();
+    laptop.run(&["log", "whoami"]);
+
+    let output = laptop.recall(&["sync"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Could not reach the sync server"), "{}", stderr);
+
+    // The command is still sent once the token is right
+    laptop.token = TOKEN.to_string();
+    assert!(laptop.run(&["sync"]).contains("Sent 1 command,"));
+
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn shared_folder_reaches_every_machine_once() {
+    let root = temp_dir("folder");
+    let shared = root.join("shared");
+    std::fs::create_dir_all(&shared).unwrap();
+    let shared = shared.to_str().unwrap();
+    let laptop = Machine::new(&root, "laptop", "");
+    let desktop = Machine::new(&root, "desktop", "");
+    let server = Machine::new(&root, "server", "");
+
+    laptop.run(&["log", "cargo build"]);
+    desktop.run(&["log", "git pull"]);
+    server.run(&["log", "echo hunter2"]);
+    for machine in [&laptop, &desktop, &server, &laptop, &desktop] {
+        machine.run(&["sync", "--dir", shared]);
+    }
+    for machine in [&laptop, &desktop, &server] {
+        assert_eq!(machine.commands(), ["cargo build", "git pull", "echo hunter2"]);
+        assert_eq!(machine.run(&["sync", "--dir", shared]), "Already up to date.\n");
+    }
+    // One file per machine, each written only by its own machine
+    assert_eq!(std::fs::read_dir(shared).unwrap().count(), 3);
+
+    server.run(&["forget", "--match", "hunter2"]);
+    server.run(&["sync", "--dir", shared]);
+    // The desktop's file still has it, but it does not come back
+    assert!(laptop.run(&["sync", "--dir", shared]).contains("Removed 1 command"));
+    for machine in [&desktop, &server, &laptop, &desktop, &server] {
+        machine.run(&["sync", "--dir", shared]);
+    }
+    for machine in [&laptop, &desktop, &server] {
+        assert_eq!(machine.commands(), ["cargo build", "git pull"]);
+    }
+
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn merging_a_database_twice_adds_it_once() {
+    let root = temp_dir("merge");
+    let laptop = Machine::new(&root, "laptop", "");
+    let desktop = Machine::new(&root, "desktop", "");
+
+    laptop.run(&["log", "make test"]);
+    laptop.run(&["log", "make install"]);
+    desktop.run(&["log", "htop"]);
+
+    let other = laptop.dir.join("recall.db");
+    let before = std::fs::read(&other).unwrap();
+    let merged = desktop.run(&["merge", other.to_str().unwrap()]);
+    assert!(merged.starts_with("Merged 2 commands from 1 new session"), "{}", merged);
+    assert!(desktop.run(&["merge", other.to_str().unwrap()]).starts_with("Nothing new"));
+    assert_eq!(desktop.commands(), ["make test", "make install", "htop"]);
+    // The merged file is only read
+    assert_eq!(std::fs::read(&other).unwrap(), before);
+
+    std::fs::remove_dir_all(&root).ok();
+}